@@ -0,0 +1,371 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use fat32::traits::{Dir as _, Entry as _, FileSystem, Metadata as _};
+use fat32::vfat::{BiosParameterBlock, Dir, Entry, Metadata, VFatHandle};
+use fat32::{find_fat_partition, partitions, MasterBootRecord};
+
+use crate::image::{self, Handle, Location};
+
+/// Adds `path` to the message of `error`, since errors from the file system
+/// don't say which path they are about.
+fn context<T>(result: io::Result<T>, path: &Path) -> io::Result<T> {
+    result.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+fn other_error<T>(message: String) -> io::Result<T> {
+    Err(io::Error::other(message))
+}
+
+/// Whether `entry` is `.` or `..`.
+fn is_dot(entry: &Entry<Handle>) -> bool {
+    entry.name() == "." || entry.name() == ".."
+}
+
+/// The entries of `dir` other than `.` and `..`, leaving out hidden entries
+/// unless `all` is set.
+fn children(dir: &Dir<Handle>, all: bool) -> io::Result<Vec<Entry<Handle>>> {
+    Ok(dir
+        .entries()?
+        .filter(|entry| !is_dot(entry) && (all || !entry.metadata().hidden()))
+        .collect())
+}
+
+/* ------------- ls ------------- */
+/// Lists the directory at `path`, or just the entry if it is a file. With
+/// `long`, the attributes, size and modification time of each entry are
+/// listed as well.
+pub fn ls(image: &Path, path: &Path, long: bool, all: bool) -> io::Result<()> {
+    let vfat = image::mount(image, false)?;
+    let entries = match context(vfat.open(path), path)? {
+        Entry::Dir(dir) if all => dir.entries()?.collect(),
+        Entry::Dir(dir) => children(&dir, false)?,
+        file => vec![file],
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for entry in entries {
+        match long {
+            true => writeln!(out, "{}", long_listing(&entry))?,
+            false => writeln!(out, "{}", entry.name())?,
+        }
+    }
+    Ok(())
+}
+
+/// The line `ls -l` prints for `entry`: its attributes, size, modification
+/// time and name.
+fn long_listing(entry: &Entry<Handle>) -> String {
+    let size = match entry {
+        Entry::File(file) => fat32::traits::File::size(file),
+        Entry::Dir(_) => 0,
+    };
+    format!(
+        "{} {:>10} {} {}",
+        attribute_flags(entry.metadata()),
+        size,
+        entry.metadata().modified,
+        entry.name()
+    )
+}
+
+/// The attributes of an entry as a string of flags: `d`irectory, `r`ead only,
+/// `h`idden, `s`ystem and `a`rchive.
+fn attribute_flags(metadata: &Metadata) -> String {
+    let attributes = metadata.attributes;
+    let flags = [
+        (attributes.directory(), 'd'),
+        (attributes.read_only(), 'r'),
+        (attributes.hidden(), 'h'),
+        (attributes.system(), 's'),
+        (attributes.archive(), 'a'),
+    ];
+    flags
+        .iter()
+        .map(|&(set, flag)| if set { flag } else { '-' })
+        .collect()
+}
+
+/* ------------- cat ------------- */
+/// Writes the contents of the files at `paths` to stdout, one after another.
+pub fn cat(image: &Path, paths: &[PathBuf]) -> io::Result<()> {
+    let vfat = image::mount(image, false)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for path in paths {
+        let mut file = context(vfat.open_file(path), path)?;
+        context(io::copy(&mut file, &mut out), path)?;
+    }
+    Ok(())
+}
+
+/* ------------- cp ------------- */
+/// Copies `source` to `dest`, one of which must be in the image. If `dest`
+/// is an existing directory, `source` is copied into it under its own name.
+/// Directories are only copied if `recursive` is set.
+pub fn cp(image: &Path, source: &str, dest: &str, recursive: bool) -> io::Result<()> {
+    match (Location::parse(source), Location::parse(dest)) {
+        (Location::Host(source), Location::Image(dest)) => {
+            let vfat = image::mount(image, true)?;
+            copy_in(&vfat, &source, &dest, recursive)?;
+            vfat.lock(|vfat| vfat.sync())
+        }
+        (Location::Image(source), Location::Host(dest)) => {
+            let vfat = image::mount(image, false)?;
+            copy_out(&vfat, &source, &dest, recursive)
+        }
+        _ => other_error(String::from(
+            "exactly one path must be in the image, prefixed with `::`",
+        )),
+    }
+}
+
+/// Copies the host file or directory `source` to `dest` in the image.
+fn copy_in(vfat: &Handle, source: &Path, dest: &Path, recursive: bool) -> io::Result<()> {
+    let dest = match vfat.open(dest) {
+        Ok(Entry::Dir(_)) => match source.file_name() {
+            Some(name) => dest.join(name),
+            None => return other_error(format!("{}: has no file name", source.display())),
+        },
+        _ => dest.to_path_buf(),
+    };
+
+    if context(fs::metadata(source), source)?.is_dir() {
+        if !recursive {
+            return other_error(format!("{}: is a directory (use -r)", source.display()));
+        }
+        if vfat.open_dir(&dest).is_err() {
+            context(vfat.create_dir(&dest), &dest)?;
+        }
+        for child in context(fs::read_dir(source), source)? {
+            let child = child?;
+            copy_in(vfat, &child.path(), &dest, true)?;
+        }
+        return Ok(());
+    }
+
+    // existing files are overwritten in place
+    let mut file = match vfat.open_file(&dest) {
+        Ok(mut file) => {
+            context(file.set_len(0), &dest)?;
+            file
+        }
+        Err(_) => context(vfat.create_file(&dest), &dest)?,
+    };
+    let mut input = context(fs::File::open(source), source)?;
+    context(io::copy(&mut input, &mut file), &dest)?;
+    Ok(())
+}
+
+/// Copies the file or directory `source` in the image to `dest` on the host.
+fn copy_out(vfat: &Handle, source: &Path, dest: &Path, recursive: bool) -> io::Result<()> {
+    let entry = context(vfat.open(source), source)?;
+    let dest = match dest.is_dir() {
+        true => dest.join(entry.name()),
+        false => dest.to_path_buf(),
+    };
+
+    match entry {
+        Entry::File(mut file) => {
+            let mut output = context(fs::File::create(&dest), &dest)?;
+            context(io::copy(&mut file, &mut output), source)?;
+        }
+        Entry::Dir(_) if !recursive => {
+            return other_error(format!("{}: is a directory (use -r)", source.display()));
+        }
+        Entry::Dir(dir) => {
+            context(fs::create_dir_all(&dest), &dest)?;
+            for child in children(&dir, true)? {
+                copy_out(vfat, &source.join(child.name()), &dest, true)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/* ------------- mkdir ------------- */
+/// Creates the directories at `paths`. With `parents`, missing parent
+/// directories are created too and existing directories are not an error.
+pub fn mkdir(image: &Path, paths: &[PathBuf], parents: bool) -> io::Result<()> {
+    let vfat = image::mount(image, true)?;
+    for path in paths {
+        if !parents {
+            context(vfat.create_dir(path), path)?;
+            continue;
+        }
+
+        let mut dir = PathBuf::from("/");
+        for component in path.components().skip(1) {
+            dir.push(component);
+            match vfat.open(&dir) {
+                Ok(Entry::Dir(_)) => {}
+                Ok(Entry::File(_)) => {
+                    return other_error(format!("{}: is not a directory", dir.display()))
+                }
+                Err(_) => drop(context(vfat.create_dir(&dir), &dir)?),
+            }
+        }
+    }
+    vfat.lock(|vfat| vfat.sync())
+}
+
+/* ------------- rm ------------- */
+/// Removes the files at `paths`. Directories are only removed if `recursive`
+/// is set, along with everything in them.
+pub fn rm(image: &Path, paths: &[PathBuf], recursive: bool) -> io::Result<()> {
+    let vfat = image::mount(image, true)?;
+    for path in paths {
+        match context(vfat.open(path), path)? {
+            Entry::Dir(_) if !recursive => {
+                return other_error(format!("{}: is a directory (use -r)", path.display()));
+            }
+            Entry::Dir(dir) => remove_dir_all(&vfat, path, &dir)?,
+            Entry::File(_) => context(vfat.remove(path), path)?,
+        }
+    }
+    vfat.lock(|vfat| vfat.sync())
+}
+
+/// Removes the directory `dir` at `path` and everything in it.
+fn remove_dir_all(vfat: &Handle, path: &Path, dir: &Dir<Handle>) -> io::Result<()> {
+    for child in children(dir, true)? {
+        let child_path = path.join(child.name());
+        if let Entry::Dir(child) = &child {
+            remove_dir_all(vfat, &child_path, child)?;
+        } else {
+            context(vfat.remove(&child_path), &child_path)?;
+        }
+    }
+    context(vfat.remove(path), path)
+}
+
+/* ------------- tree ------------- */
+/// Prints the directory tree under `path`, followed by the number of
+/// directories and files in it.
+pub fn tree(image: &Path, path: &Path, all: bool) -> io::Result<()> {
+    let vfat = image::mount(image, false)?;
+    let dir = context(vfat.open_dir(path), path)?;
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    writeln!(out, "{}", path.display())?;
+    let mut counts = (0, 0);
+    print_tree(&mut out, &dir, "", all, &mut counts)?;
+    writeln!(out, "\n{} directories, {} files", counts.0, counts.1)
+}
+
+/// Prints the entries under `dir`, each line starting with `prefix`, and
+/// adds the directories and files printed to `counts`.
+fn print_tree<W: Write>(
+    out: &mut W,
+    dir: &Dir<Handle>,
+    prefix: &str,
+    all: bool,
+    counts: &mut (usize, usize),
+) -> io::Result<()> {
+    let entries = children(dir, all)?;
+    for (i, entry) in entries.iter().enumerate() {
+        let last = i + 1 == entries.len();
+        let (branch, indent) = match last {
+            true => ("└── ", "    "),
+            false => ("├── ", "│   "),
+        };
+        writeln!(out, "{}{}{}", prefix, branch, entry.name())?;
+
+        match entry {
+            Entry::Dir(child) => {
+                counts.0 += 1;
+                print_tree(out, child, &format!("{}{}", prefix, indent), all, counts)?;
+            }
+            Entry::File(_) => counts.1 += 1,
+        }
+    }
+    Ok(())
+}
+
+/* ------------- info ------------- */
+/// Prints the partition table, the MBR, the boot sector of the FAT volume
+/// and a summary of the volume.
+pub fn info(image: &Path) -> io::Result<()> {
+    let mut device = image::open(image, false)?;
+
+    // the partition `mount` picks, or the whole image
+    let mut start = 0;
+    match partitions(&mut device) {
+        Ok(partitions) => {
+            println!("Partitions:");
+            for partition in &partitions {
+                println!(
+                    "  {}: {:?} start {} sectors {}{}",
+                    partition.index,
+                    partition.partition_type,
+                    partition.start,
+                    partition.num_sectors,
+                    if partition.bootable {
+                        " (bootable)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            if let Ok(partition) = find_fat_partition(&mut device) {
+                start = partition.start;
+            }
+        }
+        Err(e) => println!("Partitions: none ({:?})", e),
+    }
+    if let Ok(mbr) = MasterBootRecord::from(&mut device) {
+        println!("{:#?}", mbr);
+    }
+
+    match BiosParameterBlock::from(&mut device, start) {
+        Ok(ebpb) => println!("Boot sector at sector {}: {:#?}", start, ebpb),
+        Err(e) => println!("Boot sector at sector {}: invalid ({:?})", start, e),
+    }
+
+    let vfat = image::mount(image, false)?;
+    vfat.lock(|vfat| -> io::Result<()> {
+        println!("Volume:");
+        println!("  type: {:?}", vfat.fat_type());
+        println!("  cluster size: {} bytes", vfat.cluster_size());
+        println!("  total space: {} bytes", vfat.total_space());
+        println!("  free space: {} bytes", vfat.free_space()?);
+        println!("  label: {}", vfat.label()?.unwrap_or_default());
+        match vfat.serial()? {
+            Some(serial) => println!("  serial: {:04X}-{:04X}", serial >> 16, serial & 0xFFFF),
+            None => println!("  serial: none"),
+        }
+        println!("  journal: {}", vfat.has_journal());
+        println!("  dirty: {}", vfat.is_dirty());
+        Ok(())
+    })
+}
+
+/* ------------- check ------------- */
+/// Checks the volume for problems and lists them, repairing them if `repair`
+/// is set. Unrepaired problems are an error.
+pub fn check(image: &Path, repair: bool) -> io::Result<()> {
+    let vfat = image::mount(image, repair)?;
+    let report = vfat.lock(|vfat| match repair {
+        true => vfat.repair(),
+        false => vfat.check(),
+    })?;
+
+    for problem in &report.problems {
+        println!("{}", problem);
+    }
+    println!(
+        "{} directories, {} files, {} problem(s){}",
+        report.dirs,
+        report.files,
+        report.problems.len(),
+        if report.repaired { " repaired" } else { "" }
+    );
+
+    if !report.is_clean() && !report.repaired {
+        return other_error(String::from("the volume has problems (use --repair)"));
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::fmt::{self, Debug};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use fat32::vfat::{self, MountOptions, Timestamp, VFat, VFatHandle};
+
+/// A mounted volume, shared behind a mutex.
+#[derive(Clone)]
+pub struct Handle(Arc<Mutex<VFat<Handle>>>);
+
+impl Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle")
+    }
+}
+
+impl VFatHandle for Handle {
+    fn new(val: VFat<Handle>) -> Self {
+        Handle(Arc::new(Mutex::new(val)))
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut VFat<Handle>) -> R) -> R {
+        f(&mut self.0.lock().expect("volume lock poisoned"))
+    }
+}
+
+/// Opens the image file, for writing as well as reading if `writable`.
+pub fn open(image: &Path, writable: bool) -> io::Result<File> {
+    OpenOptions::new().read(true).write(writable).open(image)
+}
+
+/// Mounts the first FAT partition of the image, or the whole image if it has
+/// no partition table. Unless `writable`, the image is opened and mounted
+/// read only so that nothing can change it. Writable volumes stamp entries
+/// with the current time.
+pub fn mount(image: &Path, writable: bool) -> io::Result<Handle> {
+    let options = MountOptions {
+        read_only: !writable,
+        ..Default::default()
+    };
+    let vfat = match VFat::<Handle>::from_with_options(open(image, writable)?, &options) {
+        Ok(vfat) => vfat,
+        Err(partitioned) => {
+            VFat::<Handle>::from_superfloppy_with_options(open(image, writable)?, &options)
+                .map_err(|_| mount_error(partitioned))?
+        }
+    };
+
+    if writable {
+        vfat.lock(|vfat| vfat.set_clock(now));
+    }
+    Ok(vfat)
+}
+
+/// The current time. FAT timestamps have no time zone, and UTC is used.
+fn now() -> Timestamp {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Timestamp::from_unix(since_epoch.as_secs())
+}
+
+/// Describes why an image could not be mounted.
+fn mount_error(error: vfat::Error) -> io::Error {
+    let message = match error {
+        vfat::Error::Io(error) => return error,
+        vfat::Error::NotFound => String::from("the image has no FAT partition"),
+        vfat::Error::BadSignature => String::from("the image does not hold a FAT file system"),
+        error => format!("the image's partition table is invalid: {:?}", error),
+    };
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A path given to `cp`: paths starting with `::` are in the image, as with
+/// mtools, and all others are on the host.
+#[derive(Debug)]
+pub enum Location {
+    Image(PathBuf),
+    Host(PathBuf),
+}
+
+impl Location {
+    pub fn parse(path: &str) -> Location {
+        match path.strip_prefix("::") {
+            Some(path) => Location::Image(image_path(path)),
+            None => Location::Host(PathBuf::from(path)),
+        }
+    }
+}
+
+/// Makes `path` absolute. Paths in the image are always relative to its root
+/// directory.
+pub fn image_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    Path::new("/").join(path)
+}
@@ -0,0 +1,118 @@
+mod commands;
+mod image;
+
+use clap::{Parser, Subcommand};
+use std::io;
+use std::path::PathBuf;
+use std::process;
+
+use image::image_path;
+
+#[derive(Parser, Debug)]
+#[clap(about = "Inspect and modify FAT32 disk images without mounting them.")]
+struct Opt {
+    #[clap(help = "Path to the disk image", parse(from_os_str))]
+    image: PathBuf,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[clap(about = "List the entries of a directory")]
+    Ls {
+        #[clap(short = 'l', help = "Show attributes, sizes and modification times")]
+        long: bool,
+
+        #[clap(short = 'a', help = "Show hidden entries, `.` and `..`")]
+        all: bool,
+
+        #[clap(help = "Directory in the image", default_value = "/")]
+        path: PathBuf,
+    },
+
+    #[clap(about = "Write the contents of files to stdout")]
+    Cat {
+        #[clap(help = "Files in the image", required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    #[clap(about = "Copy a file into or out of the image; image paths start with `::`")]
+    Cp {
+        #[clap(short = 'r', help = "Copy directories recursively")]
+        recursive: bool,
+
+        #[clap(help = "Source path, e.g. `kernel.bin` or `::/kernel8.img`")]
+        source: String,
+
+        #[clap(help = "Destination path")]
+        dest: String,
+    },
+
+    #[clap(about = "Create directories")]
+    Mkdir {
+        #[clap(short = 'p', help = "Create missing parent directories as needed")]
+        parents: bool,
+
+        #[clap(help = "Directories in the image", required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    #[clap(about = "Remove files and directories")]
+    Rm {
+        #[clap(short = 'r', help = "Remove directories and their contents")]
+        recursive: bool,
+
+        #[clap(help = "Paths in the image", required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    #[clap(about = "Print the directory tree")]
+    Tree {
+        #[clap(short = 'a', help = "Show hidden entries")]
+        all: bool,
+
+        #[clap(help = "Directory in the image", default_value = "/")]
+        path: PathBuf,
+    },
+
+    #[clap(about = "Print the partition table, MBR, boot sector and volume summary")]
+    Info,
+
+    #[clap(about = "Check the file system for problems")]
+    Check {
+        #[clap(long = "repair", help = "Repair the problems found")]
+        repair: bool,
+    },
+}
+
+fn run(opt: Opt) -> io::Result<()> {
+    let image = &opt.image;
+    let paths = |paths: Vec<PathBuf>| -> Vec<PathBuf> { paths.iter().map(image_path).collect() };
+    match opt.command {
+        Command::Ls { long, all, path } => commands::ls(image, &image_path(path), long, all),
+        Command::Cat { paths: p } => commands::cat(image, &paths(p)),
+        Command::Cp {
+            recursive,
+            source,
+            dest,
+        } => commands::cp(image, &source, &dest, recursive),
+        Command::Mkdir { parents, paths: p } => commands::mkdir(image, &paths(p), parents),
+        Command::Rm {
+            recursive,
+            paths: p,
+        } => commands::rm(image, &paths(p), recursive),
+        Command::Tree { all, path } => commands::tree(image, &image_path(path), all),
+        Command::Info => commands::info(image),
+        Command::Check { repair } => commands::check(image, repair),
+    }
+}
+
+fn main() {
+    let opt = Opt::parse();
+    if let Err(e) = run(opt) {
+        eprintln!("fat32-tool: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,99 @@
+use core::fmt;
+use core::mem;
+use shim::const_assert_size;
+use shim::io;
+use shim::newioerr;
+
+use crate::traits::BlockDevice;
+use crate::vfat::Error;
+
+#[repr(C, packed)]
+pub struct BootSector {
+    pub jump: [u8; 3],
+    pub file_system_name: [u8; 8],
+    pub must_be_zero: [u8; 53],
+    pub partition_offset: u64,
+    pub volume_length: u64,
+    pub fat_offset: u32,
+    pub fat_length: u32,
+    pub cluster_heap_offset: u32,
+    pub cluster_count: u32,
+    pub root_cluster: u32,
+    pub volume_serial: u32,
+    pub revision: u16,
+    pub volume_flags: u16,
+    pub bytes_per_sector_shift: u8,
+    pub sectors_per_cluster_shift: u8,
+    pub num_fats: u8,
+    pub drive_select: u8,
+    pub percent_in_use: u8,
+    pub reserved: [u8; 7],
+    pub boot_code: [u8; 390],
+    pub boot_signature: [u8; 2],
+}
+
+const_assert_size!(BootSector, 512);
+
+impl BootSector {
+    /// Reads the exFAT boot sector from sector `sector` of device `device`.
+    ///
+    /// # Errors
+    ///
+    /// If the boot signature or file system name is invalid, returns an error
+    /// of `BadSignature`. If the sector or cluster size is out of the range
+    /// allowed by the specification, returns an `InvalidData` I/O error.
+    pub fn from<T: BlockDevice>(mut device: T, sector: u64) -> Result<BootSector, Error> {
+        let mut buf = [0u8; 512];
+        device.read_sector(sector, &mut buf)?;
+
+        let boot: BootSector = unsafe { mem::transmute(buf) };
+        if boot.boot_signature != [0x55, 0xAA] || &boot.file_system_name != b"EXFAT   " {
+            return Err(Error::BadSignature);
+        }
+
+        // sectors are 512 to 4096 bytes and clusters at most 32 MiB
+        if !(9..=12).contains(&boot.bytes_per_sector_shift)
+            || boot.bytes_per_sector_shift + boot.sectors_per_cluster_shift > 25
+        {
+            return Err(Error::Io(newioerr!(
+                InvalidData,
+                "invalid exFAT sector or cluster size"
+            )));
+        }
+
+        Ok(boot)
+    }
+
+    /// The size of a sector in bytes.
+    pub fn bytes_per_sector(&self) -> u64 {
+        1 << self.bytes_per_sector_shift
+    }
+
+    /// The number of sectors in a cluster.
+    pub fn sectors_per_cluster(&self) -> u64 {
+        1 << self.sectors_per_cluster_shift
+    }
+}
+
+impl fmt::Debug for BootSector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BootSector")
+            .field("file_system_name", &self.file_system_name)
+            .field("partition_offset", &{ self.partition_offset })
+            .field("volume_length", &{ self.volume_length })
+            .field("fat_offset", &{ self.fat_offset })
+            .field("fat_length", &{ self.fat_length })
+            .field("cluster_heap_offset", &{ self.cluster_heap_offset })
+            .field("cluster_count", &{ self.cluster_count })
+            .field("root_cluster", &{ self.root_cluster })
+            .field("volume_serial", &{ self.volume_serial })
+            .field("revision", &{ self.revision })
+            .field("volume_flags", &{ self.volume_flags })
+            .field("bytes_per_sector", &self.bytes_per_sector())
+            .field("sectors_per_cluster", &self.sectors_per_cluster())
+            .field("num_fats", &self.num_fats)
+            .field("percent_in_use", &self.percent_in_use)
+            .field("boot_signature", &self.boot_signature)
+            .finish()
+    }
+}
@@ -0,0 +1,351 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
+
+use shim::const_assert_size;
+use shim::ffi::OsStr;
+use shim::io;
+use shim::newioerr;
+
+use crate::exfat::{Chain, Entry, ExFatHandle, File};
+use crate::traits;
+use crate::vfat::{Attributes, Metadata, Timestamp};
+
+#[derive(Debug)]
+pub struct Dir<HANDLE: ExFatHandle> {
+    pub exfat: HANDLE,
+    pub(crate) name: String,
+    pub(crate) metadata: Metadata,
+    pub(crate) chain: Chain,
+    /// The size of the directory in bytes. `None` for the root directory,
+    /// whose size is only known from its FAT chain.
+    pub(crate) size: Option<u64>,
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct ExFatFileDirEntry {
+    entry_type: u8,
+    secondary_count: u8,
+    set_checksum: u16,
+    attributes: u16,
+    reserved_1: u16,
+    created: u32,
+    modified: u32,
+    accessed: u32,
+    created_10ms: u8,
+    modified_10ms: u8,
+    created_utc_offset: u8,
+    modified_utc_offset: u8,
+    accessed_utc_offset: u8,
+    reserved_2: [u8; 7],
+}
+
+const_assert_size!(ExFatFileDirEntry, 32);
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct ExFatStreamDirEntry {
+    entry_type: u8,
+    flags: u8,
+    reserved_1: u8,
+    name_length: u8,
+    name_hash: u16,
+    reserved_2: u16,
+    valid_data_length: u64,
+    reserved_3: u32,
+    first_cluster: u32,
+    data_length: u64,
+}
+
+const_assert_size!(ExFatStreamDirEntry, 32);
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct ExFatNameDirEntry {
+    entry_type: u8,
+    flags: u8,
+    name: [u16; 15],
+}
+
+const_assert_size!(ExFatNameDirEntry, 32);
+
+/// The layout shared by the allocation bitmap and up-case table entries.
+/// `checksum` is only used by the up-case table.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct ExFatAllocationDirEntry {
+    entry_type: u8,
+    flags: u8,
+    reserved_1: [u8; 2],
+    checksum: u32,
+    reserved_2: [u8; 12],
+    first_cluster: u32,
+    data_length: u64,
+}
+
+const_assert_size!(ExFatAllocationDirEntry, 32);
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct ExFatGenericDirEntry {
+    entry_type: u8,
+    data: [u8; 31],
+}
+
+const_assert_size!(ExFatGenericDirEntry, 32);
+
+#[derive(Copy, Clone)]
+pub union ExFatDirEntry {
+    generic: ExFatGenericDirEntry,
+    file: ExFatFileDirEntry,
+    stream: ExFatStreamDirEntry,
+    name: ExFatNameDirEntry,
+    allocation: ExFatAllocationDirEntry,
+}
+
+const_assert_size!(ExFatDirEntry, 32);
+
+/// Marks the end of the entries in a directory.
+pub(crate) const END_OF_DIRECTORY: u8 = 0x00;
+/// The entry describing the allocation bitmap.
+pub(crate) const ALLOCATION_BITMAP: u8 = 0x81;
+/// The entry describing the up-case table.
+pub(crate) const UPCASE_TABLE: u8 = 0x82;
+/// The first entry of the set describing a file or directory.
+const FILE: u8 = 0x85;
+/// The entry following `FILE` that describes where the data is.
+const STREAM_EXTENSION: u8 = 0xC0;
+/// An entry holding part of a file name.
+const FILE_NAME: u8 = 0xC1;
+
+/// Set in a stream extension entry's flags when the data is stored in
+/// consecutive clusters without a FAT chain.
+const NO_FAT_CHAIN: u8 = 0x02;
+
+impl ExFatDirEntry {
+    /// Reads an entry from the first 32 bytes of `bytes`.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> ExFatDirEntry {
+        let mut raw = [0u8; 32];
+        raw.copy_from_slice(&bytes[..32]);
+        unsafe { core::mem::transmute::<[u8; 32], ExFatDirEntry>(raw) }
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        unsafe { core::mem::transmute::<ExFatDirEntry, [u8; 32]>(self) }
+    }
+
+    pub(crate) fn entry_type(&self) -> u8 {
+        unsafe { self.generic.entry_type }
+    }
+
+    /// The first cluster and length of the data described by an allocation
+    /// bitmap or up-case table entry.
+    pub(crate) fn allocation(&self) -> (u32, u64) {
+        let entry = unsafe { self.allocation };
+        (entry.first_cluster, entry.data_length)
+    }
+
+    /// The checksum of the up-case table described by an up-case table entry.
+    pub(crate) fn table_checksum(&self) -> u32 {
+        unsafe { self.allocation }.checksum
+    }
+}
+
+/// The checksum of an entry set, which is stored in its first entry.
+fn set_checksum(entries: &[ExFatDirEntry]) -> u16 {
+    let mut sum = 0u16;
+    for (i, entry) in entries.iter().enumerate() {
+        for (j, &b) in entry.to_bytes().iter().enumerate() {
+            // skip the checksum itself
+            if i == 0 && (j == 2 || j == 3) {
+                continue;
+            }
+            sum = (sum << 15).wrapping_add(sum >> 1).wrapping_add(b as u16);
+        }
+    }
+    sum
+}
+
+impl<HANDLE: ExFatHandle> Dir<HANDLE> {
+    /// Returns the root directory of the file system behind `exfat`.
+    pub(crate) fn root(exfat: HANDLE) -> Dir<HANDLE> {
+        let chain = exfat.lock(|exfat| exfat.root_chain());
+        Dir {
+            exfat,
+            name: String::from("/"),
+            metadata: Metadata::default(),
+            chain,
+            size: None,
+        }
+    }
+
+    /// Finds the entry named `name` in `self` and returns it. Comparison uses
+    /// the volume's up-case table and so is case-insensitive.
+    ///
+    /// # Errors
+    ///
+    /// If no entry with name `name` exists in `self`, an error of `NotFound` is
+    /// returned.
+    ///
+    /// If `name` contains invalid UTF-8 characters, an error of `InvalidInput`
+    /// is returned.
+    pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry<HANDLE>> {
+        use crate::traits::Dir as _;
+
+        let name: Vec<u16> = name
+            .as_ref()
+            .to_str()
+            .ok_or_else(|| newioerr!(InvalidInput, "name is not valid UTF-8"))?
+            .encode_utf16()
+            .collect();
+
+        let mut entries = self.entries()?;
+        self.exfat
+            .lock(|exfat| {
+                let upcase = exfat.upcase_table();
+                let hash = upcase.name_hash(&name);
+                while let Some((entry, entry_name, entry_hash)) = entries.next_with_name() {
+                    if entry_hash == hash && upcase.names_match(&entry_name, &name) {
+                        return Some(entry);
+                    }
+                }
+                None
+            })
+            .ok_or_else(|| newioerr!(NotFound, "no entry with that name"))
+    }
+
+    /// Reads every raw entry of the directory.
+    fn raw_entries(&self) -> io::Result<Vec<ExFatDirEntry>> {
+        let mut buf = Vec::new();
+        self.exfat
+            .lock(|exfat| exfat.read_chain(self.chain, self.size, &mut buf))?;
+
+        Ok(buf
+            .as_chunks::<32>()
+            .0
+            .iter()
+            .map(|raw| ExFatDirEntry::from_bytes(raw))
+            .collect())
+    }
+}
+
+/// An iterator over the entries in a `Dir`.
+pub struct EntryIter<HANDLE: ExFatHandle> {
+    exfat: HANDLE,
+    entries: Vec<ExFatDirEntry>,
+    index: usize,
+}
+
+impl<HANDLE: ExFatHandle> Iterator for EntryIter<HANDLE> {
+    type Item = Entry<HANDLE>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_name().map(|(entry, _, _)| entry)
+    }
+}
+
+impl<HANDLE: ExFatHandle> EntryIter<HANDLE> {
+    /// Returns the next entry along with its UCS-2 name and the name hash
+    /// stored on disk. Entry sets that are incomplete or whose checksum does
+    /// not match are skipped.
+    fn next_with_name(&mut self) -> Option<(Entry<HANDLE>, Vec<u16>, u16)> {
+        while self.index < self.entries.len() {
+            let index = self.index;
+            self.index += 1;
+
+            match self.entries[index].entry_type() {
+                END_OF_DIRECTORY => {
+                    self.index = self.entries.len();
+                    return None;
+                }
+                FILE => {}
+                // deleted entries, secondary entries without a file entry and
+                // the volume's own entries
+                _ => continue,
+            }
+
+            let file = unsafe { self.entries[index].file };
+            let end = index + 1 + file.secondary_count as usize;
+            if file.secondary_count < 2 || end > self.entries.len() {
+                continue;
+            }
+
+            let set = &self.entries[index..end];
+            if set_checksum(set) != file.set_checksum || set[1].entry_type() != STREAM_EXTENSION {
+                continue;
+            }
+            self.index = end;
+
+            let stream = unsafe { set[1].stream };
+            let mut name: Vec<u16> = set[2..]
+                .iter()
+                .filter(|entry| entry.entry_type() == FILE_NAME)
+                .flat_map(|entry| unsafe { entry.name }.name)
+                .collect();
+            name.truncate(stream.name_length as usize);
+            if name.len() < stream.name_length as usize {
+                continue;
+            }
+
+            let entry = self.make_entry(&name, &file, &stream);
+            return Some((entry, name, stream.name_hash));
+        }
+
+        None
+    }
+
+    fn make_entry(
+        &self,
+        name: &[u16],
+        file: &ExFatFileDirEntry,
+        stream: &ExFatStreamDirEntry,
+    ) -> Entry<HANDLE> {
+        let name: String = decode_utf16(name.iter().cloned())
+            .map(|c| c.unwrap_or(REPLACEMENT_CHARACTER))
+            .collect();
+        let metadata = Metadata {
+            // the attributes that exFAT shares with FAT fit in a byte
+            attributes: Attributes::from_raw(file.attributes as u8),
+            created: Timestamp::from_packed(file.created),
+            accessed: Timestamp::from_packed(file.accessed),
+            modified: Timestamp::from_packed(file.modified),
+        };
+        let chain = Chain {
+            first: stream.first_cluster,
+            contiguous: stream.flags & NO_FAT_CHAIN != 0,
+        };
+
+        if metadata.attributes.directory() {
+            Entry::Dir(Dir {
+                exfat: self.exfat.clone(),
+                name,
+                metadata,
+                chain,
+                size: Some(stream.data_length),
+            })
+        } else {
+            Entry::File(File::new(
+                self.exfat.clone(),
+                name,
+                metadata,
+                chain,
+                stream.data_length,
+                stream.valid_data_length,
+            ))
+        }
+    }
+}
+
+impl<HANDLE: ExFatHandle> traits::Dir for Dir<HANDLE> {
+    type Entry = Entry<HANDLE>;
+    type Iter = EntryIter<HANDLE>;
+
+    fn entries(&self) -> io::Result<Self::Iter> {
+        Ok(EntryIter {
+            exfat: self.exfat.clone(),
+            entries: self.raw_entries()?,
+            index: 0,
+        })
+    }
+}
@@ -0,0 +1,422 @@
+use core::fmt::Debug;
+use core::marker::PhantomData;
+
+use alloc::vec::Vec;
+
+use shim::io;
+use shim::ioerr;
+use shim::newioerr;
+use shim::path;
+use shim::path::Path;
+
+use crate::exfat::dir::{self, ALLOCATION_BITMAP, END_OF_DIRECTORY, UPCASE_TABLE};
+use crate::exfat::{BootSector, Dir, Entry, File, UpcaseTable};
+use crate::normalize;
+use crate::partition::partitions;
+use crate::traits::{BlockDevice, Entry as _, FileSystem};
+use crate::vfat::{CachedPartition, Error, Partition};
+
+/// A generic trait that handles a critical section as a closure
+pub trait ExFatHandle: Clone + Debug + Send + Sync {
+    fn new(val: ExFat<Self>) -> Self;
+    fn lock<R>(&self, f: impl FnOnce(&mut ExFat<Self>) -> R) -> R;
+}
+
+/// A mounted exFAT volume. File system operations are implemented on
+/// references to this type; the handle it wraps is shared with every entry
+/// opened from it.
+#[derive(Debug, Clone)]
+pub struct ExFatFs<HANDLE: ExFatHandle> {
+    handle: HANDLE,
+}
+
+impl<HANDLE: ExFatHandle> ExFatFs<HANDLE> {
+    /// The handle to the volume's state.
+    pub fn handle(&self) -> &HANDLE {
+        &self.handle
+    }
+}
+
+/// The clusters holding a file or directory's data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Chain {
+    /// The first cluster of the data. 0 if no clusters are allocated.
+    pub first: u32,
+    /// Whether the clusters are consecutive, in which case the FAT is not
+    /// used to find them.
+    pub contiguous: bool,
+}
+
+#[derive(Debug)]
+pub struct ExFat<HANDLE: ExFatHandle> {
+    phantom: PhantomData<HANDLE>,
+    device: CachedPartition,
+    bytes_per_sector: u64,
+    sectors_per_cluster: u64,
+    fat_start_sector: u64,
+    cluster_heap_start_sector: u64,
+    cluster_count: u32,
+    root_cluster: u32,
+    /// One bit per cluster, set if the cluster is in use.
+    bitmap: Vec<u8>,
+    upcase: UpcaseTable,
+}
+
+/// The FAT entry marking the last cluster of a chain.
+const END_OF_CHAIN: u32 = 0xFFFFFFFF;
+/// The FAT entry marking a bad cluster.
+const BAD_CLUSTER: u32 = 0xFFFFFFF7;
+
+impl<HANDLE: ExFatHandle> ExFat<HANDLE> {
+    /// Mounts the first exFAT partition on `device`. Disks partitioned with
+    /// an MBR or a GUID partition table are both supported.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if no partition holds an exFAT file system.
+    pub fn from<T>(mut device: T) -> Result<ExFatFs<HANDLE>, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        // partition types don't tell exFAT apart from NTFS, so the boot
+        // sector is checked
+        let start = partitions(&mut device)?
+            .iter()
+            .filter(|partition| partition.partition_type.may_hold_exfat())
+            .find(|partition| BootSector::from(&mut device, partition.start).is_ok())
+            .map(|partition| partition.start)
+            .ok_or(Error::NotFound)?;
+        ExFat::mount(device, start)
+    }
+
+    /// Mounts the partition with index `index`, as listed by
+    /// [`partitions`](crate::partitions).
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if there is no partition with that index and
+    /// `BadSignature` if it does not hold an exFAT file system.
+    pub fn from_partition<T>(mut device: T, index: usize) -> Result<ExFatFs<HANDLE>, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        let partition = partitions(&mut device)?
+            .into_iter()
+            .find(|partition| partition.index == index)
+            .ok_or(Error::NotFound)?;
+        ExFat::mount(device, partition.start)
+    }
+
+    /// Mounts a device without a partition table that has the exFAT boot
+    /// sector at sector 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BadSignature` if sector 0 is not an exFAT boot sector.
+    pub fn from_superfloppy<T>(device: T) -> Result<ExFatFs<HANDLE>, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        ExFat::mount(device, 0)
+    }
+
+    /// Mounts the exFAT file system whose boot sector is at sector `start`.
+    fn mount<T>(mut device: T, start: u64) -> Result<ExFatFs<HANDLE>, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        let boot = BootSector::from(&mut device, start)?;
+        let partition = Partition {
+            start,
+            num_sectors: boot.volume_length,
+            sector_size: boot.bytes_per_sector(),
+        };
+
+        let mut exfat = ExFat {
+            phantom: PhantomData,
+            device: CachedPartition::new(device, partition),
+            bytes_per_sector: boot.bytes_per_sector(),
+            sectors_per_cluster: boot.sectors_per_cluster(),
+            fat_start_sector: boot.fat_offset as u64,
+            cluster_heap_start_sector: boot.cluster_heap_offset as u64,
+            cluster_count: boot.cluster_count,
+            root_cluster: boot.root_cluster,
+            bitmap: Vec::new(),
+            upcase: UpcaseTable::identity(),
+        };
+        exfat.load_system_files()?;
+
+        Ok(ExFatFs {
+            handle: HANDLE::new(exfat),
+        })
+    }
+
+    /// Loads the allocation bitmap and up-case table described by entries in
+    /// the root directory.
+    fn load_system_files(&mut self) -> io::Result<()> {
+        let mut root = Vec::new();
+        self.read_chain(self.root_chain(), None, &mut root)?;
+
+        let mut bitmap = None;
+        let mut upcase = None;
+        for raw in root.as_chunks::<32>().0 {
+            let entry = dir::ExFatDirEntry::from_bytes(raw);
+            match entry.entry_type() {
+                END_OF_DIRECTORY => break,
+                // the second bitmap only exists on TexFAT volumes with two FATs
+                ALLOCATION_BITMAP if bitmap.is_none() => bitmap = Some(entry.allocation()),
+                UPCASE_TABLE if upcase.is_none() => {
+                    upcase = Some((entry.allocation(), entry.table_checksum()))
+                }
+                _ => {}
+            }
+        }
+
+        let (first, len) =
+            bitmap.ok_or_else(|| newioerr!(InvalidData, "volume has no allocation bitmap"))?;
+        let mut data = Vec::new();
+        self.read_chain(chain_at(first), Some(len), &mut data)?;
+        self.bitmap = data;
+
+        let ((first, len), checksum) =
+            upcase.ok_or_else(|| newioerr!(InvalidData, "volume has no up-case table"))?;
+        let mut data = Vec::new();
+        self.read_chain(chain_at(first), Some(len), &mut data)?;
+        if UpcaseTable::checksum(&data) != checksum {
+            return ioerr!(InvalidData, "up-case table checksum mismatch");
+        }
+        self.upcase = UpcaseTable::from_bytes(&data);
+
+        Ok(())
+    }
+
+    /* ------------- Geometry ------------- */
+    /// The size of a cluster in bytes.
+    pub fn cluster_size(&self) -> usize {
+        (self.bytes_per_sector * self.sectors_per_cluster) as usize
+    }
+
+    /// The chain holding the root directory.
+    pub(crate) fn root_chain(&self) -> Chain {
+        chain_at(self.root_cluster)
+    }
+
+    /// The volume's up-case table.
+    pub fn upcase_table(&self) -> &UpcaseTable {
+        &self.upcase
+    }
+
+    /// Whether `cluster` is marked as in use in the allocation bitmap.
+    pub fn is_allocated(&self, cluster: u32) -> bool {
+        if cluster < 2 || cluster >= self.cluster_count + 2 {
+            return false;
+        }
+        let index = (cluster - 2) as usize;
+        self.bitmap
+            .get(index / 8)
+            .is_some_and(|byte| byte & (1 << (index % 8)) != 0)
+    }
+
+    /// The number of clusters that are not in use.
+    pub fn free_clusters(&self) -> u32 {
+        (2..self.cluster_count + 2)
+            .filter(|&cluster| !self.is_allocated(cluster))
+            .count() as u32
+    }
+
+    /// Returns an error if `cluster` is not in the cluster heap.
+    fn check_cluster(&self, cluster: u32) -> io::Result<()> {
+        if cluster < 2 || cluster >= self.cluster_count + 2 {
+            return ioerr!(InvalidData, "cluster number out of range");
+        }
+        Ok(())
+    }
+
+    /* ------------- Cluster I/O ------------- */
+    /// Reads from `offset` bytes into `cluster` into `buf`. Reads until either
+    /// `buf` is full or the end of the cluster is reached. Returns the number
+    /// of bytes read.
+    pub(crate) fn read_cluster(
+        &mut self,
+        cluster: u32,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        self.check_cluster(cluster)?;
+
+        let sector_size = self.bytes_per_sector as usize;
+        let len = buf.len().min(self.cluster_size().saturating_sub(offset));
+        let first_sector =
+            self.cluster_heap_start_sector + (cluster - 2) as u64 * self.sectors_per_cluster;
+
+        let mut read = 0;
+        while read < len {
+            let sector_offset = (offset + read) % sector_size;
+            let sector = first_sector + ((offset + read) / sector_size) as u64;
+            let data = self.device.get(sector)?;
+
+            let n = (sector_size - sector_offset).min(len - read);
+            buf[read..read + n].copy_from_slice(&data[sector_offset..sector_offset + n]);
+            read += n;
+        }
+
+        Ok(read)
+    }
+
+    /// Appends the contents of `chain` to `buf`, stopping after `len` bytes if
+    /// it is `Some`. Chains with no length are read until their FAT chain
+    /// ends. Returns the number of bytes read.
+    pub(crate) fn read_chain(
+        &mut self,
+        chain: Chain,
+        len: Option<u64>,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<usize> {
+        if chain.first == 0 {
+            return Ok(0);
+        }
+        if len.is_none() && chain.contiguous {
+            return ioerr!(InvalidData, "contiguous chain without a length");
+        }
+
+        let cluster_size = self.cluster_size();
+        let start = buf.len();
+        let mut current = Some(chain.first);
+        while let Some(cluster) = current {
+            let remaining = match len {
+                Some(len) => (len as usize).saturating_sub(buf.len() - start),
+                None => cluster_size,
+            };
+            if remaining == 0 {
+                break;
+            }
+
+            let offset = buf.len();
+            buf.resize(offset + remaining.min(cluster_size), 0);
+            self.read_cluster(cluster, 0, &mut buf[offset..])?;
+            current = self.next_in_chain(chain, cluster)?;
+        }
+
+        if let Some(len) = len {
+            if ((buf.len() - start) as u64) < len {
+                return ioerr!(UnexpectedEof, "cluster chain ended early");
+            }
+        }
+        Ok(buf.len() - start)
+    }
+
+    /* ------------- FAT ------------- */
+    /// Returns the FAT entry for `cluster`.
+    fn fat_entry(&mut self, cluster: u32) -> io::Result<u32> {
+        let byte = cluster as u64 * 4;
+        let sector = self.fat_start_sector + byte / self.bytes_per_sector;
+        let offset = (byte % self.bytes_per_sector) as usize;
+
+        let data = self.device.get(sector)?;
+        let mut entry = [0u8; 4];
+        entry.copy_from_slice(&data[offset..offset + 4]);
+        Ok(u32::from_le_bytes(entry))
+    }
+
+    /// Returns the cluster following `cluster` in `chain`, or `None` if
+    /// `cluster` is the last cluster of a FAT chain. Contiguous chains have
+    /// no end of their own; their length comes from their directory entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if the FAT entry for `cluster` does
+    /// not belong to a chain.
+    pub(crate) fn next_in_chain(&mut self, chain: Chain, cluster: u32) -> io::Result<Option<u32>> {
+        self.check_cluster(cluster)?;
+        if chain.contiguous {
+            return Ok(Some(cluster + 1));
+        }
+
+        match self.fat_entry(cluster)? {
+            END_OF_CHAIN => Ok(None),
+            BAD_CLUSTER => ioerr!(InvalidData, "cluster chain contains a bad cluster"),
+            next if next >= 2 && next < self.cluster_count + 2 => Ok(Some(next)),
+            _ => ioerr!(InvalidData, "cluster chain contains an invalid entry"),
+        }
+    }
+
+    /// Returns the cluster `steps` clusters after `start` in `chain`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `UnexpectedEof` if the chain ends before `steps`
+    /// clusters have been walked.
+    pub(crate) fn walk_chain(&mut self, chain: Chain, start: u32, steps: u64) -> io::Result<u32> {
+        if chain.contiguous {
+            let cluster = start as u64 + steps;
+            if cluster >= self.cluster_count as u64 + 2 {
+                return ioerr!(UnexpectedEof, "contiguous chain runs past the cluster heap");
+            }
+            return Ok(cluster as u32);
+        }
+
+        let mut cluster = start;
+        for _ in 0..steps {
+            cluster = self
+                .next_in_chain(chain, cluster)?
+                .ok_or_else(|| newioerr!(UnexpectedEof, "cluster chain ended early"))?;
+        }
+        Ok(cluster)
+    }
+}
+
+/// A FAT chain starting at `first`.
+fn chain_at(first: u32) -> Chain {
+    Chain {
+        first,
+        contiguous: false,
+    }
+}
+
+impl<HANDLE: ExFatHandle> FileSystem for &ExFatFs<HANDLE> {
+    type File = File<HANDLE>;
+    type Dir = Dir<HANDLE>;
+    type Entry = Entry<HANDLE>;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        let path = normalize(path);
+        if !path.is_absolute() {
+            return ioerr!(InvalidInput, "path is not absolute");
+        }
+
+        let mut entry = Entry::Dir(Dir::root(self.handle.clone()));
+        for component in path.components() {
+            let name = match component {
+                path::Component::RootDir => continue,
+                component => component.as_os_str(),
+            };
+
+            entry = match entry.as_dir() {
+                Some(dir) => dir.find(name)?,
+                None => return ioerr!(InvalidInput, "path component is not a directory"),
+            };
+        }
+
+        Ok(entry)
+    }
+
+    fn create_file<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::File> {
+        ioerr!(PermissionDenied, "exFAT volumes are read only")
+    }
+
+    fn create_dir<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::Dir> {
+        ioerr!(PermissionDenied, "exFAT volumes are read only")
+    }
+
+    fn remove<P: AsRef<Path>>(self, _path: P) -> io::Result<()> {
+        ioerr!(PermissionDenied, "exFAT volumes are read only")
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, _from: P, _to: Q) -> io::Result<()> {
+        ioerr!(PermissionDenied, "exFAT volumes are read only")
+    }
+
+    fn set_read_only<P: AsRef<Path>>(self, _path: P, _read_only: bool) -> io::Result<()> {
+        ioerr!(PermissionDenied, "exFAT volumes are read only")
+    }
+}
@@ -0,0 +1,147 @@
+use alloc::string::String;
+
+use shim::io::{self, SeekFrom};
+use shim::ioerr;
+
+use crate::exfat::{Chain, ExFatHandle};
+use crate::traits;
+use crate::vfat::Metadata;
+
+#[derive(Debug)]
+pub struct File<HANDLE: ExFatHandle> {
+    pub exfat: HANDLE,
+    pub(crate) name: String,
+    pub(crate) metadata: Metadata,
+    pub(crate) chain: Chain,
+    pub(crate) size: u64,
+    /// The number of bytes that have been written. The rest of the file reads
+    /// as zeroes.
+    pub(crate) valid_size: u64,
+    /// The current position in the file.
+    position: u64,
+    /// The most recently visited cluster and its index in the chain.
+    cursor: Option<(u64, u32)>,
+}
+
+impl<HANDLE: ExFatHandle> File<HANDLE> {
+    pub(crate) fn new(
+        exfat: HANDLE,
+        name: String,
+        metadata: Metadata,
+        chain: Chain,
+        size: u64,
+        valid_size: u64,
+    ) -> File<HANDLE> {
+        File {
+            exfat,
+            name,
+            metadata,
+            chain,
+            size,
+            valid_size: valid_size.min(size),
+            position: 0,
+            cursor: None,
+        }
+    }
+
+    /// Returns the cluster holding byte `position` of the file, walking the
+    /// chain from the cursor when possible instead of from the start.
+    fn cluster_at(&mut self, position: u64, cluster_size: u64) -> io::Result<u32> {
+        let index = position / cluster_size;
+        let (start_index, start) = match self.cursor {
+            Some((i, cluster)) if i <= index => (i, cluster),
+            _ => (0, self.chain.first),
+        };
+
+        let chain = self.chain;
+        let cluster = self
+            .exfat
+            .lock(|exfat| exfat.walk_chain(chain, start, index - start_index))?;
+        self.cursor = Some((index, cluster));
+        Ok(cluster)
+    }
+}
+
+impl<HANDLE: ExFatHandle> traits::File for File<HANDLE> {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn set_len(&mut self, _size: u64) -> io::Result<()> {
+        ioerr!(PermissionDenied, "exFAT volumes are read only")
+    }
+}
+
+impl<HANDLE: ExFatHandle> io::Read for File<HANDLE> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size.saturating_sub(self.position);
+        let len = (buf.len() as u64).min(remaining) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let cluster_size = self.exfat.lock(|exfat| exfat.cluster_size()) as u64;
+        let mut read = 0;
+        while read < len {
+            if self.position >= self.valid_size {
+                // nothing was ever written past the valid size
+                buf[read..len].fill(0);
+                self.position += (len - read) as u64;
+                read = len;
+                break;
+            }
+
+            let valid = (self.valid_size - self.position).min((len - read) as u64) as usize;
+            let cluster = self.cluster_at(self.position, cluster_size)?;
+            let offset = (self.position % cluster_size) as usize;
+            let n = self
+                .exfat
+                .lock(|exfat| exfat.read_cluster(cluster, offset, &mut buf[read..read + valid]))?;
+
+            read += n;
+            self.position += n as u64;
+        }
+
+        Ok(read)
+    }
+}
+
+impl<HANDLE: ExFatHandle> io::Write for File<HANDLE> {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        ioerr!(PermissionDenied, "exFAT volumes are read only")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<HANDLE: ExFatHandle> io::Seek for File<HANDLE> {
+    /// Seek to offset `pos` in the file.
+    ///
+    /// A seek to the end of the file is allowed. A seek _beyond_ the end of the
+    /// file returns an `InvalidInput` error.
+    ///
+    /// # Errors
+    ///
+    /// Seeking before the start of a file or beyond the end of the file results
+    /// in an `InvalidInput` error.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => offset as i128,
+            SeekFrom::End(offset) => self.size as i128 + offset as i128,
+            SeekFrom::Current(offset) => self.position as i128 + offset as i128,
+        };
+
+        if position < 0 || position > self.size as i128 {
+            return ioerr!(InvalidInput, "seek outside of the file");
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
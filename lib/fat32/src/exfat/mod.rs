@@ -0,0 +1,16 @@
+pub(crate) mod boot;
+pub(crate) mod dir;
+pub(crate) mod entry;
+#[allow(clippy::module_inception)]
+pub(crate) mod exfat;
+pub(crate) mod file;
+pub(crate) mod upcase;
+
+pub use self::boot::BootSector;
+pub use self::dir::Dir;
+pub use self::entry::Entry;
+pub use self::exfat::{ExFat, ExFatFs, ExFatHandle};
+pub use self::file::File;
+pub use self::upcase::UpcaseTable;
+
+pub(crate) use self::exfat::Chain;
@@ -0,0 +1,81 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The up-case table of an exFAT volume. Names are compared and hashed after
+/// mapping each of their characters through the table.
+#[derive(Clone)]
+pub struct UpcaseTable {
+    map: Vec<u16>,
+}
+
+impl UpcaseTable {
+    /// A table that maps every character to itself.
+    pub fn identity() -> UpcaseTable {
+        UpcaseTable { map: Vec::new() }
+    }
+
+    /// Decodes an up-case table as stored on disk. Tables may be compressed:
+    /// a `0xFFFF` is followed by the number of characters that map to
+    /// themselves.
+    pub fn from_bytes(bytes: &[u8]) -> UpcaseTable {
+        let mut values = bytes
+            .as_chunks::<2>()
+            .0
+            .iter()
+            .map(|&c| u16::from_le_bytes(c));
+
+        let mut map = Vec::new();
+        while let Some(value) = values.next() {
+            if value == 0xFFFF {
+                // an uncompressed table ends by mapping 0xFFFF to itself
+                if let Some(count) = values.next() {
+                    let start = map.len();
+                    map.extend((start..start + count as usize).map(|c| c as u16));
+                    continue;
+                }
+            }
+            map.push(value);
+        }
+
+        UpcaseTable { map }
+    }
+
+    /// The checksum of a table as stored on disk, which is recorded in the
+    /// table's directory entry.
+    pub fn checksum(bytes: &[u8]) -> u32 {
+        bytes.iter().fold(0u32, |sum, &b| {
+            (sum << 31).wrapping_add(sum >> 1).wrapping_add(b as u32)
+        })
+    }
+
+    /// Maps the UCS-2 character `c` to upper case.
+    pub fn upcase(&self, c: u16) -> u16 {
+        self.map.get(c as usize).cloned().unwrap_or(c)
+    }
+
+    /// Whether the names `a` and `b` are equal without regard to case.
+    pub fn names_match(&self, a: &[u16], b: &[u16]) -> bool {
+        a.len() == b.len()
+            && a.iter()
+                .zip(b.iter())
+                .all(|(&a, &b)| self.upcase(a) == self.upcase(b))
+    }
+
+    /// The hash of `name` stored in the stream extension entry of files and
+    /// directories.
+    pub fn name_hash(&self, name: &[u16]) -> u16 {
+        name.iter()
+            .flat_map(|&c| self.upcase(c).to_le_bytes())
+            .fold(0u16, |hash, b| {
+                (hash << 15).wrapping_add(hash >> 1).wrapping_add(b as u16)
+            })
+    }
+}
+
+impl fmt::Debug for UpcaseTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UpcaseTable")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
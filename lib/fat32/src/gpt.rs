@@ -0,0 +1,326 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use core::fmt;
+use core::mem;
+use shim::const_assert_size;
+use shim::io;
+
+use crate::mbr::{self, MasterBootRecord};
+use crate::traits::BlockDevice;
+use crate::util::crc32;
+
+/// A GUID as stored on disk: the first three fields are little endian.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    /// The GUID with the given fields, as it is usually written out.
+    pub const fn from_fields(a: u32, b: u16, c: u16, d: [u8; 8]) -> Guid {
+        let a = a.to_le_bytes();
+        let b = b.to_le_bytes();
+        let c = c.to_le_bytes();
+        Guid([
+            a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5],
+            d[6], d[7],
+        ])
+    }
+
+    /// The all-zero GUID, which marks unused partition entries.
+    pub const UNUSED: Guid = Guid([0; 16]);
+
+    /// The EFI system partition, which holds a FAT file system.
+    pub const EFI_SYSTEM: Guid = Guid::from_fields(
+        0xC12A7328,
+        0xF81F,
+        0x11D2,
+        [0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B],
+    );
+
+    /// The Microsoft basic data partition, used for FAT, exFAT and NTFS.
+    pub const BASIC_DATA: Guid = Guid::from_fields(
+        0xEBD0A0A2,
+        0xB9E5,
+        0x4433,
+        [0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7],
+    );
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let g = &self.0;
+        write!(
+            f,
+            "{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-",
+            g[3], g[2], g[1], g[0], g[5], g[4], g[7], g[6]
+        )?;
+        write!(f, "{:02X}{:02X}-", g[8], g[9])?;
+        g[10..].iter().try_for_each(|b| write!(f, "{:02X}", b))
+    }
+}
+
+const_assert_size!(Guid, 16);
+
+/// The GPT header, found in the second sector of the disk and, as a backup,
+/// in the last.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct GptHeader {
+    pub signature: [u8; 8],
+    pub revision: u32,
+    pub header_size: u32,
+    pub header_crc32: u32,
+    pub reserved: u32,
+    pub current_lba: u64,
+    pub backup_lba: u64,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub disk_guid: Guid,
+    pub partition_entry_lba: u64,
+    pub num_partition_entries: u32,
+    pub partition_entry_size: u32,
+    pub partition_entry_array_crc32: u32,
+}
+
+const_assert_size!(GptHeader, 92);
+
+impl fmt::Debug for GptHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GptHeader")
+            .field("revision", &{ self.revision })
+            .field("header_size", &{ self.header_size })
+            .field("current_lba", &{ self.current_lba })
+            .field("backup_lba", &{ self.backup_lba })
+            .field("first_usable_lba", &{ self.first_usable_lba })
+            .field("last_usable_lba", &{ self.last_usable_lba })
+            .field("disk_guid", &{ self.disk_guid })
+            .field("partition_entry_lba", &{ self.partition_entry_lba })
+            .field("num_partition_entries", &{ self.num_partition_entries })
+            .field("partition_entry_size", &{ self.partition_entry_size })
+            .finish()
+    }
+}
+
+/// The partition attribute marking a partition as bootable by legacy BIOSes.
+const LEGACY_BIOS_BOOTABLE: u64 = 1 << 2;
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct GptPartitionEntry {
+    pub type_guid: Guid,
+    pub unique_guid: Guid,
+    pub first_lba: u64,
+    /// The last sector of the partition, inclusive.
+    pub last_lba: u64,
+    pub attributes: u64,
+    pub name: [u16; 36],
+}
+
+const_assert_size!(GptPartitionEntry, 128);
+
+impl GptPartitionEntry {
+    /// Returns `true` if the entry describes a partition.
+    pub fn is_used(&self) -> bool {
+        self.type_guid != Guid::UNUSED
+    }
+
+    /// Returns `true` if the partition has the legacy BIOS bootable
+    /// attribute.
+    pub fn is_bootable(&self) -> bool {
+        self.attributes & LEGACY_BIOS_BOOTABLE != 0
+    }
+
+    /// The number of sectors in the partition.
+    pub fn num_sectors(&self) -> u64 {
+        (self.last_lba + 1).saturating_sub(self.first_lba)
+    }
+
+    /// The partition's name.
+    pub fn name(&self) -> String {
+        let name = self.name;
+        decode_utf16(name.iter().cloned().take_while(|&c| c != 0))
+            .map(|c| c.unwrap_or(REPLACEMENT_CHARACTER))
+            .collect()
+    }
+}
+
+impl fmt::Debug for GptPartitionEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GptPartitionEntry")
+            .field("type_guid", &{ self.type_guid })
+            .field("unique_guid", &{ self.unique_guid })
+            .field("first_lba", &{ self.first_lba })
+            .field("last_lba", &{ self.last_lba })
+            .field("attributes", &{ self.attributes })
+            .field("name", &self.name())
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// There was an I/O error while reading the partition table.
+    Io(io::Error),
+    /// The protective MBR could not be read.
+    Mbr(mbr::Error),
+    /// The MBR is not a protective MBR, so the disk has no GPT.
+    NoProtectiveMbr,
+    /// The GPT header signature or layout was invalid.
+    BadSignature,
+    /// The CRC32 of the GPT header or partition entry array did not match.
+    BadChecksum,
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<mbr::Error> for Error {
+    fn from(error: mbr::Error) -> Error {
+        Error::Mbr(error)
+    }
+}
+
+/// The most partition entry array bytes that will be read. The specification
+/// requires at least 16 KiB; anything much larger is treated as corrupt.
+const MAX_ENTRY_ARRAY_SIZE: u64 = 1 << 20;
+
+/// A GUID partition table (GPT).
+#[derive(Debug)]
+pub struct GuidPartitionTable {
+    pub header: GptHeader,
+    entries: Vec<GptPartitionEntry>,
+    /// Whether the primary header or entries were invalid and the backup was
+    /// used instead.
+    used_backup: bool,
+}
+
+impl GuidPartitionTable {
+    /// Reads and returns the GUID partition table from `device`. The primary
+    /// header and entries are used unless either is invalid, in which case
+    /// the backup at the end of the disk is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoProtectiveMbr` if the MBR has no protective partition. If
+    /// neither the primary nor the backup table is valid, returns the error
+    /// found in the primary table: `BadSignature` if its header is malformed
+    /// or `BadChecksum` if a CRC32 does not match. Returns `Io(err)` if the
+    /// I/O error `err` occured while reading.
+    pub fn from<T: BlockDevice>(mut device: T) -> Result<GuidPartitionTable, Error> {
+        let mbr = MasterBootRecord::from(&mut device)?;
+        let protective = mbr.protective_partition().ok_or(Error::NoProtectiveMbr)?;
+
+        // the backup is found through the primary header if it can be
+        // trusted, and in the last sector covered by the protective MBR
+        // otherwise
+        let (primary_error, backup_lba) = match read_header(&mut device, 1) {
+            Ok(header) => match read_entries(&mut device, &header) {
+                Ok(entries) => {
+                    return Ok(GuidPartitionTable {
+                        header,
+                        entries,
+                        used_backup: false,
+                    })
+                }
+                Err(e) => (e, header.backup_lba),
+            },
+            Err(e) => {
+                let last = protective.relative_sector as u64 + protective.total_sectors as u64;
+                (e, last.saturating_sub(1))
+            }
+        };
+
+        let backup = read_header(&mut device, backup_lba)
+            .and_then(|header| Ok((header, read_entries(&mut device, &header)?)));
+        match backup {
+            Ok((header, entries)) => Ok(GuidPartitionTable {
+                header,
+                entries,
+                used_backup: true,
+            }),
+            Err(_) => Err(primary_error),
+        }
+    }
+
+    /// Returns every entry in the partition entry array, including unused
+    /// ones.
+    pub fn entries(&self) -> &[GptPartitionEntry] {
+        &self.entries
+    }
+
+    /// Returns an iterator over the entries that describe a partition.
+    pub fn partitions(&self) -> impl Iterator<Item = &GptPartitionEntry> {
+        self.entries.iter().filter(|entry| entry.is_used())
+    }
+
+    /// Returns `true` if the primary table was invalid and the backup was
+    /// read instead.
+    pub fn used_backup(&self) -> bool {
+        self.used_backup
+    }
+}
+
+/// Reads and validates the GPT header in sector `lba`.
+fn read_header<T: BlockDevice>(mut device: T, lba: u64) -> Result<GptHeader, Error> {
+    let mut buf = Vec::new();
+    device.read_all_sector(lba, &mut buf)?;
+    if buf.len() < mem::size_of::<GptHeader>() {
+        return Err(Error::BadSignature);
+    }
+
+    let header: GptHeader = unsafe { (buf.as_ptr() as *const GptHeader).read_unaligned() };
+    let size = header.header_size as usize;
+    if &header.signature != b"EFI PART"
+        || size < mem::size_of::<GptHeader>()
+        || size > buf.len()
+        || header.current_lba != lba
+    {
+        return Err(Error::BadSignature);
+    }
+
+    // the checksum covers the header with its checksum field zeroed
+    buf[16..20].fill(0);
+    if crc32(&buf[..size]) != header.header_crc32 {
+        return Err(Error::BadChecksum);
+    }
+
+    Ok(header)
+}
+
+/// Reads and validates the partition entry array described by `header`.
+fn read_entries<T: BlockDevice>(
+    mut device: T,
+    header: &GptHeader,
+) -> Result<Vec<GptPartitionEntry>, Error> {
+    let entry_size = header.partition_entry_size as usize;
+    let len = header.num_partition_entries as u64 * entry_size as u64;
+    if entry_size < mem::size_of::<GptPartitionEntry>()
+        || !entry_size.is_multiple_of(8)
+        || len > MAX_ENTRY_ARRAY_SIZE
+    {
+        return Err(Error::BadSignature);
+    }
+
+    let mut buf = Vec::new();
+    let mut sector = header.partition_entry_lba;
+    while (buf.len() as u64) < len {
+        if device.read_all_sector(sector, &mut buf)? == 0 {
+            break;
+        }
+        sector += 1;
+    }
+    buf.truncate(len as usize);
+
+    if crc32(&buf) != header.partition_entry_array_crc32 {
+        return Err(Error::BadChecksum);
+    }
+
+    Ok(buf
+        .chunks_exact(entry_size)
+        .map(|raw| unsafe { (raw.as_ptr() as *const GptPartitionEntry).read_unaligned() })
+        .collect())
+}
@@ -11,11 +11,17 @@ extern crate alloc;
 compile_error!("only little endian platforms supported");
 
 mod mbr;
+mod partition;
+mod path;
 #[cfg(test)]
 mod tests;
 mod util;
 
+pub mod exfat;
+pub mod gpt;
 pub mod traits;
 pub mod vfat;
 
 pub use crate::mbr::*;
+pub use crate::partition::*;
+pub use crate::path::*;
@@ -1,4 +1,5 @@
 use core::fmt;
+use core::mem;
 use shim::const_assert_size;
 use shim::io;
 
@@ -7,29 +8,168 @@ use crate::traits::BlockDevice;
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct CHS {
-    // FIXME: Fill me in.
+    head: u8,
+    /// bits 0-5 are the sector, bits 6-7 are the high bits of the cylinder
+    sector_cylinder_high: u8,
+    cylinder_low: u8,
 }
 
-// FIXME: implement Debug for CHS
+impl CHS {
+    /// The CHS address of sector `lba` with the usual translation of 255
+    /// heads and 63 sectors per track. Sectors past what CHS can address get
+    /// the largest address, as LBA aware software expects.
+    pub(crate) fn from_lba(lba: u64) -> CHS {
+        const HEADS: u64 = 255;
+        const SECTORS: u64 = 63;
+
+        let cylinder = lba / (HEADS * SECTORS);
+        if cylinder > 1023 {
+            return CHS {
+                head: 254,
+                sector_cylinder_high: 0xFF,
+                cylinder_low: 0xFF,
+            };
+        }
+
+        let head = (lba / SECTORS) % HEADS;
+        let sector = lba % SECTORS + 1;
+        CHS {
+            head: head as u8,
+            sector_cylinder_high: sector as u8 | ((cylinder >> 2) as u8 & 0b1100_0000),
+            cylinder_low: cylinder as u8,
+        }
+    }
+
+    pub fn head(&self) -> u8 {
+        self.head
+    }
+
+    pub fn sector(&self) -> u8 {
+        self.sector_cylinder_high & 0b0011_1111
+    }
+
+    pub fn cylinder(&self) -> u16 {
+        (((self.sector_cylinder_high & 0b1100_0000) as u16) << 2) | self.cylinder_low as u16
+    }
+}
+
+impl fmt::Debug for CHS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CHS")
+            .field("head", &self.head())
+            .field("sector", &self.sector())
+            .field("cylinder", &self.cylinder())
+            .finish()
+    }
+}
 
 const_assert_size!(CHS, 3);
 
 #[repr(C, packed)]
 pub struct PartitionEntry {
-    // FIXME: Fill me in.
+    pub boot_indicator: u8,
+    pub start_chs: CHS,
+    pub partition_type: u8,
+    pub end_chs: CHS,
+    pub relative_sector: u32,
+    pub total_sectors: u32,
+}
+
+impl PartitionEntry {
+    /// Returns an entry for a partition of type `partition_type` covering
+    /// the `total_sectors` sectors starting at `relative_sector`.
+    pub(crate) fn new(
+        partition_type: u8,
+        relative_sector: u32,
+        total_sectors: u32,
+    ) -> PartitionEntry {
+        let end = (relative_sector as u64 + total_sectors as u64).saturating_sub(1);
+        PartitionEntry {
+            boot_indicator: 0,
+            start_chs: CHS::from_lba(relative_sector as u64),
+            partition_type,
+            end_chs: CHS::from_lba(end),
+            relative_sector,
+            total_sectors,
+        }
+    }
+
+    /// Returns `true` if the partition type is one of the FAT32 types
+    /// (`0xB` for CHS addressing or `0xC` for LBA addressing).
+    pub fn is_fat32(&self) -> bool {
+        self.partition_type == 0xB || self.partition_type == 0xC
+    }
+
+    /// Returns `true` if the partition type is one of the FAT types: FAT12
+    /// (`0x1`), FAT16 (`0x4`, `0x6` or `0xE` for LBA addressing) or FAT32.
+    pub fn is_fat(&self) -> bool {
+        is_fat_type(self.partition_type)
+    }
+
+    /// Returns `true` if the partition type is `0x7`, which is used by both
+    /// exFAT and NTFS. The file system itself must be checked to tell them
+    /// apart.
+    pub fn is_exfat(&self) -> bool {
+        is_exfat_type(self.partition_type)
+    }
+
+    /// Returns `true` if the partition type is `0xEE`, which a protective MBR
+    /// uses to cover a disk partitioned with a GUID partition table.
+    pub fn is_gpt_protective(&self) -> bool {
+        self.partition_type == 0xEE
+    }
+
+    /// Returns `true` if the partition is marked as bootable.
+    pub fn is_bootable(&self) -> bool {
+        self.boot_indicator == 0x80
+    }
+}
+
+/// Returns `true` if `partition_type` is one of the FAT12, FAT16 or FAT32
+/// partition types.
+pub(crate) fn is_fat_type(partition_type: u8) -> bool {
+    matches!(partition_type, 0x1 | 0x4 | 0x6 | 0xB | 0xC | 0xE)
+}
+
+/// Returns `true` if `partition_type` is `0x7`, the exFAT and NTFS partition
+/// type.
+pub(crate) fn is_exfat_type(partition_type: u8) -> bool {
+    partition_type == 0x7
 }
 
-// FIXME: implement Debug for PartitionEntry
+impl fmt::Debug for PartitionEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PartitionEntry")
+            .field("boot_indicator", &self.boot_indicator)
+            .field("start_chs", &{ self.start_chs })
+            .field("partition_type", &self.partition_type)
+            .field("end_chs", &{ self.end_chs })
+            .field("relative_sector", &{ self.relative_sector })
+            .field("total_sectors", &{ self.total_sectors })
+            .finish()
+    }
+}
 
 const_assert_size!(PartitionEntry, 16);
 
 /// The master boot record (MBR).
 #[repr(C, packed)]
 pub struct MasterBootRecord {
-    // FIXME: Fill me in.
+    pub bootstrap: [u8; 436],
+    pub disk_id: [u8; 10],
+    pub partition_table: [PartitionEntry; 4],
+    pub signature: [u8; 2],
 }
 
-// FIXME: implemente Debug for MaterBootRecord
+impl fmt::Debug for MasterBootRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MasterBootRecord")
+            .field("disk_id", &self.disk_id)
+            .field("partition_table", &self.partition_table)
+            .field("signature", &self.signature)
+            .finish()
+    }
+}
 
 const_assert_size!(MasterBootRecord, 512);
 
@@ -53,6 +193,53 @@ impl MasterBootRecord {
     /// boot indicator. Returns `Io(err)` if the I/O error `err` occured while
     /// reading the MBR.
     pub fn from<T: BlockDevice>(mut device: T) -> Result<MasterBootRecord, Error> {
-        unimplemented!("MasterBootRecord::from()")
+        let mut buf = [0u8; 512];
+        let read = device.read_sector(0, &mut buf).map_err(Error::Io)?;
+        if read != buf.len() {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "MBR sector too short",
+            )));
+        }
+
+        let mbr: MasterBootRecord = unsafe { mem::transmute(buf) };
+
+        if mbr.signature != [0x55, 0xAA] {
+            return Err(Error::BadSignature);
+        }
+
+        for (i, partition) in mbr.partition_table.iter().enumerate() {
+            if partition.boot_indicator != 0 && partition.boot_indicator != 0x80 {
+                return Err(Error::UnknownBootIndicator(i as u8));
+            }
+        }
+
+        Ok(mbr)
+    }
+
+    /// Returns an MBR with an empty partition table.
+    pub(crate) fn empty() -> MasterBootRecord {
+        unsafe { mem::transmute([0u8; 512]) }
+    }
+
+    pub(crate) fn into_bytes(self) -> [u8; 512] {
+        unsafe { mem::transmute(self) }
+    }
+
+    /// Returns the first partition entry that holds a FAT32 file system.
+    pub fn first_fat32(&self) -> Option<&PartitionEntry> {
+        self.partition_table.iter().find(|p| p.is_fat32())
+    }
+
+    /// Returns the first partition entry that holds a FAT12, FAT16 or FAT32
+    /// file system.
+    pub fn first_fat(&self) -> Option<&PartitionEntry> {
+        self.partition_table.iter().find(|p| p.is_fat())
+    }
+
+    /// Returns the protective partition entry if this is a protective MBR,
+    /// in which case the real partitions are in a GUID partition table.
+    pub fn protective_partition(&self) -> Option<&PartitionEntry> {
+        self.partition_table.iter().find(|p| p.is_gpt_protective())
     }
 }
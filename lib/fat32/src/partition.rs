@@ -0,0 +1,115 @@
+use alloc::vec::Vec;
+
+use crate::gpt::{Guid, GuidPartitionTable};
+use crate::mbr::{self, MasterBootRecord};
+use crate::traits::BlockDevice;
+use crate::vfat::{BiosParameterBlock, Error};
+
+/// The type of a partition, as recorded by the partitioning scheme in use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PartitionType {
+    /// The type byte of an MBR partition entry.
+    Mbr(u8),
+    /// The type GUID of a GPT partition entry.
+    Gpt(Guid),
+}
+
+impl PartitionType {
+    /// Returns `true` if partitions of this type can hold a FAT file system.
+    /// GPT types are shared with other file systems, so the partition itself
+    /// must be checked to be sure.
+    pub fn may_hold_fat(&self) -> bool {
+        match *self {
+            PartitionType::Mbr(partition_type) => mbr::is_fat_type(partition_type),
+            PartitionType::Gpt(guid) => guid == Guid::EFI_SYSTEM || guid == Guid::BASIC_DATA,
+        }
+    }
+
+    /// Returns `true` if partitions of this type can hold an exFAT file
+    /// system. The types are shared with NTFS, so the partition itself must
+    /// be checked to be sure.
+    pub fn may_hold_exfat(&self) -> bool {
+        match *self {
+            PartitionType::Mbr(partition_type) => mbr::is_exfat_type(partition_type),
+            PartitionType::Gpt(guid) => guid == Guid::BASIC_DATA,
+        }
+    }
+}
+
+/// A partition found on a device, independent of the partitioning scheme.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PartitionInfo {
+    /// The index of the partition's entry in the MBR partition table or the
+    /// GPT partition entry array.
+    pub index: usize,
+    pub partition_type: PartitionType,
+    /// The first sector of the partition.
+    pub start: u64,
+    /// The number of sectors in the partition.
+    pub num_sectors: u64,
+    pub bootable: bool,
+}
+
+/// Returns the partitions on `device`. Disks with a protective MBR are read
+/// through their GUID partition table. Empty MBR entries and unused GPT
+/// entries are skipped.
+///
+/// # Errors
+///
+/// Returns `Mbr(err)` if the MBR can't be read or `Gpt(err)` if the MBR is a
+/// protective MBR but the GUID partition table can't be read.
+pub fn partitions<T: BlockDevice>(mut device: T) -> Result<Vec<PartitionInfo>, Error> {
+    let mbr = MasterBootRecord::from(&mut device)?;
+    if mbr.protective_partition().is_none() {
+        let partitions = mbr
+            .partition_table
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.partition_type != 0)
+            .map(|(index, entry)| PartitionInfo {
+                index,
+                partition_type: PartitionType::Mbr(entry.partition_type),
+                start: entry.relative_sector as u64,
+                num_sectors: entry.total_sectors as u64,
+                bootable: entry.is_bootable(),
+            });
+        return Ok(partitions.collect());
+    }
+
+    let gpt = GuidPartitionTable::from(&mut device)?;
+    let partitions = gpt
+        .entries()
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.is_used())
+        .map(|(index, entry)| PartitionInfo {
+            index,
+            partition_type: PartitionType::Gpt(entry.type_guid),
+            start: entry.first_lba,
+            num_sectors: entry.num_sectors(),
+            bootable: entry.is_bootable(),
+        });
+    Ok(partitions.collect())
+}
+
+/// Returns the first partition on `device` that holds a FAT file system. This
+/// is the partition mounted by [`VFat::from`](crate::vfat::VFat::from).
+///
+/// # Errors
+///
+/// Returns `NotFound` if there is no such partition, or the errors of
+/// [`partitions`] if the partition table can't be read.
+pub fn find_fat_partition<T: BlockDevice>(mut device: T) -> Result<PartitionInfo, Error> {
+    let partitions = partitions(&mut device)?;
+    partitions
+        .into_iter()
+        .filter(|partition| partition.partition_type.may_hold_fat())
+        .find(|partition| match partition.partition_type {
+            PartitionType::Mbr(_) => true,
+            // GPT partition types don't tell FAT apart from exFAT or NTFS, so
+            // the boot sector is checked
+            PartitionType::Gpt(_) => BiosParameterBlock::from(&mut device, partition.start)
+                .is_ok_and(|ebpb| ebpb.is_fat()),
+        })
+        .ok_or(Error::NotFound)
+}
@@ -0,0 +1,31 @@
+use shim::path::{Component, Path, PathBuf};
+
+/// Lexically normalizes `path`: repeated separators and `.` components are
+/// removed, and each `..` removes the component before it. A `..` at the root
+/// of an absolute path stays at the root, while those at the start of a
+/// relative path are kept. The file system is not consulted, so `a/..` is
+/// removed even if `a` does not exist.
+///
+/// A relative path that normalizes to nothing, such as `.`, becomes the empty
+/// path.
+pub fn normalize<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut normal = PathBuf::new();
+    // the number of normal components that a `..` can remove
+    let mut depth = 0;
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => {
+                normal.pop();
+                depth -= 1;
+            }
+            Component::ParentDir if normal.has_root() => {}
+            Component::Normal(name) => {
+                normal.push(name);
+                depth += 1;
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
@@ -7,6 +7,7 @@ use std::io::Cursor;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
+use crate::exfat;
 use crate::mbr;
 use crate::traits::*;
 use crate::vfat;
@@ -171,6 +172,7 @@ fn check_entry_sizes() {
     check_size!(vfat::dir::VFatUnknownDirEntry, 32);
     check_size!(vfat::dir::VFatLfnDirEntry, 32);
     check_size!(vfat::dir::VFatDirEntry, 32);
+    check_size!(vfat::FsInfo, 512);
 }
 
 #[test]
@@ -265,7 +267,7 @@ fn hash_dir_recursive<P: AsRef<Path>>(
     let path = path.as_ref();
     let dir = vfat.open_dir(path).expect("directory");
 
-    write!(hash, "{}\n", path.display())?;
+    writeln!(hash, "{}", path.display())?;
     let entries = hash_dir(hash, dir)?;
     if entries.iter().any(|e| e.is_dir()) {
         hash.push_str("\n\n");
@@ -474,3 +476,3001 @@ fn shuffle_test() {
     let hash = hash_files_recursive_from(vfat, "/");
     assert_hash_eq!("mock 1 file hashes", hash, hash_for!("files-1"));
 }
+
+/// A `BlockDevice` over an in-memory image that stays accessible after the
+/// device has been handed to a `VFat`.
+#[derive(Clone)]
+struct SharedImage(Arc<Mutex<Cursor<Vec<u8>>>>);
+
+impl SharedImage {
+    fn new(data: Vec<u8>) -> SharedImage {
+        SharedImage(Arc::new(Mutex::new(Cursor::new(data))))
+    }
+}
+
+impl BlockDevice for SharedImage {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().read_sector(n, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write_sector(n, buf)
+    }
+
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().read_sectors(start, count, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write_sectors(start, count, buf)
+    }
+}
+
+const RESERVED: u32 = 32;
+const CLUSTERS: u32 = 66_000;
+const FAT_SECTORS: u32 = ((CLUSTERS + 2) * 4).div_ceil(512);
+const TOTAL: u32 = RESERVED + 2 * FAT_SECTORS + CLUSTERS;
+
+/// Builds an empty FAT32 image with 512 byte sectors and clusters. The single
+/// partition starts at sector 1 and the root directory is at cluster 2.
+fn empty_fat32_image() -> Vec<u8> {
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    let mut image = vec![0u8; (1 + TOTAL as usize) * 512];
+
+    // MBR with a single FAT32 (LBA) partition
+    put(&mut image, 446 + 4, &[0x0C]);
+    put(&mut image, 446 + 8, &1u32.to_le_bytes());
+    put(&mut image, 446 + 12, &TOTAL.to_le_bytes());
+    put(&mut image, 510, &[0x55, 0xAA]);
+
+    // EBPB
+    let ebpb = 512;
+    put(&mut image, ebpb + 11, &512u16.to_le_bytes());
+    put(&mut image, ebpb + 13, &[1]);
+    put(&mut image, ebpb + 14, &(RESERVED as u16).to_le_bytes());
+    put(&mut image, ebpb + 16, &[2]);
+    put(&mut image, ebpb + 21, &[0xF8]);
+    put(&mut image, ebpb + 32, &TOTAL.to_le_bytes());
+    put(&mut image, ebpb + 36, &FAT_SECTORS.to_le_bytes());
+    put(&mut image, ebpb + 44, &2u32.to_le_bytes());
+    put(&mut image, ebpb + 48, &1u16.to_le_bytes());
+    put(&mut image, ebpb + 66, &[0x29]);
+    put(&mut image, ebpb + 510, &[0x55, 0xAA]);
+
+    // FSInfo in the next sector, with the free count left unknown
+    let fsinfo = 2 * 512;
+    put(&mut image, fsinfo, &0x41615252u32.to_le_bytes());
+    put(&mut image, fsinfo + 484, &0x61417272u32.to_le_bytes());
+    put(&mut image, fsinfo + 488, &0xFFFFFFFFu32.to_le_bytes());
+    put(&mut image, fsinfo + 492, &0xFFFFFFFFu32.to_le_bytes());
+    put(&mut image, fsinfo + 508, &0xAA550000u32.to_le_bytes());
+
+    // both FATs: media descriptor, reserved entry and the root directory
+    for fat in 0..2 {
+        let start = (1 + RESERVED + fat * FAT_SECTORS) as usize * 512;
+        put(&mut image, start, &0x0FFFFFF8u32.to_le_bytes());
+        put(&mut image, start + 4, &0x0FFFFFFFu32.to_le_bytes());
+        put(&mut image, start + 8, &0x0FFFFFFFu32.to_le_bytes());
+    }
+
+    image
+}
+
+/// Builds an empty FAT12 or FAT16 image with `clusters` 512 byte clusters and
+/// a 512 entry root directory. The FAT type follows from the cluster count.
+/// The single partition starts at sector 1.
+fn empty_fat16_image(clusters: u32) -> Vec<u8> {
+    const RESERVED: u32 = 1;
+    const ROOT_SECTORS: u32 = 512 * 32 / 512;
+
+    let fat12 = clusters < 4085;
+    let fat_bytes = match fat12 {
+        true => ((clusters + 2) * 3).div_ceil(2),
+        false => (clusters + 2) * 2,
+    };
+    let fat_sectors = fat_bytes.div_ceil(512);
+    let total = RESERVED + 2 * fat_sectors + ROOT_SECTORS + clusters;
+
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    let mut image = vec![0u8; (1 + total as usize) * 512];
+
+    // MBR with a single FAT12 or FAT16 (LBA) partition
+    put(&mut image, 446 + 4, &[if fat12 { 0x01 } else { 0x0E }]);
+    put(&mut image, 446 + 8, &1u32.to_le_bytes());
+    put(&mut image, 446 + 12, &total.to_le_bytes());
+    put(&mut image, 510, &[0x55, 0xAA]);
+
+    // BPB
+    let bpb = 512;
+    put(&mut image, bpb + 11, &512u16.to_le_bytes());
+    put(&mut image, bpb + 13, &[1]);
+    put(&mut image, bpb + 14, &(RESERVED as u16).to_le_bytes());
+    put(&mut image, bpb + 16, &[2]);
+    put(&mut image, bpb + 17, &512u16.to_le_bytes());
+    put(&mut image, bpb + 19, &(total as u16).to_le_bytes());
+    put(&mut image, bpb + 21, &[0xF8]);
+    put(&mut image, bpb + 22, &(fat_sectors as u16).to_le_bytes());
+    put(&mut image, bpb + 38, &[0x29]);
+    put(&mut image, bpb + 510, &[0x55, 0xAA]);
+
+    // both FATs: media descriptor and reserved entry
+    for fat in 0..2 {
+        let start = (1 + RESERVED + fat * fat_sectors) as usize * 512;
+        match fat12 {
+            true => put(&mut image, start, &[0xF8, 0xFF, 0xFF]),
+            false => put(&mut image, start, &[0xF8, 0xFF, 0xFF, 0xFF]),
+        }
+    }
+
+    image
+}
+
+fn read_all<T: File>(file: &mut T) -> Vec<u8> {
+    let mut data = Vec::new();
+    file.seek(io::SeekFrom::Start(0)).expect("seek to start");
+    file.read_to_end(&mut data).expect("read file");
+    data
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn entry_names<T: Dir>(dir: &T) -> Vec<String> {
+    let mut names: Vec<String> = dir
+        .entries()
+        .expect("entries")
+        .map(|entry| entry.name().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+/// The FAT entry for `cluster` in the first FAT of an `empty_fat32_image`.
+fn fat_entry(image: &SharedImage, cluster: usize) -> u32 {
+    fat_entry_at(image, 1, cluster)
+}
+
+/// The FAT entry for `cluster` in the first FAT of a FAT32 image with 32
+/// reserved sectors whose partition starts at sector `start`.
+fn fat_entry_at(image: &SharedImage, start: usize, cluster: usize) -> u32 {
+    let data = image.0.lock().unwrap();
+    let offset = (start + RESERVED as usize) * 512 + cluster * 4;
+    let mut raw = [0u8; 4];
+    raw.copy_from_slice(&data.get_ref()[offset..offset + 4]);
+    u32::from_le_bytes(raw)
+}
+
+#[test]
+fn test_create_and_write() {
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(empty_fat32_image())).unwrap();
+
+    let mut file = vfat.create_file("/HELLO.TXT").expect("create file");
+    assert_eq!(file.size(), 0);
+
+    let data = pattern(5000);
+    file.write_all(&data).expect("write file");
+    assert_eq!(file.size(), 5000);
+    assert_eq!(read_all(&mut file), data);
+
+    let mut file = vfat.open_file("/hello.txt").expect("file exists");
+    assert_eq!(file.size(), 5000);
+    assert_eq!(read_all(&mut file), data);
+}
+
+#[test]
+fn test_create_errors() {
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(empty_fat32_image())).unwrap();
+
+    vfat.create_file("/A.TXT").expect("create file");
+    let e = vfat.create_file("/A.TXT").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+
+    let e = vfat.create_file("/NOPE/A.TXT").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+
+    let e = vfat.create_file("/WHAT?.TXT").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    let e = vfat.create_file("A.TXT").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_append_and_sync() {
+    let image = SharedImage::new(empty_fat32_image());
+
+    let first = pattern(700);
+    let second = pattern(3000);
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        let mut file = vfat.create_file("/LOG").expect("create file");
+        file.write_all(&first).expect("write file");
+        file.sync().expect("sync");
+    }
+
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        let mut file = vfat.open_file("/LOG").expect("file persisted");
+        assert_eq!(read_all(&mut file), first);
+
+        file.seek(io::SeekFrom::End(0)).expect("seek to end");
+        file.write_all(&second).expect("append");
+        file.sync().expect("sync");
+    }
+
+    let vfat = VFat::<StdVFatHandle>::from(image).unwrap();
+    let mut file = vfat.open_file("/LOG").expect("file persisted");
+    let expected: Vec<u8> = first.iter().chain(second.iter()).cloned().collect();
+    assert_eq!(file.size(), expected.len() as u64);
+    assert_eq!(read_all(&mut file), expected);
+}
+
+#[test]
+fn test_overwrite_in_place() {
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(empty_fat32_image())).unwrap();
+    let mut file = vfat.create_file("/DATA.BIN").expect("create file");
+    file.write_all(&pattern(2048)).expect("write file");
+
+    file.seek(io::SeekFrom::Start(500)).expect("seek");
+    file.write_all(&[0xAA; 100]).expect("overwrite");
+    assert_eq!(file.size(), 2048);
+
+    let mut expected = pattern(2048);
+    expected[500..600].copy_from_slice(&[0xAA; 100]);
+    assert_eq!(read_all(&mut file), expected);
+}
+
+#[test]
+fn test_truncate_and_extend() {
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(empty_fat32_image())).unwrap();
+    let mut file = vfat.create_file("/TRUNC").expect("create file");
+    let data = pattern(4000);
+    file.write_all(&data).expect("write file");
+
+    file.set_len(1000).expect("truncate");
+    assert_eq!(file.size(), 1000);
+    assert_eq!(read_all(&mut file), &data[..1000]);
+
+    file.set_len(1500).expect("extend");
+    let mut expected = data[..1000].to_vec();
+    expected.resize(1500, 0);
+    assert_eq!(read_all(&mut file), expected);
+
+    // growing by many clusters zeroes them without writing each byte
+    file.set_len(4 * 1024 * 1024 + 7).expect("extend");
+    expected.resize(4 * 1024 * 1024 + 7, 0);
+    let mut file = vfat.open_file("/TRUNC").expect("file exists");
+    assert_eq!(file.size(), 4 * 1024 * 1024 + 7);
+    assert!(read_all(&mut file) == expected);
+
+    file.set_len(0).expect("truncate to empty");
+    assert_eq!(file.size(), 0);
+    assert!(read_all(&mut file).is_empty());
+
+    // the freed clusters are reused by the next file
+    let mut other = vfat.create_file("/OTHER").expect("create file");
+    other.write_all(&data).expect("write file");
+    assert_eq!(read_all(&mut other), data);
+
+    let file = vfat.open_file("/TRUNC").expect("file exists");
+    assert_eq!(file.size(), 0);
+}
+
+#[test]
+fn test_directory_grows() {
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(empty_fat32_image())).unwrap();
+
+    // a 512 byte cluster holds 16 entries
+    for i in 0..40 {
+        let mut file = vfat
+            .create_file(format!("/FILE{}.TXT", i))
+            .expect("create file");
+        file.write_all(format!("file {}", i).as_bytes())
+            .expect("write file");
+    }
+
+    let entries: Vec<_> = vfat
+        .open_dir("/")
+        .expect("root")
+        .entries()
+        .expect("entries")
+        .collect();
+    assert_eq!(entries.len(), 40);
+
+    let mut file = vfat.open_file("/FILE37.TXT").expect("file exists");
+    assert_eq!(read_all(&mut file), b"file 37");
+}
+
+/// The first cluster of the root directory of an `empty_fat32_image`.
+fn root_cluster(image: &SharedImage) -> Vec<u8> {
+    let data = image.0.lock().unwrap();
+    let offset = (1 + RESERVED + 2 * FAT_SECTORS) as usize * 512;
+    data.get_ref()[offset..offset + 512].to_vec()
+}
+
+#[test]
+fn test_create_dir() {
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(empty_fat32_image())).unwrap();
+
+    vfat.create_dir("/DOCS").expect("create dir");
+    vfat.create_dir("/DOCS/NOTES").expect("create nested dir");
+    let mut file = vfat
+        .create_file("/DOCS/NOTES/TODO.TXT")
+        .expect("create file");
+    file.write_all(b"write tests").expect("write file");
+
+    let docs = vfat.open_dir("/DOCS").expect("dir exists");
+    assert_eq!(entry_names(&docs), vec![".", "..", "NOTES"]);
+
+    let root = vfat.open_dir("/DOCS/NOTES/../..").expect("walk up to root");
+    assert_eq!(entry_names(&root), vec!["DOCS"]);
+
+    let mut file = vfat.open_file("/DOCS/NOTES/TODO.TXT").expect("file exists");
+    assert_eq!(read_all(&mut file), b"write tests");
+
+    let e = vfat.create_dir("/DOCS/NOTES").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+    let e = vfat.create_dir("/DOCS/NOTES/TODO.TXT/X").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+}
+
+#[test]
+fn test_long_file_names() {
+    let image = SharedImage::new(empty_fat32_image());
+    let long = "A rather long name with spaces.markdown";
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        let mut file = vfat.create_file(format!("/{}", long)).expect("create file");
+        file.write_all(b"long").expect("write file");
+        vfat.create_file("/lower.txt").expect("create file");
+        vfat.create_dir("/Photos from 2019").expect("create dir");
+        vfat.create_file("/Photos from 2019/beach.jpeg")
+            .expect("create file in dir");
+        file.sync().expect("sync");
+    }
+
+    let vfat = VFat::<StdVFatHandle>::from(image).unwrap();
+    let root = vfat.open_dir("/").expect("root");
+    assert_eq!(
+        entry_names(&root),
+        vec![long, "Photos from 2019", "lower.txt"]
+    );
+
+    let mut file = vfat.open_file(format!("/{}", long)).expect("file exists");
+    assert_eq!(read_all(&mut file), b"long");
+    vfat.open_file("/PHOTOS FROM 2019/Beach.jpeg")
+        .expect("case-insensitive lookup");
+
+    let e = vfat.create_file("/LOWER.TXT").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn test_remove() {
+    let image = SharedImage::new(empty_fat32_image());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+
+    let mut file = vfat.create_file("/Some big file.bin").expect("create file");
+    file.write_all(&pattern(5000)).expect("write file");
+    vfat.create_dir("/DIR").expect("create dir");
+    vfat.create_file("/DIR/INNER").expect("create file");
+
+    let e = vfat.remove("/DIR").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+    let e = vfat.remove("/DIR/..").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = vfat.remove("/MISSING").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+
+    vfat.remove("/Some big file.bin").expect("remove file");
+    vfat.remove("/DIR/INNER").expect("remove file");
+    vfat.remove("/DIR").expect("remove empty dir");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+
+    let root = vfat.open_dir("/").expect("root");
+    assert!(entry_names(&root).is_empty());
+    for cluster in 3..20 {
+        assert_eq!(fat_entry(&image, cluster), 0, "cluster {} is free", cluster);
+    }
+
+    // the freed entries and clusters are reused
+    let mut file = vfat.create_file("/AGAIN").expect("create file");
+    file.write_all(&pattern(600)).expect("write file");
+    assert_eq!(entry_names(&root), vec!["AGAIN"]);
+}
+
+#[test]
+fn test_rename() {
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(empty_fat32_image())).unwrap();
+
+    let mut file = vfat.create_file("/OLD.TXT").expect("create file");
+    file.write_all(&pattern(1500)).expect("write file");
+    vfat.create_dir("/A").expect("create dir");
+    vfat.create_dir("/B").expect("create dir");
+    vfat.create_file("/A/INNER").expect("create file");
+
+    vfat.rename("/OLD.TXT", "/A much longer name.txt")
+        .expect("rename in place");
+    vfat.rename("/A much longer name.txt", "/B/NEW.TXT")
+        .expect("move across dirs");
+    let mut file = vfat.open_file("/B/NEW.TXT").expect("moved file");
+    assert_eq!(read_all(&mut file), pattern(1500));
+    let root = vfat.open_dir("/").expect("root");
+    assert_eq!(entry_names(&root), vec!["A", "B"]);
+
+    // moving a directory updates its `..` entry
+    vfat.rename("/A", "/B/A").expect("move dir");
+    let b = vfat.open_dir("/B/A/..").expect("parent of moved dir");
+    assert_eq!(entry_names(&b), vec![".", "..", "A", "NEW.TXT"]);
+    vfat.open_file("/B/A/INNER").expect("contents moved");
+
+    vfat.rename("/B/NEW.TXT", "/B/new.txt")
+        .expect("change case");
+    let b = vfat.open_dir("/B").expect("dir exists");
+    assert_eq!(entry_names(&b), vec![".", "..", "A", "new.txt"]);
+
+    // the entry's own short name does not count as taken, so no alias is made
+    let names: Vec<String> = b
+        .raw_entries()
+        .expect("raw entries")
+        .map(|raw| raw.expect("raw entry").1)
+        .filter(|entry| !entry.is_end() && !entry.is_deleted() && !entry.is_lfn())
+        .map(|entry| entry.regular().short_name())
+        .collect();
+    assert_eq!(names, vec![".", "..", "A", "new.txt"]);
+
+    let e = vfat.rename("/B/A/INNER", "/B/new.txt").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+    let e = vfat.rename("/B", "/B/A/B").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = vfat.rename("/MISSING", "/THERE").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_short_name_generation() {
+    use vfat::name::{generate_short_name, LOWERCASE_BASE, LOWERCASE_EXT};
+
+    let short = |name: &str, taken: &[[u8; 11]]| {
+        let short = generate_short_name(name, taken).expect("short name");
+        (
+            String::from_utf8(short.raw.to_vec()).unwrap(),
+            short.case,
+            short.needs_lfn,
+        )
+    };
+
+    assert_eq!(short("README.TXT", &[]), ("README  TXT".into(), 0, false));
+    assert_eq!(
+        short("readme.txt", &[]),
+        ("README  TXT".into(), LOWERCASE_BASE | LOWERCASE_EXT, false)
+    );
+    assert_eq!(short("Makefile", &[]), ("MAKEFILE   ".into(), 0, true));
+    assert_eq!(short("Read Me.txt", &[]), ("README~1TXT".into(), 0, true));
+    assert_eq!(
+        short("archive.tar.gz", &[]),
+        ("ARCHIV~1GZ ".into(), 0, true)
+    );
+    assert_eq!(short("page.html", &[]), ("PAGE~1  HTM".into(), 0, true));
+    assert_eq!(short(".bashrc", &[]), ("BASHRC~1   ".into(), 0, true));
+    assert_eq!(short("naïve.txt", &[]), ("NA_VE~1 TXT".into(), 0, true));
+    assert_eq!(short("a+b", &[]), ("A_B~1      ".into(), 0, true));
+
+    // collisions move on to the next numeric tail and then to a hash
+    let mut taken = vec![*b"README  TXT"];
+    assert_eq!(short("ReadMe.txt", &taken), ("README~1TXT".into(), 0, true));
+    for n in 1..=4 {
+        taken.push(*format!("README~{}TXT", n).as_bytes().first_chunk().unwrap());
+    }
+    let (hashed, _, needs_lfn) = short("ReadMe.txt", &taken);
+    assert!(needs_lfn);
+    assert!(hashed.starts_with("RE"), "{}", hashed);
+    assert!(
+        hashed[2..6].bytes().all(|b| b.is_ascii_hexdigit()),
+        "{}",
+        hashed
+    );
+    assert_eq!(&hashed[6..], "~1TXT");
+}
+
+#[test]
+fn test_lfn_on_disk_layout() {
+    let image = SharedImage::new(empty_fat32_image());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    vfat.create_file("/A long file name.txt")
+        .expect("create file");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+
+    // 20 characters need two LFN entries, stored last part first
+    let root = root_cluster(&image);
+    let (first, second, short) = (&root[0..32], &root[32..64], &root[64..96]);
+    assert_eq!(&short[..11], b"ALONGF~1TXT");
+    let checksum = vfat::name::lfn_checksum(short.first_chunk().unwrap());
+    for (entry, sequence) in [(first, 0x42), (second, 0x01)] {
+        assert_eq!(entry[0], sequence);
+        assert_eq!(entry[11], 0x0F);
+        assert_eq!(entry[13], checksum);
+    }
+
+    // the second part is NUL terminated and padded with 0xFFFF
+    let chars: Vec<u16> = [&first[1..11], &first[14..26], &first[28..32]]
+        .concat()
+        .chunks(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    let expected: Vec<u16> = "ame.txt"
+        .encode_utf16()
+        .chain([0x0000, 0xFFFF, 0xFFFF, 0xFFFF, 0xFFFF, 0xFFFF])
+        .collect();
+    assert_eq!(chars, expected);
+}
+
+#[test]
+fn test_name_collisions() {
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(empty_fat32_image())).unwrap();
+
+    for i in 0..8 {
+        let mut file = vfat
+            .create_file(format!("/Quarterly report {}.pdf", i))
+            .expect("create file");
+        file.write_all(&[i]).expect("write file");
+    }
+    for i in 0..8 {
+        let mut file = vfat
+            .open_file(format!("/quarterly REPORT {}.PDF", i))
+            .expect("file exists");
+        assert_eq!(read_all(&mut file), [i]);
+    }
+
+    // the alias of an entry is taken, and can be used to open it
+    let mut file = vfat.open_file("/QUARTE~1.PDF").expect("open by alias");
+    assert_eq!(read_all(&mut file), [0]);
+    let e = vfat.create_file("/QUARTE~1.PDF").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+
+    vfat.create_file("/Ünïcödé.txt").expect("create file");
+    vfat.open_file("/ÜNÏCÖDÉ.TXT")
+        .expect("case-insensitive lookup");
+
+    for name in ["/emoji 🦀.txt", "/trailing.", "/tab\tname"] {
+        let e = vfat.create_file(name).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{}", name);
+    }
+}
+
+#[test]
+fn test_fat_type_detection() {
+    use vfat::FatType;
+
+    assert_eq!(FatType::from_cluster_count(4084), FatType::Fat12);
+    assert_eq!(FatType::from_cluster_count(4085), FatType::Fat16);
+    assert_eq!(FatType::from_cluster_count(65524), FatType::Fat16);
+    assert_eq!(FatType::from_cluster_count(65525), FatType::Fat32);
+
+    let images = [
+        (empty_fat16_image(2000), FatType::Fat12),
+        (empty_fat16_image(20_000), FatType::Fat16),
+        (empty_fat32_image(), FatType::Fat32),
+    ];
+    for (image, expected) in images {
+        let ebpb = BiosParameterBlock::from(Cursor::new(image.clone()), 1).unwrap();
+        assert_eq!(ebpb.fat_type(), expected);
+
+        let vfat = VFat::<StdVFatHandle>::from(Cursor::new(image)).unwrap();
+        assert_eq!(vfat.lock(|vfat| vfat.fat_type()), expected);
+    }
+}
+
+#[test]
+fn test_fat12_fat16_read_write() {
+    for clusters in [2000, 20_000] {
+        let image = SharedImage::new(empty_fat16_image(clusters));
+        {
+            let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+            // odd and even FAT12 entries, spread over several FAT sectors
+            for i in 0..6 {
+                let mut file = vfat
+                    .create_file(format!("/File number {}.bin", i))
+                    .expect("create file");
+                file.write_all(&pattern(300 * (i + 1))).expect("write file");
+            }
+            vfat.create_dir("/SUB").expect("create dir");
+            let mut file = vfat.create_file("/SUB/INNER.TXT").expect("create file");
+            file.write_all(&pattern(1400)).expect("write file");
+            vfat.remove("/File number 2.bin").expect("remove file");
+            vfat.lock(|vfat| vfat.sync()).expect("sync");
+        }
+
+        let vfat = VFat::<StdVFatHandle>::from(image).unwrap();
+        for i in [0, 1, 3, 4, 5] {
+            let mut file = vfat
+                .open_file(format!("/File number {}.bin", i))
+                .expect("file persisted");
+            assert_eq!(read_all(&mut file), pattern(300 * (i + 1)));
+        }
+        let mut file = vfat.open_file("/SUB/INNER.TXT").expect("file persisted");
+        assert_eq!(read_all(&mut file), pattern(1400));
+
+        let root = vfat.open_dir("/SUB/..").expect("walk up to root");
+        assert_eq!(root.entries().expect("entries").count(), 6);
+    }
+}
+
+#[test]
+fn test_fixed_root_dir_is_full() {
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(empty_fat16_image(20_000))).unwrap();
+
+    // the root directory has room for exactly 512 entries
+    for i in 0..512 {
+        vfat.create_file(format!("/F{}", i)).expect("create file");
+    }
+    let e = vfat.create_file("/ONEMORE").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+
+    // subdirectories still grow
+    vfat.remove("/F0").expect("remove file");
+    vfat.create_dir("/DIR").expect("create dir");
+    for i in 0..40 {
+        vfat.create_file(format!("/DIR/F{}", i))
+            .expect("create file");
+    }
+}
+
+#[derive(Clone)]
+struct StdExFatHandle(Arc<Mutex<exfat::ExFat<Self>>>);
+
+impl Debug for StdExFatHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "StdExFatHandle")
+    }
+}
+
+impl exfat::ExFatHandle for StdExFatHandle {
+    fn new(val: exfat::ExFat<StdExFatHandle>) -> Self {
+        StdExFatHandle(Arc::new(Mutex::new(val)))
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut exfat::ExFat<StdExFatHandle>) -> R) -> R {
+        f(&mut self.0.lock().expect("all okay"))
+    }
+}
+
+/// The exFAT up-case table used by `exfat_image`, compressed as on disk. It
+/// maps ASCII and Latin-1 lower case letters to upper case.
+fn exfat_upcase_bytes() -> Vec<u8> {
+    let mut table: Vec<u16> = vec![0xFFFF, b'a' as u16];
+    table.extend(b'A' as u16..=b'Z' as u16);
+    table.extend([0xFFFF, 0xE0 - (b'z' as u16 + 1)]);
+    table.extend((0xE0..=0xFE).map(|c| if c == 0xF7 { c } else { c - 0x20 }));
+    table.iter().flat_map(|c| c.to_le_bytes()).collect()
+}
+
+/// Builds the entry set for a file or directory named `name`.
+fn exfat_entry_set(
+    name: &str,
+    directory: bool,
+    first_cluster: u32,
+    len: u64,
+    valid_len: u64,
+    contiguous: bool,
+) -> Vec<[u8; 32]> {
+    let name: Vec<u16> = name.encode_utf16().collect();
+    let upcase = exfat::UpcaseTable::from_bytes(&exfat_upcase_bytes());
+    let name_entries = name.len().div_ceil(15);
+
+    let mut file = [0u8; 32];
+    file[0] = 0x85;
+    file[1] = 1 + name_entries as u8;
+    file[4] = if directory { 0x10 } else { 0x20 };
+    // 2021-03-04 05:06:08
+    let date = ((2021 - 1980) << 9) | (3 << 5) | 4;
+    let time = (5 << 11) | (6 << 5) | (8 / 2);
+    file[12..16].copy_from_slice(&((date << 16) | time as u32).to_le_bytes());
+
+    let mut stream = [0u8; 32];
+    stream[0] = 0xC0;
+    stream[1] = 0x01 | if contiguous { 0x02 } else { 0 };
+    stream[3] = name.len() as u8;
+    stream[4..6].copy_from_slice(&upcase.name_hash(&name).to_le_bytes());
+    stream[8..16].copy_from_slice(&valid_len.to_le_bytes());
+    stream[20..24].copy_from_slice(&first_cluster.to_le_bytes());
+    stream[24..32].copy_from_slice(&len.to_le_bytes());
+
+    let mut set = vec![file, stream];
+    for part in name.chunks(15) {
+        let mut entry = [0u8; 32];
+        entry[0] = 0xC1;
+        for (i, c) in part.iter().enumerate() {
+            entry[2 + i * 2..4 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        set.push(entry);
+    }
+
+    let mut checksum = 0u16;
+    for (i, entry) in set.iter().enumerate() {
+        for (j, &b) in entry.iter().enumerate() {
+            if i == 0 && (j == 2 || j == 3) {
+                continue;
+            }
+            checksum = (checksum << 15)
+                .wrapping_add(checksum >> 1)
+                .wrapping_add(b as u16);
+        }
+    }
+    set[0][2..4].copy_from_slice(&checksum.to_le_bytes());
+    set
+}
+
+const EXFAT_CLUSTERS: u32 = 500;
+const EXFAT_LONG_NAME: &str = "A long file name spanning several entries.txt";
+
+/// Builds an exFAT image with 512 byte sectors and clusters in a partition
+/// starting at sector 1. The root directory spans clusters 4 and 9 and holds
+/// `hello.txt` (700 contiguous bytes at clusters 5 and 6), an empty file with
+/// a long name, a deleted file and `Docs` at cluster 7. `Docs/nested.bin` is
+/// 1300 bytes long in the fragmented chain 8, 10, 11, of which only the first
+/// 1000 bytes are valid.
+fn exfat_image() -> Vec<u8> {
+    const FAT_OFFSET: u32 = 24;
+    const HEAP_OFFSET: u32 = 32;
+    const TOTAL: u64 = (HEAP_OFFSET + EXFAT_CLUSTERS) as u64;
+
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+    fn cluster(n: u32) -> usize {
+        (1 + HEAP_OFFSET + n - 2) as usize * 512
+    }
+
+    // anything the image doesn't set reads as garbage
+    let mut image = vec![0xAAu8; (1 + TOTAL as usize) * 512];
+    put(&mut image, 0, &[0; 512]);
+
+    // MBR with a single exFAT partition
+    put(&mut image, 446 + 4, &[0x07]);
+    put(&mut image, 446 + 8, &1u32.to_le_bytes());
+    put(&mut image, 446 + 12, &(TOTAL as u32).to_le_bytes());
+    put(&mut image, 510, &[0x55, 0xAA]);
+
+    // boot sector
+    let boot = 512;
+    put(&mut image, boot, &[0; 512]);
+    put(&mut image, boot + 3, b"EXFAT   ");
+    put(&mut image, boot + 72, &TOTAL.to_le_bytes());
+    put(&mut image, boot + 80, &FAT_OFFSET.to_le_bytes());
+    put(&mut image, boot + 84, &8u32.to_le_bytes());
+    put(&mut image, boot + 88, &HEAP_OFFSET.to_le_bytes());
+    put(&mut image, boot + 92, &EXFAT_CLUSTERS.to_le_bytes());
+    put(&mut image, boot + 96, &4u32.to_le_bytes());
+    put(&mut image, boot + 108, &[9, 0, 1]);
+    put(&mut image, boot + 510, &[0x55, 0xAA]);
+
+    // FAT: the root directory and nested.bin are chained, everything else is
+    // contiguous or a single cluster
+    let fat = (1 + FAT_OFFSET) as usize * 512;
+    put(&mut image, fat, &[0; 8 * 512]);
+    let links: [(usize, u32); 9] = [
+        (0, 0xFFFFFFF8),
+        (1, 0xFFFFFFFF),
+        (2, 0xFFFFFFFF),
+        (3, 0xFFFFFFFF),
+        (4, 9),
+        (9, 0xFFFFFFFF),
+        (8, 10),
+        (10, 11),
+        (11, 0xFFFFFFFF),
+    ];
+    for (from, to) in links {
+        put(&mut image, fat + from * 4, &to.to_le_bytes());
+    }
+
+    // allocation bitmap: clusters 2 to 11 are in use
+    let bitmap_len = EXFAT_CLUSTERS.div_ceil(8) as usize;
+    put(&mut image, cluster(2), &[0; 512]);
+    put(&mut image, cluster(2), &[0xFF, 0x03]);
+
+    // up-case table
+    let upcase = exfat_upcase_bytes();
+    put(&mut image, cluster(3), &upcase);
+
+    // root directory
+    let mut label = [0u8; 32];
+    label[0] = 0x83;
+    let mut bitmap = [0u8; 32];
+    bitmap[0] = 0x81;
+    bitmap[20..24].copy_from_slice(&2u32.to_le_bytes());
+    bitmap[24..32].copy_from_slice(&(bitmap_len as u64).to_le_bytes());
+    let mut table = [0u8; 32];
+    table[0] = 0x82;
+    table[4..8].copy_from_slice(&exfat::UpcaseTable::checksum(&upcase).to_le_bytes());
+    table[20..24].copy_from_slice(&3u32.to_le_bytes());
+    table[24..32].copy_from_slice(&(upcase.len() as u64).to_le_bytes());
+
+    let mut deleted = exfat_entry_set("gone.txt", false, 0, 0, 0, false);
+    for entry in deleted.iter_mut() {
+        entry[0] &= 0x7F;
+    }
+
+    let mut root = vec![label, bitmap, table];
+    root.extend(exfat_entry_set("hello.txt", false, 5, 700, 700, true));
+    root.extend(exfat_entry_set("Docs", true, 7, 512, 512, true));
+    root.extend(deleted);
+    root.extend(exfat_entry_set(EXFAT_LONG_NAME, false, 0, 0, 0, false));
+    assert!(root.len() > 16, "root directory should span two clusters");
+
+    put(&mut image, cluster(4), &[0; 512]);
+    put(&mut image, cluster(9), &[0; 512]);
+    for (i, entry) in root.iter().enumerate() {
+        let offset = match i < 16 {
+            true => cluster(4) + i * 32,
+            false => cluster(9) + (i - 16) * 32,
+        };
+        put(&mut image, offset, entry);
+    }
+
+    // Docs
+    put(&mut image, cluster(7), &[0; 512]);
+    for (i, entry) in exfat_entry_set("nested.bin", false, 8, 1300, 1000, false)
+        .iter()
+        .enumerate()
+    {
+        put(&mut image, cluster(7) + i * 32, entry);
+    }
+
+    // file data
+    let hello = pattern(700);
+    put(&mut image, cluster(5), &hello[..512]);
+    put(&mut image, cluster(6), &hello[512..]);
+    let nested = pattern(1000);
+    put(&mut image, cluster(8), &nested[..512]);
+    put(&mut image, cluster(10), &nested[512..]);
+
+    image
+}
+
+#[test]
+fn test_exfat_mount() {
+    use exfat::ExFatHandle;
+
+    let fs = exfat::ExFat::<StdExFatHandle>::from(Cursor::new(exfat_image())).expect("mount");
+    assert_eq!(fs.handle().lock(|exfat| exfat.cluster_size()), 512);
+    assert_eq!(fs.handle().lock(|exfat| exfat.free_clusters()), 490);
+    assert!(fs.handle().lock(|exfat| exfat.is_allocated(11)));
+    assert!(!fs.handle().lock(|exfat| exfat.is_allocated(12)));
+
+    // a FAT volume is not an exFAT volume
+    let e = exfat::ExFat::<StdExFatHandle>::from(Cursor::new(empty_fat32_image())).unwrap_err();
+    expect_variant!(e, vfat::Error::NotFound);
+
+    // the up-case table must match its checksum
+    let mut image = exfat_image();
+    image[(1 + 32 + 1) * 512] ^= 1;
+    let e = exfat::ExFat::<StdExFatHandle>::from(Cursor::new(image)).unwrap_err();
+    expect_variant!(e, vfat::Error::Io(ref e) if e.kind() == io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_exfat_partitions() {
+    use crate::gpt::Guid;
+    use exfat::ExFatHandle;
+
+    let free =
+        |fs: &exfat::ExFatFs<StdExFatHandle>| fs.handle().lock(|exfat| exfat.free_clusters());
+
+    // the volume without the MBR in front of it
+    let volume = exfat_image()[512..].to_vec();
+    let fs = exfat::ExFat::<StdExFatHandle>::from_superfloppy(Cursor::new(volume.clone()))
+        .expect("mount superfloppy");
+    assert_eq!(free(&fs), 490);
+    let e =
+        exfat::ExFat::<StdExFatHandle>::from_superfloppy(Cursor::new(exfat_image())).unwrap_err();
+    expect_variant!(e, vfat::Error::BadSignature);
+
+    let fs = exfat::ExFat::<StdExFatHandle>::from_partition(Cursor::new(exfat_image()), 0)
+        .expect("mount chosen partition");
+    assert_eq!(free(&fs), 490);
+    let e =
+        exfat::ExFat::<StdExFatHandle>::from_partition(Cursor::new(exfat_image()), 1).unwrap_err();
+    expect_variant!(e, vfat::Error::NotFound);
+
+    // the first basic data partition has no file system and is passed over
+    let gpt = gpt_image_holding(&volume, Guid::BASIC_DATA);
+    let fs = exfat::ExFat::<StdExFatHandle>::from(Cursor::new(gpt.clone())).expect("mount GPT");
+    assert_eq!(free(&fs), 490);
+    let e = exfat::ExFat::<StdExFatHandle>::from_partition(Cursor::new(gpt), 0).unwrap_err();
+    expect_variant!(e, vfat::Error::BadSignature);
+}
+
+#[test]
+fn test_exfat_entries() {
+    let fs = exfat::ExFat::<StdExFatHandle>::from(Cursor::new(exfat_image())).expect("mount");
+
+    let root = fs.open_dir("/").expect("open root");
+    assert_eq!(
+        entry_names(&root),
+        vec![EXFAT_LONG_NAME, "Docs", "hello.txt"]
+    );
+
+    let docs = fs.open("/docs").expect("case-insensitive open");
+    assert!(docs.is_dir());
+    assert_eq!(docs.name(), "Docs");
+    let modified = docs.metadata().modified();
+    assert_eq!(
+        (modified.year(), modified.month(), modified.day()),
+        (2021, 3, 4)
+    );
+    assert_eq!(
+        (modified.hour(), modified.minute(), modified.second()),
+        (5, 6, 8)
+    );
+
+    assert!(fs
+        .open(format!("/{}", EXFAT_LONG_NAME.to_uppercase()))
+        .is_ok());
+    assert!(fs.open("/DOCS/NESTED.BIN").expect("nested file").is_file());
+    expect_variant!(fs.open("/gone.txt"), Err(ref e) if e.kind() == io::ErrorKind::NotFound);
+    expect_variant!(fs.open("/hello.txt/x"), Err(ref e) if e.kind() == io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_exfat_read() {
+    let fs = exfat::ExFat::<StdExFatHandle>::from(Cursor::new(exfat_image())).expect("mount");
+
+    let mut hello = fs.open_file("/hello.txt").expect("open contiguous file");
+    assert_eq!(hello.size(), 700);
+    assert_eq!(read_all(&mut hello), pattern(700));
+
+    // past the valid length the file reads as zeroes, not what is on disk
+    let mut nested = fs
+        .open_file("/Docs/nested.bin")
+        .expect("open fragmented file");
+    let mut expected = pattern(1000);
+    expected.resize(1300, 0);
+    assert_eq!(read_all(&mut nested), expected);
+
+    nested.seek(io::SeekFrom::Start(990)).expect("seek");
+    let mut buf = [0xFFu8; 20];
+    nested
+        .read_exact(&mut buf)
+        .expect("read across the valid length");
+    assert_eq!(buf[..10], pattern(1000)[990..]);
+    assert_eq!(buf[10..], [0; 10]);
+
+    let mut empty = fs
+        .open_file(format!("/{}", EXFAT_LONG_NAME))
+        .expect("open empty file");
+    assert_eq!(read_all(&mut empty), Vec::<u8>::new());
+}
+
+#[test]
+fn test_exfat_is_read_only() {
+    let fs = exfat::ExFat::<StdExFatHandle>::from(Cursor::new(exfat_image())).expect("mount");
+
+    let mut file = fs.open_file("/hello.txt").expect("open file");
+    let e = file.write(b"hi").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    let e = file.set_len(0).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+
+    let e = fs.create_file("/new.txt").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    let e = fs.remove("/hello.txt").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    let e = fs.set_read_only("/hello.txt", false).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+}
+
+/// The first sector of the FAT partition in `gpt_image`.
+const GPT_FAT_START: u64 = 42;
+
+/// Builds a GPT disk holding a basic data partition without a file system at
+/// sectors 34 to 41 and the FAT32 volume of `empty_fat32_image` in an EFI
+/// system partition at `GPT_FAT_START`. Both tables have 128 entries.
+fn gpt_image() -> Vec<u8> {
+    gpt_image_holding(&empty_fat32_image()[512..], crate::gpt::Guid::EFI_SYSTEM)
+}
+
+/// Builds a GPT disk like `gpt_image` whose second partition has type
+/// `type_guid` and holds `volume`.
+fn gpt_image_holding(volume: &[u8], type_guid: crate::gpt::Guid) -> Vec<u8> {
+    use crate::gpt::Guid;
+    use crate::util::crc32;
+
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    let fat_sectors = (volume.len() / 512) as u64;
+    let last_lba = GPT_FAT_START + fat_sectors + 32;
+    let mut image = vec![0u8; (last_lba as usize + 1) * 512];
+    put(&mut image, GPT_FAT_START as usize * 512, volume);
+
+    // protective MBR covering the whole disk
+    put(&mut image, 446 + 4, &[0xEE]);
+    put(&mut image, 446 + 8, &1u32.to_le_bytes());
+    put(&mut image, 446 + 12, &(last_lba as u32).to_le_bytes());
+    put(&mut image, 510, &[0x55, 0xAA]);
+
+    let mut entries = vec![0u8; 128 * 128];
+    let partitions = [
+        (Guid::BASIC_DATA, 34, GPT_FAT_START - 1, "data"),
+        (
+            type_guid,
+            GPT_FAT_START,
+            GPT_FAT_START + fat_sectors - 1,
+            "EFI system",
+        ),
+    ];
+    for (i, (guid, first, last, name)) in partitions.iter().enumerate() {
+        let entry = &mut entries[i * 128..(i + 1) * 128];
+        entry[..16].copy_from_slice(&guid.0);
+        entry[16] = i as u8 + 1;
+        entry[32..40].copy_from_slice(&first.to_le_bytes());
+        entry[40..48].copy_from_slice(&last.to_le_bytes());
+        for (j, c) in name.encode_utf16().enumerate() {
+            entry[56 + j * 2..58 + j * 2].copy_from_slice(&c.to_le_bytes());
+        }
+    }
+    let entries_crc = crc32(&entries);
+
+    // primary table after the MBR, backup table at the end of the disk
+    let tables = [(1, last_lba, 2), (last_lba, 1, last_lba - 32)];
+    for (current, backup, entries_lba) in tables {
+        let mut header = [0u8; 92];
+        header[..8].copy_from_slice(b"EFI PART");
+        header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+        header[12..16].copy_from_slice(&92u32.to_le_bytes());
+        header[24..32].copy_from_slice(&current.to_le_bytes());
+        header[32..40].copy_from_slice(&backup.to_le_bytes());
+        header[40..48].copy_from_slice(&34u64.to_le_bytes());
+        header[48..56].copy_from_slice(&(last_lba - 33).to_le_bytes());
+        header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&128u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let header_crc = crc32(&header);
+        header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+
+        put(&mut image, current as usize * 512, &header);
+        put(&mut image, entries_lba as usize * 512, &entries);
+    }
+
+    image
+}
+
+#[test]
+fn test_crc32() {
+    use crate::util::crc32;
+
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+}
+
+#[test]
+fn test_gpt_partitions() {
+    use crate::gpt::{Guid, GuidPartitionTable};
+
+    assert_eq!(
+        format!("{:?}", Guid::EFI_SYSTEM),
+        "C12A7328-F81F-11D2-BA4B-00A0C93EC93B"
+    );
+
+    let image = SharedImage::new(gpt_image());
+    let gpt = GuidPartitionTable::from(image.clone()).expect("read GPT");
+    assert!(!gpt.used_backup());
+
+    let partitions: Vec<_> = gpt.partitions().collect();
+    assert_eq!(partitions.len(), 2);
+    assert_eq!(partitions[0].name(), "data");
+    assert!(partitions[0].type_guid == Guid::BASIC_DATA);
+    assert_eq!(partitions[1].name(), "EFI system");
+    assert_eq!({ partitions[1].first_lba }, GPT_FAT_START);
+    assert_eq!(partitions[1].num_sectors(), TOTAL as u64);
+
+    // the empty basic data partition is skipped for the FAT one
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).expect("mount GPT disk");
+        let mut file = vfat.create_file("/GPT.TXT").expect("create file");
+        file.write_all(b"on a GPT disk").expect("write file");
+        vfat.lock(|vfat| vfat.sync()).expect("sync");
+    }
+    let vfat = VFat::<StdVFatHandle>::from(image).expect("mount GPT disk");
+    let mut file = vfat.open_file("/GPT.TXT").expect("file persisted");
+    assert_eq!(read_all(&mut file), b"on a GPT disk");
+
+    // an MBR disk has no GPT
+    let e = GuidPartitionTable::from(Cursor::new(empty_fat32_image())).unwrap_err();
+    expect_variant!(e, crate::gpt::Error::NoProtectiveMbr);
+}
+
+#[test]
+fn test_gpt_backup_fallback() {
+    use crate::gpt::{Error, GuidPartitionTable};
+
+    let last_sector = gpt_image().len() / 512 - 1;
+    let corruptions = [
+        // primary header checksum and signature
+        vec![512 + 16],
+        vec![512],
+        // primary partition entry array
+        vec![2 * 512 + 56],
+    ];
+    for offsets in corruptions {
+        let mut image = gpt_image();
+        for offset in offsets {
+            image[offset] ^= 0xFF;
+        }
+
+        let gpt = GuidPartitionTable::from(Cursor::new(image.clone())).expect("read backup");
+        assert!(gpt.used_backup());
+        assert_eq!({ gpt.header.current_lba }, last_sector as u64);
+        assert_eq!(gpt.partitions().count(), 2);
+        VFat::<StdVFatHandle>::from(Cursor::new(image)).expect("mount from backup");
+    }
+
+    // with both tables damaged the primary's error is reported
+    let mut image = gpt_image();
+    image[512 + 16] ^= 0xFF;
+    image[last_sector * 512 + 16] ^= 0xFF;
+    let e = GuidPartitionTable::from(Cursor::new(image.clone())).unwrap_err();
+    expect_variant!(e, Error::BadChecksum);
+    let e = VFat::<StdVFatHandle>::from(Cursor::new(image)).unwrap_err();
+    expect_variant!(e, vfat::Error::Gpt(Error::BadChecksum));
+}
+
+/// Builds an MBR disk with two FAT12 volumes from `empty_fat16_image` and an
+/// empty, bootable Linux partition between them.
+fn two_fat_partition_image() -> Vec<u8> {
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    let volume = empty_fat16_image(2000);
+    let volume = &volume[512..];
+    let sectors = (volume.len() / 512) as u32;
+    let starts = [1, 1 + sectors + 8];
+
+    let mut image = vec![0u8; (1 + 2 * sectors as usize + 8) * 512];
+    put(&mut image, 510, &[0x55, 0xAA]);
+    let entries = [
+        (0, 0x01, starts[0], sectors),
+        (1, 0x83, 1 + sectors, 8),
+        (3, 0x01, starts[1], sectors),
+    ];
+    for (slot, partition_type, start, len) in entries {
+        let entry = 446 + slot * 16;
+        put(&mut image, entry + 4, &[partition_type]);
+        put(&mut image, entry + 8, &start.to_le_bytes());
+        put(&mut image, entry + 12, &len.to_le_bytes());
+    }
+    put(&mut image, 446 + 16, &[0x80]);
+
+    for start in starts {
+        put(&mut image, start as usize * 512, volume);
+    }
+    image
+}
+
+#[test]
+fn test_partition_enumeration() {
+    use crate::gpt::Guid;
+    use crate::{partitions, PartitionInfo, PartitionType};
+
+    let image = two_fat_partition_image();
+    let sectors = (empty_fat16_image(2000).len() / 512 - 1) as u64;
+    assert_eq!(
+        partitions(Cursor::new(image)).expect("read MBR"),
+        vec![
+            PartitionInfo {
+                index: 0,
+                partition_type: PartitionType::Mbr(0x01),
+                start: 1,
+                num_sectors: sectors,
+                bootable: false,
+            },
+            PartitionInfo {
+                index: 1,
+                partition_type: PartitionType::Mbr(0x83),
+                start: 1 + sectors,
+                num_sectors: 8,
+                bootable: true,
+            },
+            PartitionInfo {
+                index: 3,
+                partition_type: PartitionType::Mbr(0x01),
+                start: 1 + sectors + 8,
+                num_sectors: sectors,
+                bootable: false,
+            },
+        ]
+    );
+
+    let gpt = partitions(Cursor::new(gpt_image())).expect("read GPT");
+    let types: Vec<_> = gpt.iter().map(|p| (p.index, p.partition_type)).collect();
+    assert_eq!(
+        types,
+        vec![
+            (0, PartitionType::Gpt(Guid::BASIC_DATA)),
+            (1, PartitionType::Gpt(Guid::EFI_SYSTEM)),
+        ]
+    );
+    assert_eq!(gpt[1].start, GPT_FAT_START);
+    assert!(gpt.iter().all(|p| p.partition_type.may_hold_fat()));
+}
+
+#[test]
+fn test_mount_chosen_partition() {
+    let image = SharedImage::new(two_fat_partition_image());
+    {
+        let second = VFat::<StdVFatHandle>::from_partition(image.clone(), 3).expect("mount");
+        second.create_file("/SECOND.TXT").expect("create file");
+        second.lock(|vfat| vfat.sync()).expect("sync");
+    }
+
+    // `from` still picks the first FAT partition, which is untouched
+    let first = VFat::<StdVFatHandle>::from(image.clone()).expect("mount first");
+    assert!(first.open("/SECOND.TXT").is_err());
+    let second = VFat::<StdVFatHandle>::from_partition(image.clone(), 3).expect("mount");
+    assert!(second.open("/SECOND.TXT").is_ok());
+
+    // partitions without FAT and missing partitions
+    let e = VFat::<StdVFatHandle>::from_partition(image.clone(), 1).unwrap_err();
+    expect_variant!(e, vfat::Error::BadSignature);
+    let e = VFat::<StdVFatHandle>::from_partition(image.clone(), 2).unwrap_err();
+    expect_variant!(e, vfat::Error::NotFound);
+
+    let gpt = SharedImage::new(gpt_image());
+    let e = VFat::<StdVFatHandle>::from_partition(gpt.clone(), 0).unwrap_err();
+    expect_variant!(e, vfat::Error::BadSignature);
+    VFat::<StdVFatHandle>::from_partition(gpt, 1).expect("mount EFI system partition");
+
+    let options = vfat::MountOptions {
+        read_only: true,
+        ..Default::default()
+    };
+    let second = VFat::<StdVFatHandle>::from_partition_with_options(image.clone(), 3, &options)
+        .expect("mount read only");
+    assert!(second.lock(|vfat| vfat.is_read_only()));
+    let e = second.create_file("/THIRD.TXT").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn test_mount_superfloppy() {
+    // the volume without the MBR in front of it
+    let image = SharedImage::new(empty_fat32_image()[512..].to_vec());
+    {
+        let vfat = VFat::<StdVFatHandle>::from_superfloppy(image.clone()).expect("mount");
+        let mut file = vfat.create_file("/FLOPPY.TXT").expect("create file");
+        file.write_all(b"no partition table").expect("write file");
+        vfat.lock(|vfat| vfat.sync()).expect("sync");
+    }
+
+    let vfat = VFat::<StdVFatHandle>::from_superfloppy(image.clone()).expect("mount");
+    let mut file = vfat.open_file("/FLOPPY.TXT").expect("file persisted");
+    assert_eq!(read_all(&mut file), b"no partition table");
+
+    let options = vfat::MountOptions {
+        read_only: true,
+        ..Default::default()
+    };
+    let vfat = VFat::<StdVFatHandle>::from_superfloppy_with_options(image, &options)
+        .expect("mount read only");
+    assert!(vfat.lock(|vfat| vfat.is_read_only()));
+    let e = vfat.remove("/FLOPPY.TXT").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+
+    // a partitioned disk has an MBR, not a boot sector, at sector 0
+    let e = VFat::<StdVFatHandle>::from_superfloppy(Cursor::new(empty_fat32_image())).unwrap_err();
+    expect_variant!(e, vfat::Error::BadSignature);
+}
+
+#[test]
+fn test_cache_eviction_and_write_back() {
+    use crate::vfat::{CacheStats, CachedPartition, Partition};
+
+    let image = SharedImage::new(pattern(16 * 512));
+    let partition = Partition {
+        start: 0,
+        num_sectors: 16,
+        sector_size: 512,
+    };
+    let mut cache = CachedPartition::with_capacity(image.clone(), partition, 4);
+
+    for sector in 0..8 {
+        assert_eq!(
+            cache.get(sector).unwrap(),
+            &pattern(16 * 512)[sector as usize * 512..][..512]
+        );
+    }
+    cache.get(7).unwrap();
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 1,
+            misses: 8,
+            evictions: 4,
+            write_backs: 0,
+        }
+    );
+
+    // dirty sectors are written back when they are evicted
+    cache.write_sector(0, &[0xAB; 512]).unwrap();
+    for sector in 8..16 {
+        cache.get(sector).unwrap();
+    }
+    let on_disk = |sector: usize| image.0.lock().unwrap().get_ref()[sector * 512..][..512].to_vec();
+    assert_eq!(on_disk(0), vec![0xAB; 512]);
+    assert_eq!(cache.stats().write_backs, 1);
+
+    // flush writes back but keeps the sector cached, sync_all drops it
+    cache.write_sector(15, &[0xCD; 512]).unwrap();
+    cache.flush().unwrap();
+    assert_eq!(on_disk(15), vec![0xCD; 512]);
+    cache.reset_stats();
+    cache.get(15).unwrap();
+    assert_eq!(cache.stats().hits, 1);
+    cache.write_sector(14, &[0xEF; 512]).unwrap();
+    cache.sync_all().unwrap();
+    assert_eq!(on_disk(14), vec![0xEF; 512]);
+    cache.get(15).unwrap();
+    assert_eq!(cache.stats().misses, 1);
+
+    // shrinking the cache writes back what it evicts
+    cache.write_sector(3, &[0x11; 512]).unwrap();
+    cache.set_capacity(1).unwrap();
+    cache.get(4).unwrap();
+    assert_eq!(on_disk(3), vec![0x11; 512]);
+}
+
+#[test]
+fn test_vfat_cache_is_bounded() {
+    let image = SharedImage::new(empty_fat32_image());
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        vfat.lock(|vfat| vfat.set_cache_capacity(16)).unwrap();
+
+        // far more sectors than the cache holds
+        let mut file = vfat.create_file("/BIG.BIN").expect("create file");
+        file.write_all(&pattern(200 * 512)).expect("write file");
+        assert_eq!(read_all(&mut file), pattern(200 * 512));
+
+        let stats = vfat.lock(|vfat| vfat.cache_stats());
+        assert!(stats.evictions > 0 && stats.write_backs > 0, "{}", stats);
+        assert!(stats.to_string().contains("hit rate"));
+        vfat.lock(|vfat| vfat.sync_all()).unwrap();
+    }
+
+    let vfat = VFat::<StdVFatHandle>::from(image).unwrap();
+    let mut file = vfat.open_file("/BIG.BIN").expect("file persisted");
+    assert_eq!(read_all(&mut file), pattern(200 * 512));
+}
+
+/// Returns a device tracing accesses to `image` into the returned log.
+fn traced(image: &SharedImage) -> (impl BlockDevice, Arc<Mutex<Vec<Access>>>) {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let trace = log.clone();
+    let device = Tracing::new(image.clone(), move |access| {
+        trace.lock().unwrap().push(access)
+    });
+    (device, log)
+}
+
+/// The number of sectors in each write in `log`, or in each read if `writes`
+/// is false.
+fn transfers(log: &Mutex<Vec<Access>>, writes: bool) -> Vec<u64> {
+    let log = log.lock().unwrap();
+    log.iter()
+        .filter_map(|access| match (*access, writes) {
+            (Access::Write { count, .. }, true) | (Access::Read { count, .. }, false) => {
+                Some(count)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_multi_sector_defaults() {
+    // only implements the single sector methods
+    struct Sectors(Vec<u8>);
+
+    impl BlockDevice for Sectors {
+        fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+            let data = &self.0[n as usize * 512..][..512];
+            buf[..512].copy_from_slice(data);
+            Ok(512)
+        }
+
+        fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+            self.0[n as usize * 512..][..512].copy_from_slice(&buf[..512]);
+            Ok(512)
+        }
+    }
+
+    let mut device = Sectors(pattern(8 * 512));
+    let mut cursor = Cursor::new(pattern(8 * 512));
+    let mut buf = vec![0; 3 * 512];
+    assert_eq!(device.read_sectors(2, 3, &mut buf).unwrap(), 3 * 512);
+    assert_eq!(buf, pattern(8 * 512)[2 * 512..5 * 512]);
+    assert_eq!(cursor.read_sectors(2, 3, &mut buf).unwrap(), 3 * 512);
+    assert_eq!(buf, pattern(8 * 512)[2 * 512..5 * 512]);
+
+    assert_eq!(device.write_sectors(5, 2, &[7; 1024]).unwrap(), 1024);
+    assert_eq!(device.0[5 * 512..7 * 512], [7; 1024]);
+    assert_eq!(cursor.write_sectors(5, 2, &[7; 1024]).unwrap(), 1024);
+    assert_eq!(cursor.get_ref()[5 * 512..7 * 512], [7; 1024]);
+
+    let e = device.read_sectors(0, 4, &mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = cursor.write_sectors(0, 4, &buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_contiguous_clusters_use_multi_sector_io() {
+    let image = SharedImage::new(empty_fat32_image());
+
+    let data = pattern(64 * 512 + 100);
+    let (device, log) = traced(&image);
+    {
+        let vfat = VFat::<StdVFatHandle>::from(device).unwrap();
+        let mut file = vfat.create_file("/RUN.BIN").expect("create file");
+        file.write_all(&data).expect("write file");
+        vfat.lock(|vfat| vfat.sync()).expect("sync");
+    }
+    assert_eq!(transfers(&log, true).into_iter().max(), Some(64));
+
+    let (device, log) = traced(&image);
+    let vfat = VFat::<StdVFatHandle>::from(device).unwrap();
+    let mut file = vfat.open_file("/RUN.BIN").expect("file persisted");
+    let mut buf = vec![0; data.len()];
+    file.read_exact(&mut buf).expect("read file");
+    assert_eq!(buf, data);
+
+    // one call for the 64 whole clusters instead of one per sector
+    let reads = transfers(&log, false);
+    assert_eq!(reads.iter().max(), Some(&64));
+    let single = reads.iter().filter(|&&count| count == 1).count();
+    assert!(single < 64, "{:?}", reads);
+
+    // a partial read at the end still sees what the cache holds
+    file.seek(io::SeekFrom::Start(64 * 512)).unwrap();
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, data[64 * 512..]);
+}
+
+#[test]
+fn test_device_wrappers() {
+    let image = SharedImage::new(vec![0; 8 * 512]);
+    let sector = |n: usize| image.0.lock().unwrap().get_ref()[n * 512..][..512].to_vec();
+
+    // a write past the limit writes the sectors before it and fails
+    let mut device = FailAfter::new(image.clone(), 2, Fault::Fail);
+    device.write_sectors(0, 1, &[1; 512]).unwrap();
+    device.write_sectors(1, 3, &[2; 3 * 512]).unwrap_err();
+    assert_eq!(
+        (sector(0), sector(1), sector(2)),
+        (vec![1; 512], vec![2; 512], vec![0; 512])
+    );
+    device.write_sector(4, &[3; 512]).unwrap_err();
+    assert_eq!(sector(4), vec![0; 512]);
+    let mut buf = vec![0; 512];
+    device.read_sector(1, &mut buf).unwrap();
+    assert_eq!(buf, vec![2; 512]);
+
+    // a torn write keeps the end of the old sector
+    let mut device = FailAfter::new(image.clone(), 0, Fault::Tear(100));
+    device.write_sector(0, &[4; 512]).unwrap_err();
+    assert_eq!(sector(0)[..100], [4; 100]);
+    assert_eq!(sector(0)[100..], [1; 412]);
+
+    // a transfer touching a bad sector fails as a whole
+    let mut device = BadSectors::new(image.clone(), [3]);
+    let mut buf = vec![0; 3 * 512];
+    device.read_sectors(1, 2, &mut buf[..1024]).unwrap();
+    device.read_sectors(1, 3, &mut buf).unwrap_err();
+    device.write_sectors(2, 3, &[5; 3 * 512]).unwrap_err();
+    assert_eq!(sector(2), vec![0; 512]);
+    device.write_sector(4, &[5; 512]).unwrap();
+
+    let (mut device, log) = traced(&image);
+    device.read_sector(4, &mut buf).unwrap();
+    device.write_sectors(5, 2, &[6; 1024]).unwrap();
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            Access::Read { start: 4, count: 1 },
+            Access::Write { start: 5, count: 2 }
+        ]
+    );
+    assert_eq!(sector(6), vec![6; 512]);
+}
+
+#[test]
+fn test_multi_sector_io_is_cache_coherent() {
+    use crate::vfat::{CachedPartition, Partition};
+
+    let image = SharedImage::new(vec![0; 8 * 512]);
+    let partition = Partition {
+        start: 0,
+        num_sectors: 8,
+        sector_size: 512,
+    };
+    let mut cache = CachedPartition::new(image.clone(), partition);
+
+    // dirty cached sectors win over what is on the disk
+    cache.write_sector(2, &[2; 512]).unwrap();
+    let mut buf = vec![0xFF; 4 * 512];
+    cache.read_sectors(0, 4, &mut buf).unwrap();
+    assert_eq!(buf[..2 * 512], [0; 1024]);
+    assert_eq!(buf[2 * 512..3 * 512], [2; 512]);
+
+    // writes reach the disk and update cached copies
+    cache.write_sectors(2, 2, &[3; 1024]).unwrap();
+    assert_eq!(cache.get(2).unwrap(), &[3; 512]);
+    cache.flush().unwrap();
+    assert_eq!(
+        image.0.lock().unwrap().get_ref()[2 * 512..4 * 512],
+        [3; 1024]
+    );
+
+    let e = cache.read_sectors(6, 4, &mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
+
+/// Overwrites the bytes of `image` at `offset` with `bytes`.
+fn poke(image: &SharedImage, offset: usize, bytes: &[u8]) {
+    let mut data = image.0.lock().unwrap();
+    data.get_mut()[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+/// The byte offset of `cluster` in an `empty_fat32_image`.
+fn cluster_offset(cluster: usize) -> usize {
+    (1 + RESERVED + 2 * FAT_SECTORS) as usize * 512 + (cluster - 2) * 512
+}
+
+#[test]
+fn test_check_and_repair() {
+    use vfat::Problem;
+
+    const LONG: &str = "A long name.markdown";
+
+    let image = SharedImage::new(empty_fat32_image());
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        let mut a = vfat.create_file("/A.BIN").expect("create file");
+        a.write_all(&pattern(1500)).expect("write file");
+        let mut b = vfat.create_file("/B.BIN").expect("create file");
+        b.write_all(&pattern(100)).expect("write file");
+        vfat.create_file(format!("/{}", LONG)).expect("create file");
+        vfat.create_dir("/DOCS").expect("create dir");
+        let mut notes = vfat.create_file("/DOCS/NOTES.TXT").expect("create file");
+        notes.write_all(b"notes").expect("write file");
+
+        let report = vfat.lock(|vfat| vfat.check()).expect("check");
+        assert!(report.is_clean(), "{:?}", report.problems);
+        assert_eq!((report.files, report.dirs), (4, 2));
+    }
+
+    // A.BIN is clusters 3 to 5 and B.BIN is cluster 6
+    assert_eq!(fat_entry(&image, 3), 4);
+    assert_eq!(fat_entry(&image, 6), 0x0FFFFFFF);
+    let fat = (1 + RESERVED) as usize * 512;
+    let root = cluster_offset(2);
+    // A.BIN claims to be 100 bytes long
+    poke(&image, root + 28, &100u32.to_le_bytes());
+    // B.BIN runs into A.BIN
+    poke(&image, fat + 6 * 4, &5u32.to_le_bytes());
+    // the LFN entries of the long name no longer match its short entry
+    poke(&image, root + 2 * 32 + 13, &[0]);
+    poke(&image, root + 3 * 32 + 13, &[0]);
+    // a chain that belongs to nothing
+    poke(&image, fat + 1000 * 4, &1001u32.to_le_bytes());
+    poke(&image, fat + 1001 * 4, &0x0FFFFFFFu32.to_le_bytes());
+    // a stale free count
+    poke(&image, 2 * 512 + 488, &12345u32.to_le_bytes());
+
+    let mut expected = vec![
+        Problem::FatMismatch {
+            copy: 1,
+            sectors: 2,
+        },
+        Problem::SizeMismatch {
+            path: String::from("/A.BIN"),
+            size: 100,
+            clusters: 3,
+        },
+        Problem::CrossLinked {
+            path: String::from("/B.BIN"),
+            other: String::from("/A.BIN"),
+            cluster: 5,
+        },
+        Problem::InvalidLfn {
+            dir: String::from("/"),
+            offset: 64,
+        },
+        Problem::LostChain {
+            start: 1000,
+            len: 2,
+        },
+        Problem::FsInfoFreeCount {
+            recorded: 12345,
+            actual: CLUSTERS - 9,
+        },
+    ];
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert_eq!(report.problems, expected);
+    assert!(!report.repaired);
+
+    // checking changes nothing
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert_eq!(report.problems, expected);
+
+    // the free count is compared against the repaired FAT
+    expected[5] = Problem::FsInfoFreeCount {
+        recorded: 12345,
+        actual: CLUSTERS - 5,
+    };
+    let report = vfat.lock(|vfat| vfat.repair()).expect("repair");
+    assert_eq!(report.problems, expected);
+    assert!(report.repaired);
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+
+    let mut a = vfat.open_file("/A.BIN").expect("file exists");
+    assert_eq!(read_all(&mut a), &pattern(1500)[..100]);
+    let mut b = vfat.open_file("/B.BIN").expect("file exists");
+    assert_eq!(read_all(&mut b), pattern(100));
+    assert_eq!(fat_entry(&image, 4), 0);
+    assert_eq!(fat_entry(&image, 1000), 0);
+
+    // the long name is gone but the file is still there under its alias
+    let root = vfat.open_dir("/").expect("root");
+    assert_eq!(
+        entry_names(&root),
+        vec!["A.BIN", "ALONGN~1.MAR", "B.BIN", "DOCS"]
+    );
+    let mut notes = vfat.open_file("/DOCS/NOTES.TXT").expect("file exists");
+    assert_eq!(read_all(&mut notes), b"notes");
+}
+
+#[test]
+fn test_repair_removes_unusable_directories() {
+    use vfat::Problem;
+
+    let image = SharedImage::new(empty_fat16_image(5000));
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        vfat.create_dir("/SUB").expect("create dir");
+        let mut file = vfat.create_file("/SUB/FILE.TXT").expect("create file");
+        file.write_all(b"lost").expect("write file");
+        vfat.create_file("/KEEP.TXT").expect("create file");
+        vfat.lock(|vfat| vfat.repair()).expect("repair");
+    }
+
+    // SUB is cluster 2 and its file cluster 3. free SUB's cluster in both FATs
+    let fat_sectors = (5002 * 2usize).div_ceil(512);
+    for fat in 0..2 {
+        poke(&image, (2 + fat * fat_sectors) * 512 + 2 * 2, &[0, 0]);
+    }
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let report = vfat.lock(|vfat| vfat.repair()).expect("repair");
+    assert_eq!(
+        report.problems,
+        vec![
+            Problem::BadChain {
+                path: String::from("/SUB"),
+                cluster: 2,
+            },
+            Problem::LostChain { start: 3, len: 1 },
+        ]
+    );
+
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+    let root = vfat.open_dir("/").expect("root");
+    assert_eq!(entry_names(&root), vec!["KEEP.TXT"]);
+}
+
+/// Enough 512 byte sectors for `vfat::format` to fit the fewest clusters
+/// FAT32 allows.
+const FORMAT_SECTORS: u64 = 70_000;
+
+#[test]
+fn test_format() {
+    let image = SharedImage::new(vec![0; FORMAT_SECTORS as usize * 512]);
+    let options = vfat::FormatOptions {
+        volume_label: Some(String::from("rustos")),
+        volume_id: 0xDEADBEEF,
+        ..Default::default()
+    };
+    vfat::format(image.clone(), FORMAT_SECTORS, &options).expect("format");
+
+    let mbr = MasterBootRecord::from(image.clone()).expect("valid MBR");
+    let partition = &mbr.partition_table[0];
+    assert_eq!(partition.partition_type, 0x0C);
+    assert_eq!({ partition.relative_sector }, 2048);
+    assert_eq!({ partition.total_sectors } as u64, FORMAT_SECTORS - 2048);
+
+    let ebpb = BiosParameterBlock::from(image.clone(), 2048).expect("valid EBPB");
+    assert!(ebpb.is_fat());
+    assert_eq!(ebpb.fat_type(), vfat::FatType::Fat32);
+    assert_eq!(&ebpb.volume_label, b"RUSTOS     ");
+    assert_eq!({ ebpb.volume_id }, 0xDEADBEEF);
+    let backup = BiosParameterBlock::from(image.clone(), 2048 + 6).expect("valid backup");
+    assert_eq!(backup.boot_code[..], ebpb.boot_code[..]);
+    assert_eq!({ backup.sectors_per_fat }, { ebpb.sectors_per_fat });
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).expect("mount");
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+
+    // the volume label entry is not listed
+    let root = vfat.open_dir("/").expect("root");
+    assert!(entry_names(&root).is_empty());
+
+    let mut file = vfat.create_file("/HELLO.TXT").expect("create file");
+    file.write_all(&pattern(3000)).expect("write file");
+    file.sync().expect("sync");
+
+    let vfat = VFat::<StdVFatHandle>::from(image).expect("mount");
+    let mut file = vfat.open_file("/HELLO.TXT").expect("file exists");
+    assert_eq!(read_all(&mut file), pattern(3000));
+}
+
+#[test]
+fn test_format_superfloppy() {
+    let mut image = Cursor::new(vec![0; FORMAT_SECTORS as usize * 512]);
+    let options = vfat::FormatOptions {
+        partitioned: false,
+        ..Default::default()
+    };
+    vfat::format(&mut image, FORMAT_SECTORS, &options).expect("format");
+
+    let ebpb = BiosParameterBlock::from(&mut image, 0).expect("valid EBPB");
+    assert_eq!(&ebpb.volume_label, b"NO NAME    ");
+
+    let vfat = VFat::<StdVFatHandle>::from_superfloppy(image).expect("mount");
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+}
+
+#[test]
+fn test_format_errors() {
+    let mut image = Cursor::new(vec![0; FORMAT_SECTORS as usize * 512]);
+    let format = |image: &mut Cursor<Vec<u8>>, sectors, options: vfat::FormatOptions| {
+        vfat::format(image, sectors, &options).unwrap_err().kind()
+    };
+
+    // too few clusters for FAT32
+    let kind = format(&mut image, 10_000, Default::default());
+    assert_eq!(kind, io::ErrorKind::InvalidInput);
+    let options = vfat::FormatOptions {
+        cluster_size: Some(4096),
+        ..Default::default()
+    };
+    assert_eq!(
+        format(&mut image, FORMAT_SECTORS, options),
+        io::ErrorKind::InvalidInput
+    );
+
+    for cluster_size in [0, 256, 1000, 128 * 1024] {
+        let options = vfat::FormatOptions {
+            cluster_size: Some(cluster_size),
+            ..Default::default()
+        };
+        let kind = format(&mut image, FORMAT_SECTORS, options);
+        assert_eq!(kind, io::ErrorKind::InvalidInput);
+    }
+
+    for label in ["", " LEADING", "TWELVE CHARS", "A*B"] {
+        let options = vfat::FormatOptions {
+            volume_label: Some(String::from(label)),
+            ..Default::default()
+        };
+        let kind = format(&mut image, FORMAT_SECTORS, options);
+        assert_eq!(kind, io::ErrorKind::InvalidInput);
+    }
+}
+
+/// The free cluster count and next free cluster hint in the FSInfo sector of
+/// an image made by `vfat::format`.
+fn fsinfo_fields(image: &SharedImage) -> (u32, u32) {
+    let data = image.0.lock().unwrap();
+    let offset = (2048 + 1) * 512;
+    let field = |at: usize| {
+        let mut raw = [0u8; 4];
+        raw.copy_from_slice(&data.get_ref()[offset + at..offset + at + 4]);
+        u32::from_le_bytes(raw)
+    };
+    (field(488), field(492))
+}
+
+#[test]
+fn test_fsinfo_free_space() {
+    let image = SharedImage::new(vec![0; FORMAT_SECTORS as usize * 512]);
+    vfat::format(image.clone(), FORMAT_SECTORS, &Default::default()).expect("format");
+    let clusters = BiosParameterBlock::from(image.clone(), 2048)
+        .expect("valid EBPB")
+        .cluster_count();
+    assert_eq!(fsinfo_fields(&image), (clusters - 1, 3));
+
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        let total = vfat.lock(|vfat| vfat.total_space());
+        assert_eq!(total, clusters as u64 * 512);
+        let free = vfat.lock(|vfat| vfat.free_space()).unwrap();
+        assert_eq!(free, total - 512);
+
+        // 10 clusters for the file, then 3 for the second after one is freed
+        let mut file = vfat.create_file("/TEN").expect("create file");
+        file.write_all(&pattern(10 * 512)).expect("write file");
+        file.set_len(9 * 512).expect("truncate");
+        let mut file = vfat.create_file("/THREE").expect("create file");
+        file.write_all(&pattern(3 * 512)).expect("write file");
+        let free = vfat.lock(|vfat| vfat.free_clusters()).unwrap();
+        assert_eq!(free, clusters - 13);
+
+        // nothing reaches the disk before a sync
+        assert_eq!(fsinfo_fields(&image), (clusters - 1, 3));
+        file.sync().expect("sync");
+    }
+
+    // the freed cluster is skipped over by the hint
+    assert_eq!(fsinfo_fields(&image), (clusters - 13, 16));
+    assert_eq!(fat_entry_at(&image, 2048, 12), 0);
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    assert_eq!(
+        vfat.lock(|vfat| vfat.free_clusters()).unwrap(),
+        clusters - 13
+    );
+    vfat.remove("/THREE").expect("remove");
+    assert_eq!(
+        vfat.lock(|vfat| vfat.free_clusters()).unwrap(),
+        clusters - 10
+    );
+}
+
+#[test]
+fn test_fsinfo_fallback_to_fat_scan() {
+    let image = SharedImage::new(vec![0; FORMAT_SECTORS as usize * 512]);
+    vfat::format(image.clone(), FORMAT_SECTORS, &Default::default()).expect("format");
+    let clusters = BiosParameterBlock::from(image.clone(), 2048)
+        .expect("valid EBPB")
+        .cluster_count();
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        let mut file = vfat.create_file("/FILE").expect("create file");
+        file.write_all(&pattern(4 * 512)).expect("write file");
+        file.sync().expect("sync");
+    }
+
+    let fsinfo = (2048 + 1) * 512;
+    // a count larger than the volume is ignored
+    poke(&image, fsinfo + 488, &(clusters + 1).to_le_bytes());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    assert_eq!(
+        vfat.lock(|vfat| vfat.free_clusters()).unwrap(),
+        clusters - 5
+    );
+
+    // so is all of FSInfo when its signature is wrong, and it is left alone
+    poke(&image, fsinfo + 488, &7u32.to_le_bytes());
+    poke(&image, fsinfo, b"XXXX");
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    assert_eq!(
+        vfat.lock(|vfat| vfat.free_clusters()).unwrap(),
+        clusters - 5
+    );
+    vfat.create_file("/OTHER").expect("create file");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+    assert_eq!(fsinfo_fields(&image).0, 7);
+}
+
+/// The FAT entry for `cluster` in FAT number `copy` of an
+/// `empty_fat32_image`.
+fn fat_copy_entry(image: &SharedImage, copy: u32, cluster: usize) -> u32 {
+    fat_entry_at(image, (1 + copy * FAT_SECTORS) as usize, cluster)
+}
+
+#[test]
+fn test_fat_mirroring() {
+    let image = SharedImage::new(empty_fat32_image());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let mut file = vfat.create_file("/FILE").expect("create file");
+    file.write_all(&pattern(1500)).expect("write file");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+
+    assert_eq!(vfat.lock(|vfat| vfat.active_fat()), 0);
+    for cluster in 3..6 {
+        assert_ne!(fat_copy_entry(&image, 0, cluster), 0);
+        assert_eq!(
+            fat_copy_entry(&image, 0, cluster),
+            fat_copy_entry(&image, 1, cluster)
+        );
+    }
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+}
+
+#[test]
+fn test_fat_mirroring_disabled() {
+    let image = SharedImage::new(empty_fat32_image());
+    // only the second FAT is active
+    poke(&image, 512 + 40, &0x81u16.to_le_bytes());
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    assert_eq!(vfat.lock(|vfat| vfat.active_fat()), 1);
+    let mut file = vfat.create_file("/FILE").expect("create file");
+    file.write_all(&pattern(1500)).expect("write file");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+
+    assert_eq!(fat_copy_entry(&image, 0, 3), 0);
+    assert_eq!(fat_copy_entry(&image, 1, 3), 4);
+    // the inactive FAT is expected to differ
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+
+    let mut file = vfat.open_file("/FILE").expect("file exists");
+    assert_eq!(read_all(&mut file), pattern(1500));
+
+    // an active FAT that does not exist
+    poke(&image, 512 + 40, &0x82u16.to_le_bytes());
+    match VFat::<StdVFatHandle>::from(image.clone()) {
+        Err(vfat::Error::BadSignature) => {}
+        other => panic!("expected BadSignature, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_fat_fallback_and_comparison() {
+    let image = SharedImage::new(empty_fat32_image());
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        let mut file = vfat.create_file("/FILE").expect("create file");
+        file.write_all(&pattern(1500)).expect("write file");
+        vfat.lock(|vfat| vfat.sync()).expect("sync");
+    }
+    let compare = vfat::MountOptions {
+        compare_fats: true,
+        ..Default::default()
+    };
+
+    // a diverged sector is reported but the first FAT stays in use
+    let last_sector = (1 + RESERVED + 2 * FAT_SECTORS - 1) as usize * 512;
+    poke(&image, last_sector, &[1]);
+    let vfat = VFat::<StdVFatHandle>::from_with_options(image.clone(), &compare).unwrap();
+    assert_eq!(vfat.lock(|vfat| vfat.active_fat()), 0);
+    let copies = vfat.lock(|vfat| vfat.fat_copies().to_vec());
+    assert_eq!(
+        copies,
+        vec![
+            vfat::FatCopy {
+                copy: 0,
+                differing_sectors: 0,
+                unreadable_sectors: 0
+            },
+            vfat::FatCopy {
+                copy: 1,
+                differing_sectors: 1,
+                unreadable_sectors: 0
+            },
+        ]
+    );
+    poke(&image, last_sector, &[0]);
+
+    // the first sector of the first FAT cannot be read
+    let bad = 1 + RESERVED as u64;
+
+    // without comparing, the second FAT is read in place of the bad sector
+    let device = BadSectors::new(image.clone(), [bad]);
+    let vfat = VFat::<StdVFatHandle>::from(device).unwrap();
+    assert_eq!(vfat.lock(|vfat| vfat.active_fat()), 0);
+    assert!(vfat.lock(|vfat| vfat.fat_copies().is_empty()));
+    let mut file = vfat.open_file("/FILE").expect("file exists");
+    assert_eq!(read_all(&mut file), pattern(1500));
+
+    // comparing switches to the second FAT entirely
+    let device = BadSectors::new(image.clone(), [bad]);
+    let vfat = VFat::<StdVFatHandle>::from_with_options(device, &compare).unwrap();
+    assert_eq!(vfat.lock(|vfat| vfat.active_fat()), 1);
+    let copies = vfat.lock(|vfat| vfat.fat_copies().to_vec());
+    assert_eq!(copies[0].unreadable_sectors, 1);
+    assert_eq!(copies[1].unreadable_sectors, 0);
+    let mut file = vfat.open_file("/FILE").expect("file exists");
+    assert_eq!(read_all(&mut file), pattern(1500));
+}
+
+#[test]
+fn test_unreadable_directory() {
+    let image = SharedImage::new(empty_fat32_image());
+    let cluster = {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        let sub = vfat.create_dir("/SUB").expect("create dir");
+        // fill the first cluster so that the file goes in the second one,
+        // then leave only deleted entries in the first
+        for i in 0..14 {
+            vfat.create_file(format!("/SUB/{}", i))
+                .expect("create file");
+        }
+        vfat.create_file("/SUB/FILE").expect("create file");
+        for i in 0..14 {
+            vfat.remove(format!("/SUB/{}", i)).expect("remove file");
+        }
+        vfat.lock(|vfat| vfat.sync()).expect("sync");
+        sub.first_cluster.num() as usize
+    };
+    let second = fat_entry(&image, cluster) as usize;
+    assert!((2..0x0FFF_FFF8).contains(&second));
+
+    // reading the directory fails, which is not the same as the entries being
+    // missing or the directory being empty
+    let device = BadSectors::new(image.clone(), [(cluster_offset(second) / 512) as u64]);
+    let vfat = VFat::<StdVFatHandle>::from(device).unwrap();
+    let sub = vfat.open_dir("/SUB").expect("open dir");
+    expect_variant!(sub.find("FILE"), Err(e) if e.kind() == io::ErrorKind::Other);
+    expect_variant!(
+        vfat.create_file("/SUB/FILE"),
+        Err(e) if e.kind() == io::ErrorKind::Other
+    );
+    expect_variant!(
+        vfat.remove("/SUB"),
+        Err(e) if e.kind() == io::ErrorKind::Other
+    );
+    vfat.open_dir("/SUB").expect("dir is kept");
+}
+
+#[test]
+fn test_timestamp_conversion() {
+    use vfat::{Date, Time, Timestamp};
+
+    assert!(Date::new(2000, 2, 29).is_some());
+    assert!(Date::new(2100, 2, 29).is_none());
+    assert!(Date::new(2023, 4, 31).is_none());
+    assert!(Date::new(1979, 12, 31).is_none());
+    assert!(Date::new(2108, 1, 1).is_none());
+    assert!(Date::new(2023, 13, 1).is_none());
+    assert!(Time::new(24, 0, 0).is_none());
+    assert!(Time::new(23, 60, 0).is_none());
+
+    let ts = Timestamp::from_unix(1_000_000_000);
+    assert_eq!(ts, Timestamp::new(2001, 9, 9, 1, 46, 40).unwrap());
+    assert_eq!((ts.year(), ts.month(), ts.day()), (2001, 9, 9), "{}", ts);
+    assert_eq!((ts.hour(), ts.minute(), ts.second()), (1, 46, 40));
+    assert_eq!(ts.to_unix(), 1_000_000_000);
+
+    // two second resolution
+    assert_eq!(Timestamp::from_unix(1_000_000_001).to_unix(), 1_000_000_000);
+    let leap = Timestamp::new(2000, 2, 29, 0, 0, 0).unwrap();
+    assert_eq!(leap.to_unix(), 951_782_400);
+    assert_eq!(Timestamp::from_unix(951_782_400), leap);
+
+    // clamped to the range FAT can hold
+    assert_eq!(
+        Timestamp::from_unix(0),
+        Timestamp::new(1980, 1, 1, 0, 0, 0).unwrap()
+    );
+    assert_eq!(Timestamp::from_unix(u64::MAX).to_unix(), 4_354_819_198);
+}
+
+#[test]
+fn test_clock_stamps_entries() {
+    use vfat::Timestamp;
+
+    let image = SharedImage::new(empty_fat32_image());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+
+    // without a clock nothing is stamped
+    vfat.create_file("/PLAIN").expect("create file");
+    let plain = vfat.open("/PLAIN").expect("file exists");
+    assert_eq!(plain.metadata().created(), Timestamp::default());
+
+    let clock = Arc::new(Mutex::new(1_700_000_000u64));
+    let time = clock.clone();
+    vfat.lock(|vfat| vfat.set_clock(move || Timestamp::from_unix(*time.lock().unwrap())));
+    let at = Timestamp::from_unix;
+
+    let mut file = vfat.create_file("/FILE").expect("create file");
+    vfat.create_dir("/DIR").expect("create dir");
+    let metadata = vfat.open("/DIR").expect("dir exists").metadata().clone();
+    assert_eq!(metadata.created(), at(1_700_000_000));
+    assert_eq!(metadata.modified(), at(1_700_000_000));
+
+    *clock.lock().unwrap() = 1_700_100_000;
+    file.write_all(b"stamped").expect("write file");
+    let metadata = vfat.open("/FILE").expect("file exists").metadata().clone();
+    assert_eq!(metadata.created(), at(1_700_000_000));
+    assert_eq!(metadata.modified(), at(1_700_100_000));
+    assert_eq!(metadata.accessed().date, at(1_700_100_000).date);
+
+    // reading on a later day updates only the access date
+    *clock.lock().unwrap() = 1_700_200_000;
+    let mut file = vfat.open_file("/FILE").expect("file exists");
+    assert_eq!(read_all(&mut file), b"stamped");
+    let metadata = vfat.open("/FILE").expect("file exists").metadata().clone();
+    assert_eq!(metadata.modified(), at(1_700_100_000));
+    assert_eq!(metadata.accessed().date, at(1_700_200_000).date);
+    assert_eq!(metadata.accessed().time, vfat::Time::default());
+
+    // `.` and `..` share the new directory's timestamps
+    let dot = vfat.open("/DIR/.").expect("dot entry");
+    assert_eq!(dot.metadata().created(), at(1_700_000_000));
+}
+
+#[test]
+fn test_attributes() {
+    let image = SharedImage::new(empty_fat32_image());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let mut file = vfat.create_file("/FILE").expect("create file");
+    file.write_all(b"original").expect("write file");
+    vfat.create_dir("/DIR").expect("create dir");
+
+    vfat.set_read_only("/FILE", true).expect("set read only");
+    let mut file = vfat.open_file("/FILE").expect("file exists");
+    assert!(file.metadata.read_only());
+    let e = file.write(b"changed").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    let e = file.set_len(0).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    assert_eq!(read_all(&mut file), b"original");
+
+    // the on-disk attribute byte
+    let attributes = |vfat: &StdVFatHandle, index: usize| {
+        vfat.lock(|vfat| vfat.sync()).expect("sync");
+        root_cluster(&image)[index * 32 + 11]
+    };
+    assert_eq!(attributes(&vfat, 0), 0x21);
+
+    let mut entry = vfat.open("/DIR").expect("dir exists");
+    let mut dir_attributes = entry.metadata().attributes;
+    assert!(dir_attributes.directory());
+    assert!(!dir_attributes.system());
+    dir_attributes.set_hidden(true);
+    dir_attributes.set_system(true);
+    entry
+        .set_attributes(dir_attributes)
+        .expect("set attributes");
+    assert!(entry.metadata().hidden());
+    assert_eq!(attributes(&vfat, 1), 0x16);
+
+    // the directory bit cannot be changed, but the archive bit can
+    let mut file_attributes = vfat::Attributes::default();
+    file_attributes.set_read_only(false);
+    file_attributes.set_archive(false);
+    file.set_attributes(file_attributes)
+        .expect("set attributes");
+    assert!(!file.metadata.attributes.archive());
+    vfat.open("/DIR")
+        .expect("dir exists")
+        .set_attributes(file_attributes)
+        .expect("set attributes");
+    assert_eq!(attributes(&vfat, 0), 0x00);
+    assert_eq!(attributes(&vfat, 1), 0x10);
+
+    file.write_all(b" and changed").expect("write file");
+    let mut file = vfat.open_file("/FILE").expect("file exists");
+    assert_eq!(read_all(&mut file), b"original and changed");
+
+    let e = vfat.set_read_only("/", true).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_volume_label() {
+    let image = SharedImage::new(vec![0; FORMAT_SECTORS as usize * 512]);
+    let options = vfat::FormatOptions {
+        volume_label: Some(String::from("rustos")),
+        volume_id: 0x1234ABCD,
+        ..Default::default()
+    };
+    vfat::format(image.clone(), FORMAT_SECTORS, &options).expect("format");
+
+    let boot_label = |image: &SharedImage, sector: usize| {
+        let data = image.0.lock().unwrap();
+        let offset = (2048 + sector) * 512 + 71;
+        data.get_ref()[offset..offset + 11].to_vec()
+    };
+    let root = 2048
+        + 32
+        + 2 * BiosParameterBlock::from(image.clone(), 2048)
+            .unwrap()
+            .fat_size();
+    let root = root as usize * 512;
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    assert_eq!(vfat.lock(|vfat| vfat.serial()).unwrap(), Some(0x1234ABCD));
+    assert_eq!(
+        vfat.lock(|vfat| vfat.label()).unwrap().as_deref(),
+        Some("RUSTOS")
+    );
+
+    vfat.lock(|vfat| vfat.set_label(Some("Card 2")))
+        .expect("set label");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+    assert_eq!(
+        vfat.lock(|vfat| vfat.label()).unwrap().as_deref(),
+        Some("CARD 2")
+    );
+    assert_eq!(boot_label(&image, 0), b"CARD 2     ");
+    assert_eq!(boot_label(&image, 6), b"CARD 2     ");
+    assert_eq!(
+        &image.0.lock().unwrap().get_ref()[root..root + 12],
+        b"CARD 2     \x08"
+    );
+
+    // the label is not a directory entry
+    vfat.create_file("/FILE").expect("create file");
+    assert_eq!(entry_names(&vfat.open_dir("/").unwrap()), vec!["FILE"]);
+
+    vfat.lock(|vfat| vfat.set_label(None))
+        .expect("remove label");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+    assert_eq!(vfat.lock(|vfat| vfat.label()).unwrap(), None);
+    assert_eq!(boot_label(&image, 0), b"NO NAME    ");
+    assert_eq!(image.0.lock().unwrap().get_ref()[root], 0xE5);
+
+    // the deleted entry is reused
+    vfat.lock(|vfat| vfat.set_label(Some("again")))
+        .expect("set label");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+    assert_eq!(&image.0.lock().unwrap().get_ref()[root..root + 5], b"AGAIN");
+    let e = vfat
+        .lock(|vfat| vfat.set_label(Some("much too long")))
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    assert_eq!(
+        vfat.lock(|vfat| vfat.label()).unwrap().as_deref(),
+        Some("AGAIN")
+    );
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+}
+
+#[test]
+fn test_volume_label_is_journaled() {
+    let image = SharedImage::new(vec![0; FORMAT_SECTORS as usize * 512]);
+    vfat::format(image.clone(), FORMAT_SECTORS, &Default::default()).expect("format");
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    vfat.lock(|vfat| vfat.create_journal(16))
+        .expect("create journal");
+    vfat.lock(|vfat| vfat.set_label(Some("logged")))
+        .expect("set label");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+
+    // the new boot sector was logged before it was written in place
+    let boot = image.0.lock().unwrap().get_ref()[2048 * 512..2049 * 512].to_vec();
+    assert_eq!(&boot[71..82], b"LOGGED     ");
+    let mut journal = vfat.open_file("/JOURNAL.SYS").expect("journal file");
+    let log = read_all(&mut journal);
+    assert!(log.chunks(512).any(|sector| sector == &boot[..]));
+}
+
+#[test]
+fn test_fat16_volume_label() {
+    let image = SharedImage::new(empty_fat16_image(5000));
+    poke(&image, 512 + 39, &0xCAFEu32.to_le_bytes());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    assert_eq!(vfat.lock(|vfat| vfat.serial()).unwrap(), Some(0xCAFE));
+    assert_eq!(vfat.lock(|vfat| vfat.label()).unwrap(), None);
+
+    vfat.lock(|vfat| vfat.set_label(Some("SMALL")))
+        .expect("set label");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+    assert_eq!(
+        vfat.lock(|vfat| vfat.label()).unwrap().as_deref(),
+        Some("SMALL")
+    );
+    let data = image.0.lock().unwrap();
+    assert_eq!(&data.get_ref()[512 + 43..512 + 54], b"SMALL      ");
+}
+
+#[test]
+fn test_directory_streams_clusters() {
+    let image = SharedImage::new(empty_fat32_image());
+    let files = (0..100).map(|i| format!("FILE{:03}.TXT", i));
+    let names: Vec<String> = [".", ".."]
+        .iter()
+        .map(|s| s.to_string())
+        .chain(files)
+        .collect();
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        let dir = vfat.create_dir("/MANY").expect("create dir");
+        for name in &names[2..] {
+            dir.create_file(name).expect("create file");
+        }
+
+        // 102 entries with `.` and `..` take 7 clusters
+        assert_eq!(entry_names(&dir), names);
+        dir.find("FILE099.TXT").expect("last entry");
+        dir.remove("FILE050.TXT").expect("remove");
+        let mut file = dir.create_file("A long name.txt").expect("create file");
+        file.write_all(b"data").expect("write file");
+        let mut file = vfat
+            .open_file("/MANY/A long name.txt")
+            .expect("file exists");
+        assert_eq!(read_all(&mut file), b"data");
+        vfat.lock(|vfat| vfat.sync()).expect("sync");
+    }
+    assert_eq!(fat_entry(&image, 3), 4);
+
+    // the entries are read one cluster at a time, so those before a broken
+    // link in the chain are still listed
+    poke(
+        &image,
+        (1 + RESERVED) as usize * 512 + 3 * 4,
+        &0x0FFFFFF7u32.to_le_bytes(),
+    );
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let dir = vfat.open_dir("/MANY").expect("dir exists");
+    assert_eq!(entry_names(&dir), &names[..16]);
+}
+
+#[test]
+fn test_file_random_access() {
+    const LEN: usize = 300 * 512 + 100;
+
+    let image = SharedImage::new(empty_fat32_image());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let data = pattern(LEN);
+    let mut file = vfat.create_file("/BIG").expect("create file");
+    // written in pieces with another file in between, so the chain is
+    // fragmented and cannot be read as one contiguous run
+    let mut other = vfat.create_file("/OTHER").expect("create file");
+    for chunk in data.chunks(512 * 7) {
+        file.write_all(chunk).expect("write file");
+        other.write_all(&[0; 512]).expect("write file");
+    }
+
+    let mut file = vfat.open_file("/BIG").expect("file exists");
+    let mut state = 12345u64;
+    let mut buf = [0u8; 700];
+    for _ in 0..500 {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+        let offset = (state >> 33) as usize % LEN;
+        file.seek(io::SeekFrom::Start(offset as u64)).expect("seek");
+        let n = file.read(&mut buf).expect("read");
+        let expected = &data[offset..(offset + buf.len()).min(LEN)];
+        assert_eq!(&buf[..n], &expected[..n], "read at {}", offset);
+        assert!(n > 0);
+    }
+
+    // every FAT lookup is a cache access
+    let accesses = |vfat: &StdVFatHandle| {
+        let stats = vfat.lock(|vfat| vfat.cache_stats());
+        stats.hits + stats.misses
+    };
+    let mut file = vfat.open_file("/BIG").expect("file exists");
+    let before = accesses(&vfat);
+    file.seek(io::SeekFrom::End(-1)).expect("seek");
+    file.read_exact(&mut [0]).expect("read");
+    assert!(accesses(&vfat) - before >= 300);
+
+    // once the chain has been walked, seeking backwards starts from a
+    // checkpoint instead of the first cluster
+    for &cluster in &[290u64, 150, 20, 299, 151] {
+        let before = accesses(&vfat);
+        file.seek(io::SeekFrom::Start(cluster * 512 + 3))
+            .expect("seek");
+        let mut byte = [0];
+        file.read_exact(&mut byte).expect("read");
+        assert_eq!(byte[0], data[cluster as usize * 512 + 3]);
+        assert!(
+            accesses(&vfat) - before <= 20,
+            "seek to cluster {}",
+            cluster
+        );
+    }
+
+    // truncating drops checkpoints past the end
+    file.set_len(100 * 512).expect("truncate");
+    file.write_all(&[0xAB; 512 * 50]).expect("write file");
+    file.seek(io::SeekFrom::Start(140 * 512)).expect("seek");
+    let mut byte = [0];
+    file.read_exact(&mut byte).expect("read");
+    assert_eq!(byte[0], 0xAB);
+}
+
+#[test]
+fn test_path_normalization() {
+    use crate::normalize;
+
+    let cases = [
+        ("/", "/"),
+        ("//a///b/", "/a/b"),
+        ("/a/./b/.", "/a/b"),
+        ("/a/b/../c", "/a/c"),
+        ("/../..", "/"),
+        ("/a/../../b", "/b"),
+        ("a/../..", ".."),
+        ("../a/./b/..", "../a"),
+        (".", ""),
+        ("a/b/../../", ""),
+    ];
+    for &(path, expected) in cases.iter() {
+        assert_eq!(normalize(path), Path::new(expected), "{}", path);
+    }
+}
+
+#[test]
+fn test_relative_paths() {
+    let vfat = VFat::<StdVFatHandle>::from(SharedImage::new(empty_fat32_image())).unwrap();
+    vfat.create_dir("/A").expect("create dir");
+    vfat.create_dir("/A/./B").expect("create dir");
+    vfat.create_file("//A/B/../B/file.txt")
+        .expect("create file")
+        .write_all(b"data")
+        .expect("write file");
+    vfat.create_dir("/C").expect("create dir");
+
+    let mut file = vfat.open_file("/A//B/./file.txt").expect("file exists");
+    assert_eq!(read_all(&mut file), b"data");
+    assert!(vfat.open("/../A/B/..").expect("dir exists").is_dir());
+    expect_variant!(vfat.open("A/B"), Err(e) if e.kind() == io::ErrorKind::InvalidInput);
+
+    assert_eq!(
+        vfat.canonicalize("/A/B/../../C/./").expect("dir exists"),
+        Path::new("/C")
+    );
+    assert_eq!(
+        vfat.canonicalize("/..").expect("root exists"),
+        Path::new("/")
+    );
+    expect_variant!(vfat.canonicalize("/A/./missing"), Err(e) if e.kind() == io::ErrorKind::NotFound);
+    // `..` is resolved before anything is looked up
+    assert_eq!(
+        vfat.canonicalize("/missing/../A").expect("dir exists"),
+        Path::new("/A")
+    );
+
+    // paths relative to a directory handle follow the `..` entries on disk
+    let b = vfat.open_dir("/A/B").expect("dir exists");
+    let names_at = |path| entry_names(&b.open(path).expect("dir exists").into_dir().unwrap());
+    let mut file = b
+        .open("file.txt")
+        .expect("file exists")
+        .into_file()
+        .unwrap();
+    assert_eq!(read_all(&mut file), b"data");
+    assert_eq!(b.open(".").expect("dir exists").name(), "B");
+    assert_eq!(b.open("").expect("dir exists").name(), "B");
+    assert_eq!(b.open("..").expect("dir exists").name(), "A");
+    assert_eq!(names_at(".."), [".", "..", "B"]);
+    assert_eq!(names_at("../.."), ["A", "C"]);
+    assert_eq!(names_at("../../../.."), ["A", "C"]);
+    assert_eq!(b.open("../../C").expect("dir exists").name(), "C");
+    assert_eq!(b.open("/C/.").expect("dir exists").name(), "C");
+    expect_variant!(b.open("file.txt/x"), Err(e) if e.kind() == io::ErrorKind::InvalidInput);
+    expect_variant!(b.open("../missing"), Err(e) if e.kind() == io::ErrorKind::NotFound);
+
+    // the root directory reached through `..` cannot have its attributes set
+    let mut root = b.open("../..").unwrap().into_dir().unwrap();
+    expect_variant!(
+        root.set_attributes(vfat::Attributes::default()),
+        Err(e) if e.kind() == io::ErrorKind::InvalidInput
+    );
+
+    // other directories reached through `..` change their own entries
+    let c = vfat.create_dir("/A/B/C").expect("create dir");
+    let mut a = c.open("../..").expect("dir exists");
+    assert_eq!(a.name(), "A");
+    let mut hidden = vfat::Attributes::default();
+    hidden.set_hidden(true);
+    a.set_attributes(hidden).expect("set attributes");
+    assert!(a.metadata().attributes.hidden());
+    let a = vfat.open("/A").expect("dir exists");
+    assert!(a.metadata().attributes.hidden());
+    let dotdot = vfat.open("/A/B/C/..").expect("dir exists");
+    assert_eq!(dotdot.name(), "B");
+    assert!(!dotdot.metadata().attributes.hidden());
+}
+
+#[test]
+fn test_raw_dir_entries() {
+    let image = SharedImage::new(empty_fat32_image());
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        vfat.lock(|vfat| vfat.set_label(Some("RAW")))
+            .expect("set label");
+        vfat.create_file("/A long name.txt").expect("create file");
+        vfat.create_file("/Orphan long name.txt")
+            .expect("create file");
+        vfat.create_file("/KEEP.TXT").expect("create file");
+        vfat.remove("/A long name.txt").expect("remove");
+        vfat.lock(|vfat| vfat.sync()).expect("sync");
+    }
+    // deleting only the short entry leaves its LFN entries orphaned
+    poke(&image, cluster_offset(2) + 192, &[0xE5]);
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let root = vfat.open_dir("/").expect("root");
+    let raw: Vec<(u64, vfat::VFatDirEntry)> = root
+        .raw_entries()
+        .expect("raw entries")
+        .collect::<io::Result<_>>()
+        .expect("read entries");
+
+    // the root directory is one 512 byte cluster
+    assert_eq!(raw.len(), 16);
+    for (i, (offset, _)) in raw.iter().enumerate() {
+        assert_eq!(*offset, i as u64 * 32);
+    }
+
+    let label = raw[0].1;
+    assert!(!label.is_lfn() && label.regular().metadata().attributes.volume_id());
+    assert_eq!(&label.regular().raw_short_name(), b"RAW        ");
+
+    // the deleted file keeps its LFN entries and the rest of its short name
+    assert!(raw[1..4].iter().all(|(_, entry)| entry.is_deleted()));
+    assert!(raw[1].1.is_lfn() && raw[2].1.is_lfn() && !raw[3].1.is_lfn());
+    assert_eq!(&raw[3].1.regular().raw_short_name()[1..], b"LONGN~1TXT");
+
+    // the orphaned LFN entries are yielded with a checksum matching nothing
+    let orphan = raw[6].1.regular();
+    assert!(raw[6].1.is_deleted());
+    let mut name = Vec::new();
+    for (_, entry) in &raw[4..6] {
+        let lfn = entry.long_filename();
+        assert!(entry.is_lfn() && !entry.is_deleted());
+        assert_ne!(lfn.checksum(), orphan.lfn_checksum());
+        lfn.copy_name_into(&mut name);
+    }
+    assert!(raw[4].1.long_filename().is_last());
+    assert_eq!(
+        String::from_utf16_lossy(&name[..20]),
+        "Orphan long name.txt"
+    );
+
+    assert_eq!(raw[7].1.regular().short_name(), "KEEP.TXT");
+    assert!(raw[8..].iter().all(|(_, entry)| entry.is_end()));
+    assert_eq!(entry_names(&root), ["KEEP.TXT"]);
+}
+
+#[test]
+fn test_recover_deleted_file() {
+    let image = SharedImage::new(empty_fat32_image());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let free = vfat
+        .lock(|vfat| vfat.free_clusters())
+        .expect("free clusters");
+    for (path, len) in [
+        ("/DATA.BIN", 1300),
+        ("/KEEP.TXT", 1),
+        ("/GONE.BIN", 600),
+        ("/XATA.BIN", 0),
+    ] {
+        let mut file = vfat.create_file(path).expect("create file");
+        file.write_all(&pattern(len)).expect("write file");
+    }
+    vfat.remove("/DATA.BIN").expect("remove");
+    vfat.remove("/GONE.BIN").expect("remove");
+    assert_eq!(vfat.lock(|vfat| vfat.free_clusters()).unwrap(), free - 1);
+
+    let root = vfat.open_dir("/").expect("root");
+    let deleted: Vec<u64> = root
+        .raw_entries()
+        .expect("raw entries")
+        .map(|entry| entry.expect("read entry"))
+        .filter(|(_, entry)| entry.is_deleted())
+        .map(|(offset, _)| offset)
+        .collect();
+    assert_eq!(deleted, [0, 64]);
+
+    expect_variant!(
+        vfat.lock(|vfat| vfat.recover(&root, 0, 'X')),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists
+    );
+
+    // the first character is restored in uppercase and the clusters are
+    // linked into a chain again
+    vfat.lock(|vfat| vfat.recover(&root, 0, 'd'))
+        .expect("recover");
+    let mut file = vfat.open_file("/DATA.BIN").expect("file recovered");
+    assert_eq!(read_all(&mut file), pattern(1300));
+    assert_eq!(vfat.lock(|vfat| vfat.free_clusters()).unwrap(), free - 4);
+    assert!(vfat.lock(|vfat| vfat.check()).expect("check").is_clean());
+
+    for (offset, first, kind) in [
+        (0, 'D', io::ErrorKind::InvalidInput),
+        (32, 'K', io::ErrorKind::InvalidInput),
+        (64, ' ', io::ErrorKind::InvalidInput),
+        (64, 'é', io::ErrorKind::InvalidInput),
+        (70, 'G', io::ErrorKind::InvalidInput),
+    ] {
+        expect_variant!(
+            vfat.lock(|vfat| vfat.recover(&root, offset, first)),
+            Err(e) if e.kind() == kind
+        );
+    }
+
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+
+    // GONE.BIN had clusters 7 and 8, after the root directory, DATA.BIN and
+    // KEEP.TXT. Once one of them is reused the file cannot be recovered.
+    assert_eq!(fat_entry(&image, 8), 0);
+    poke(
+        &image,
+        (1 + RESERVED) as usize * 512 + 8 * 4,
+        &0x0FFFFFFFu32.to_le_bytes(),
+    );
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let root = vfat.open_dir("/").expect("root");
+    expect_variant!(
+        vfat.lock(|vfat| vfat.recover(&root, 64, 'G')),
+        Err(e) if e.kind() == io::ErrorKind::Other
+    );
+    expect_variant!(vfat.open("/GONE.BIN"), Err(e) if e.kind() == io::ErrorKind::NotFound);
+
+    // an empty file gets no clusters even if its entry recorded one, while a
+    // file with data but no recorded clusters cannot be recovered
+    let image = SharedImage::new(empty_fat32_image());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    vfat.create_file("/EMPTY").expect("create file");
+    let mut file = vfat.create_file("/LOST.BIN").expect("create file");
+    file.write_all(&pattern(600)).expect("write file");
+    vfat.remove("/EMPTY").expect("remove");
+    vfat.remove("/LOST.BIN").expect("remove");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+    poke(&image, cluster_offset(2) + 26, &9u16.to_le_bytes());
+    poke(&image, cluster_offset(2) + 32 + 20, &0u16.to_le_bytes());
+    poke(&image, cluster_offset(2) + 32 + 26, &0u16.to_le_bytes());
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let root = vfat.open_dir("/").expect("root");
+    vfat.lock(|vfat| vfat.recover(&root, 0, 'E'))
+        .expect("recover");
+    assert!(vfat.lock(|vfat| vfat.check()).expect("check").is_clean());
+    let e = vfat.lock(|vfat| vfat.recover(&root, 32, 'L')).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+    assert!(e.to_string().contains("no clusters"), "{}", e);
+}
+
+#[test]
+fn test_recover_fat16_ignores_high_cluster() {
+    let image = SharedImage::new(empty_fat16_image(5000));
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let mut file = vfat.create_file("/DATA.BIN").expect("create file");
+    file.write_all(&pattern(1300)).expect("write file");
+    vfat.remove("/DATA.BIN").expect("remove");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+
+    // FAT12 and FAT16 leave the high half of the cluster number to other uses
+    let ebpb = BiosParameterBlock::from(image.clone(), 1).unwrap();
+    let root = 1 + ebpb.reserved_sectors as usize + 2 * ebpb.fat_size() as usize;
+    poke(&image, root * 512 + 20, &0x1234u16.to_le_bytes());
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let root = vfat.open_dir("/").expect("root");
+    vfat.lock(|vfat| vfat.recover(&root, 0, 'D'))
+        .expect("recover");
+    let mut file = vfat.open_file("/DATA.BIN").expect("file recovered");
+    assert_eq!(read_all(&mut file), pattern(1300));
+    assert!(vfat.lock(|vfat| vfat.check()).expect("check").is_clean());
+}
+
+/// Whether the volume in `image` is consistent and either has `/OLD.TXT` or
+/// the files that replace it in `test_journal_survives_power_loss`. Returns
+/// `true` for the new files.
+fn journal_test_state(image: &SharedImage) -> bool {
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+    match vfat.open_file("/OLD.TXT") {
+        Ok(mut file) => {
+            assert_eq!(read_all(&mut file), pattern(700));
+            expect_variant!(vfat.open("/NEW.TXT"), Err(_));
+            expect_variant!(vfat.open("/DIR"), Err(_));
+            false
+        }
+        Err(_) => {
+            let mut file = vfat.open_file("/NEW.TXT").expect("new file");
+            assert_eq!(read_all(&mut file), pattern(1500));
+            assert!(vfat.open("/DIR").expect("new dir").is_dir());
+            true
+        }
+    }
+}
+
+#[test]
+fn test_journal_survives_power_loss() {
+    let image = SharedImage::new(empty_fat32_image());
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        assert!(!vfat.lock(|vfat| vfat.has_journal()));
+        vfat.lock(|vfat| vfat.create_journal(16))
+            .expect("create journal");
+        expect_variant!(
+            vfat.lock(|vfat| vfat.create_journal(16)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists
+        );
+        let mut file = vfat.create_file("/OLD.TXT").expect("create file");
+        file.write_all(&pattern(700)).expect("write file");
+        vfat.lock(|vfat| vfat.sync()).expect("sync");
+    }
+    let base = image.0.lock().unwrap().get_ref().clone();
+
+    // cut the power after every possible number of writes while the old
+    // file is replaced, leaving the last write undone or torn. the next mount
+    // always sees one state or the other
+    let mut image = SharedImage::new(base.clone());
+    for fault in [Fault::Fail, Fault::Tear(100)] {
+        let (mut old, mut new) = (0, 0);
+        for writes in 0.. {
+            image = SharedImage::new(base.clone());
+            let device = FailAfter::new(image.clone(), writes, fault);
+            let vfat = VFat::<StdVFatHandle>::from(device).unwrap();
+            assert!(vfat.lock(|vfat| vfat.has_journal()));
+            let result = (|| -> io::Result<()> {
+                vfat.create_file("/NEW.TXT")?.write_all(&pattern(1500))?;
+                vfat.remove("/OLD.TXT")?;
+                vfat.create_dir("/DIR")?;
+                vfat.lock(|vfat| vfat.sync())
+            })();
+            drop(vfat);
+
+            match journal_test_state(&image) {
+                true => new += 1,
+                false => old += 1,
+            }
+            if result.is_ok() {
+                assert!(journal_test_state(&image));
+                break;
+            }
+        }
+        assert!(old > 0, "{:?}", fault);
+        // some cuts came after the transaction committed but before all of
+        // it was written in place, and were finished on mount
+        assert!(new > 1, "{:?}", fault);
+    }
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    expect_variant!(
+        vfat.remove("/JOURNAL.SYS"),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied
+    );
+    expect_variant!(
+        vfat.rename("/JOURNAL.SYS", "/OTHER.SYS"),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied
+    );
+    expect_variant!(
+        vfat.set_read_only("/JOURNAL.SYS", false),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied
+    );
+    let journal = vfat.open("/JOURNAL.SYS").expect("journal file");
+    let attributes = journal.metadata().attributes;
+    assert!(attributes.hidden() && attributes.system() && attributes.read_only());
+}
+
+/// The size of a file whose clusters span hundreds of sectors of each FAT.
+const SPANNING_FATS: u64 = 40_000 * 512;
+
+#[test]
+fn test_journal_splits_large_operations() {
+    let image = SharedImage::new(empty_fat32_image());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    vfat.lock(|vfat| vfat.create_journal(16))
+        .expect("create journal");
+    let free = vfat
+        .lock(|vfat| vfat.free_clusters())
+        .expect("free clusters");
+
+    // a write too large for the journal stops short and the rest follows in
+    // later writes
+    let mut big = vfat.create_file("/BIG.BIN").expect("create file");
+    let written = big.write(&pattern(1000 * 512)).expect("write file");
+    assert!(written > 0 && written < 1000 * 512, "{}", written);
+    big.write_all(&pattern(1000 * 512)[written..])
+        .expect("write file");
+    big.set_len(SPANNING_FATS).expect("extend file");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+    let mut big = vfat.open_file("/BIG.BIN").expect("big file");
+    assert_eq!(big.size(), SPANNING_FATS);
+    let mut start = vec![0; 1000 * 512];
+    big.read_exact(&mut start).expect("read file");
+    assert_eq!(start, pattern(1000 * 512));
+
+    // shrinking and removing free the clusters in several commits
+    big.set_len(700).expect("truncate file");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!(vfat.lock(|vfat| vfat.free_clusters()).unwrap(), free - 2);
+    let mut big = vfat.open_file("/BIG.BIN").expect("big file");
+    assert_eq!(read_all(&mut big), pattern(700));
+
+    big.set_len(SPANNING_FATS).expect("extend file");
+    vfat.remove("/BIG.BIN").expect("remove file");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!(vfat.lock(|vfat| vfat.free_clusters()).unwrap(), free);
+
+    // repairs are not limited by the journal's size
+    let copy = (1 + RESERVED + FAT_SECTORS) as usize * 512;
+    for cluster in 0..1000 {
+        poke(&image, copy + (100 + cluster) * 4, &[0xFF]);
+    }
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    assert!(!vfat.lock(|vfat| vfat.check()).expect("check").is_clean());
+    vfat.lock(|vfat| vfat.repair()).expect("repair");
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    assert!(vfat.lock(|vfat| vfat.has_journal()));
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!(fat_copy_entry(&image, 1, 100), 0);
+}
+
+#[test]
+fn test_journal_lists_more_sectors_than_its_header_holds() {
+    let image = SharedImage::new(empty_fat32_image());
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        vfat.lock(|vfat| vfat.create_journal(1024))
+            .expect("create journal");
+        vfat.create_file("/BIG.BIN")
+            .expect("create file")
+            .set_len(10_000 * 512)
+            .expect("extend file");
+        vfat.lock(|vfat| vfat.sync()).expect("sync");
+    }
+    let base = image.0.lock().unwrap().get_ref().clone();
+    fn remove<T: BlockDevice + 'static>(device: T) -> io::Result<()> {
+        let vfat = VFat::<StdVFatHandle>::from(device).unwrap();
+        vfat.remove("/BIG.BIN")?;
+        vfat.lock(|vfat| vfat.sync())
+    }
+
+    // the removal is one transaction, whose header is the last write before
+    // the first FAT sector is written in place
+    let (device, log) = traced(&image);
+    remove(device).expect("remove file");
+    let fat = (1 + RESERVED) as u64..(1 + RESERVED + 2 * FAT_SECTORS) as u64;
+    let mut logged = 0;
+    for access in log.lock().unwrap().iter() {
+        match *access {
+            Access::Write { start, .. } if fat.contains(&start) => break,
+            Access::Write { count, .. } => logged += count,
+            Access::Read { .. } => {}
+        }
+    }
+    assert!(logged > 512 / 8, "{}", logged);
+
+    // cut the power just before and just after the header is written
+    for (writes, removed) in [(logged - 1, false), (logged, true)] {
+        let image = SharedImage::new(base.clone());
+        let device = FailAfter::new(image.clone(), writes, Fault::Fail);
+        assert!(remove(device).is_err());
+
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        assert_eq!(vfat.open("/BIG.BIN").is_err(), removed);
+        let report = vfat.lock(|vfat| vfat.check()).expect("check");
+        assert!(report.is_clean(), "{:?}", report.problems);
+    }
+}
+
+#[test]
+fn test_read_only_mount() {
+    use vfat::{MountOptions, Timestamp};
+
+    let image = SharedImage::new(empty_fat32_image());
+    {
+        let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+        vfat.create_file("/FILE")
+            .expect("create file")
+            .write_all(&pattern(700))
+            .expect("write file");
+        vfat.create_dir("/DIR").expect("create dir");
+        vfat.lock(|vfat| vfat.sync()).expect("sync");
+    }
+    let before = image.0.lock().unwrap().get_ref().clone();
+
+    let options = MountOptions {
+        read_only: true,
+        ..Default::default()
+    };
+    let (device, log) = traced(&image);
+    let vfat = VFat::<StdVFatHandle>::from_with_options(device, &options).unwrap();
+    vfat.lock(|vfat| vfat.set_clock(|| Timestamp::from_unix(1_900_000_000)));
+    assert!(vfat.lock(|vfat| vfat.is_read_only()));
+
+    let denied = |result: io::Result<()>| expect_variant!(result, Err(e) if e.kind() == io::ErrorKind::PermissionDenied);
+    denied(vfat.create_file("/NEW").map(|_| ()));
+    denied(vfat.create_file("/FILE").map(|_| ()));
+    denied(vfat.create_dir("/DIR/SUB").map(|_| ()));
+    denied(vfat.remove("/FILE"));
+    denied(vfat.rename("/FILE", "/DIR/FILE"));
+    denied(vfat.set_read_only("/FILE", true));
+    denied(vfat.lock(|vfat| vfat.set_label(Some("BOOT"))));
+    denied(vfat.lock(|vfat| vfat.repair()).map(|_| ()));
+
+    let mut file = vfat.open_file("/FILE").expect("file exists");
+    denied(file.write(b"changed").map(|_| ()));
+    denied(file.set_len(0));
+    assert_eq!(read_all(&mut file), pattern(700));
+    vfat.lock(|vfat| vfat.sync()).expect("sync does nothing");
+    let report = vfat.lock(|vfat| vfat.check()).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+
+    assert_eq!(transfers(&log, true), []);
+    assert!(*image.0.lock().unwrap().get_ref() == before);
+
+    // reading a file without access time updates leaves its entry alone
+    let options = MountOptions {
+        no_access_time: true,
+        ..Default::default()
+    };
+    let vfat = VFat::<StdVFatHandle>::from_with_options(image.clone(), &options).unwrap();
+    vfat.lock(|vfat| vfat.set_clock(|| Timestamp::from_unix(1_900_000_000)));
+    let mut file = vfat.open_file("/FILE").expect("file exists");
+    assert_eq!(read_all(&mut file), pattern(700));
+    let accessed = vfat.open("/FILE").unwrap().metadata().accessed();
+    assert_eq!(accessed, Timestamp::default());
+    vfat.create_file("/NEW").expect("still writable");
+}
+
+#[test]
+fn test_dirty_volume_flag() {
+    const CLEAN: u32 = 0x0800_0000;
+
+    let image = SharedImage::new(empty_fat32_image());
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    assert!(!vfat.lock(|vfat| vfat.is_dirty()));
+
+    // the first change marks the volume dirty on disk, and syncing marks it
+    // clean again
+    let mut file = vfat.create_file("/FILE").expect("create file");
+    assert_eq!(fat_copy_entry(&image, 0, 1) & CLEAN, 0);
+    assert_eq!(fat_copy_entry(&image, 1, 1) & CLEAN, 0);
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+    assert_eq!(fat_copy_entry(&image, 0, 1), 0x0FFFFFFF);
+    assert_eq!(fat_copy_entry(&image, 1, 1), 0x0FFFFFFF);
+    file.write_all(&pattern(700)).expect("write file");
+    assert_eq!(fat_entry(&image, 1) & CLEAN, 0);
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+    assert_eq!(fat_entry(&image, 1), 0x0FFFFFFF);
+    drop(vfat);
+
+    // a volume left dirty stays dirty until it is repaired
+    let flag = (1 + RESERVED as usize) * 512 + 4;
+    poke(&image, flag, &0x07FFFFFFu32.to_le_bytes());
+    poke(
+        &image,
+        flag + FAT_SECTORS as usize * 512,
+        &0x07FFFFFFu32.to_le_bytes(),
+    );
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).unwrap();
+    assert!(vfat.lock(|vfat| vfat.is_dirty()));
+    vfat.create_dir("/DIR").expect("create dir");
+    vfat.lock(|vfat| vfat.sync()).expect("sync");
+    assert_eq!(fat_entry(&image, 1), 0x07FFFFFF);
+
+    let report = vfat.lock(|vfat| vfat.repair()).expect("repair");
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert!(!vfat.lock(|vfat| vfat.is_dirty()));
+    assert_eq!(fat_copy_entry(&image, 0, 1), 0x0FFFFFFF);
+    assert_eq!(fat_copy_entry(&image, 1, 1), 0x0FFFFFFF);
+}
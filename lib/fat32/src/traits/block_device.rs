@@ -1,4 +1,3 @@
-use alloc::boxed::Box;
 use alloc::vec::Vec;
 use shim::io;
 
@@ -32,17 +31,18 @@ pub trait BlockDevice: Send {
         let sector_size = self.sector_size() as usize;
 
         let start = vec.len();
-        vec.reserve(sector_size);
+        vec.resize(start + sector_size, 0);
 
-        unsafe {
-            vec.set_len(start + sector_size);
+        match self.read_sector(n, &mut vec[start..]) {
+            Ok(read) => {
+                vec.truncate(start + read);
+                Ok(read)
+            }
+            Err(e) => {
+                vec.truncate(start);
+                Err(e)
+            }
         }
-        // XXX. handle: clean-up dirty data when failed
-        let read = self.read_sector(n, &mut vec[start..])?;
-        unsafe {
-            vec.set_len(start + read);
-        }
-        Ok(read)
     }
 
     /// Overwrites sector `n` with the contents of `buf`.
@@ -56,9 +56,63 @@ pub trait BlockDevice: Send {
     /// error of `UnexpectedEof` if the length of `buf` is less than
     /// `self.sector_size()`.
     fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize>;
+
+    /// Reads `count` consecutive sectors starting at sector `start` into
+    /// `buf`. The number of bytes read is returned.
+    ///
+    /// The default implementation calls `read_sector` once per sector.
+    /// Devices that can transfer several sectors at once should override it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `buf` is shorter than `count`
+    /// sectors. Returns an error if seeking or reading from `self` fails.
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size() as usize;
+        let len = check_sectors_len(count, sector_size, buf.len())?;
+        for (i, chunk) in buf[..len].chunks_mut(sector_size).enumerate() {
+            self.read_sector(start + i as u64, chunk)?;
+        }
+        Ok(len)
+    }
+
+    /// Overwrites the `count` consecutive sectors starting at sector `start`
+    /// with the contents of `buf`. The number of bytes written is returned.
+    ///
+    /// The default implementation calls `write_sector` once per sector.
+    /// Devices that can transfer several sectors at once should override it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `buf` is shorter than `count`
+    /// sectors. Returns an error if seeking or writing to `self` fails.
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size() as usize;
+        let len = check_sectors_len(count, sector_size, buf.len())?;
+        for (i, chunk) in buf[..len].chunks(sector_size).enumerate() {
+            self.write_sector(start + i as u64, chunk)?;
+        }
+        Ok(len)
+    }
 }
 
-impl<'a, T: BlockDevice> BlockDevice for &'a mut T {
+/// Returns the number of bytes in `count` sectors of `sector_size` bytes, or
+/// an error if a buffer of `buf_len` bytes can't hold them.
+fn check_sectors_len(count: u64, sector_size: usize, buf_len: usize) -> io::Result<usize> {
+    match (count as usize).checked_mul(sector_size) {
+        Some(len) if len <= buf_len => Ok(len),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "buffer is too short for the sectors",
+        )),
+    }
+}
+
+impl<T: BlockDevice> BlockDevice for &mut T {
+    fn sector_size(&self) -> u64 {
+        (**self).sector_size()
+    }
+
     fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
         (*self).read_sector(n, buf)
     }
@@ -66,32 +120,56 @@ impl<'a, T: BlockDevice> BlockDevice for &'a mut T {
     fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
         (*self).write_sector(n, buf)
     }
+
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (*self).read_sectors(start, count, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        (*self).write_sectors(start, count, buf)
+    }
 }
 
 macro impl_for_read_write_seek($(<$($gen:tt),*>)* $T:path) {
-    use shim::io::{Read, Write, Seek};
-
     impl $(<$($gen),*>)* BlockDevice for $T {
         fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
             let sector_size = self.sector_size();
             let to_read = ::core::cmp::min(sector_size as usize, buf.len());
-            self.seek(io::SeekFrom::Start(n * sector_size))?;
-            self.read_exact(&mut buf[..to_read])?;
+            io::Seek::seek(self, io::SeekFrom::Start(n * sector_size))?;
+            io::Read::read_exact(self, &mut buf[..to_read])?;
             Ok(to_read)
         }
 
         fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
             let sector_size = self.sector_size();
             let to_write = ::core::cmp::min(sector_size as usize, buf.len());
-            self.seek(io::SeekFrom::Start(n * sector_size))?;
-            self.write_all(&buf[..to_write])?;
+            io::Seek::seek(self, io::SeekFrom::Start(n * sector_size))?;
+            io::Write::write_all(self, &buf[..to_write])?;
             Ok(to_write)
         }
+
+        fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+            let sector_size = self.sector_size();
+            let len = check_sectors_len(count, sector_size as usize, buf.len())?;
+            io::Seek::seek(self, io::SeekFrom::Start(start * sector_size))?;
+            io::Read::read_exact(self, &mut buf[..len])?;
+            Ok(len)
+        }
+
+        fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+            let sector_size = self.sector_size();
+            let len = check_sectors_len(count, sector_size as usize, buf.len())?;
+            io::Seek::seek(self, io::SeekFrom::Start(start * sector_size))?;
+            io::Write::write_all(self, &buf[..len])?;
+            Ok(len)
+        }
     }
 }
 
 impl_for_read_write_seek!(<'a> shim::io::Cursor<&'a mut [u8]>);
+#[cfg(not(feature = "no_std"))]
 impl_for_read_write_seek!(shim::io::Cursor<Vec<u8>>);
-impl_for_read_write_seek!(shim::io::Cursor<Box<[u8]>>);
-#[cfg(test)]
+#[cfg(not(feature = "no_std"))]
+impl_for_read_write_seek!(shim::io::Cursor<alloc::boxed::Box<[u8]>>);
+#[cfg(not(feature = "no_std"))]
 impl_for_read_write_seek!(::std::fs::File);
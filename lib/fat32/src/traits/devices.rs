@@ -0,0 +1,194 @@
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use shim::io;
+use shim::ioerr;
+
+use crate::traits::BlockDevice;
+
+/// An access made through a [`Tracing`] device. Multi-sector transfers are
+/// a single access.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    /// `count` sectors were read starting at sector `start`.
+    Read { start: u64, count: u64 },
+    /// `count` sectors were written starting at sector `start`.
+    Write { start: u64, count: u64 },
+}
+
+/// A device that reports every access to the device it wraps to a callback
+/// before making it, so that access patterns can be checked.
+pub struct Tracing<T, F> {
+    device: T,
+    trace: F,
+}
+
+impl<T: BlockDevice, F: FnMut(Access) + Send> Tracing<T, F> {
+    /// Wraps `device`, calling `trace` with every access made to it.
+    pub fn new(device: T, trace: F) -> Tracing<T, F> {
+        Tracing { device, trace }
+    }
+}
+
+impl<T: BlockDevice, F: FnMut(Access) + Send> BlockDevice for Tracing<T, F> {
+    fn sector_size(&self) -> u64 {
+        self.device.sector_size()
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (self.trace)(Access::Read { start: n, count: 1 });
+        self.device.read_sector(n, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        (self.trace)(Access::Write { start: n, count: 1 });
+        self.device.write_sector(n, buf)
+    }
+
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (self.trace)(Access::Read { start, count });
+        self.device.read_sectors(start, count, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        (self.trace)(Access::Write { start, count });
+        self.device.write_sectors(start, count, buf)
+    }
+}
+
+/// What happens to the write that a [`FailAfter`] device fails.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The sector is left as it was.
+    Fail,
+    /// Only the first `len` bytes of the sector are written, as when power
+    /// is lost partway through a write.
+    Tear(usize),
+}
+
+/// A device that writes `writes` sectors to the device it wraps and then
+/// fails, as if its power were cut. The write that runs past the limit is
+/// treated as `fault` describes and fails, as does every write after it.
+/// Reads keep working so that what reached the device can be inspected.
+///
+/// Multi-sector writes count once per sector and are passed on whole unless
+/// they run past the limit, in which case the sectors before it are written.
+pub struct FailAfter<T> {
+    device: T,
+    writes: u64,
+    fault: Fault,
+    failed: bool,
+}
+
+impl<T: BlockDevice> FailAfter<T> {
+    /// Wraps `device`, failing after `writes` sectors have been written.
+    pub fn new(device: T, writes: u64, fault: Fault) -> FailAfter<T> {
+        FailAfter {
+            device,
+            writes,
+            fault,
+            failed: false,
+        }
+    }
+}
+
+impl<T: BlockDevice> BlockDevice for FailAfter<T> {
+    fn sector_size(&self) -> u64 {
+        self.device.sector_size()
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.device.read_sector(n, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        if self.failed {
+            return ioerr!(Other, "device has failed");
+        }
+        if self.writes > 0 {
+            self.writes -= 1;
+            return self.device.write_sector(n, buf);
+        }
+
+        self.failed = true;
+        if let Fault::Tear(len) = self.fault {
+            let mut sector = Vec::new();
+            self.device.read_all_sector(n, &mut sector)?;
+            let len = len.min(buf.len()).min(sector.len());
+            sector[..len].copy_from_slice(&buf[..len]);
+            self.device.write_sector(n, &sector)?;
+        }
+        ioerr!(Other, "device failed while writing")
+    }
+
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.device.read_sectors(start, count, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        if !self.failed && self.writes >= count {
+            self.writes -= count;
+            return self.device.write_sectors(start, count, buf);
+        }
+
+        // the sectors before the limit are written one at a time, and the
+        // write that reaches it fails
+        let sector_size = self.sector_size() as usize;
+        for (i, chunk) in buf.chunks(sector_size).take(count as usize).enumerate() {
+            self.write_sector(start + i as u64, chunk)?;
+        }
+        ioerr!(Other, "device has failed")
+    }
+}
+
+/// A device whose `bad` sectors cannot be read or written, like a disk with
+/// failing sectors. A multi-sector transfer that includes a bad sector fails
+/// as a whole.
+pub struct BadSectors<T> {
+    device: T,
+    bad: BTreeSet<u64>,
+}
+
+impl<T: BlockDevice> BadSectors<T> {
+    /// Wraps `device`, failing every access to a sector in `bad`.
+    pub fn new<I: IntoIterator<Item = u64>>(device: T, bad: I) -> BadSectors<T> {
+        BadSectors {
+            device,
+            bad: bad.into_iter().collect(),
+        }
+    }
+
+    /// Returns an error if any of the `count` sectors starting at `start` is
+    /// bad.
+    fn check(&self, start: u64, count: u64) -> io::Result<()> {
+        match self.bad.range(start..start.saturating_add(count)).next() {
+            Some(_) => ioerr!(Other, "bad sector"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: BlockDevice> BlockDevice for BadSectors<T> {
+    fn sector_size(&self) -> u64 {
+        self.device.sector_size()
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.check(n, 1)?;
+        self.device.read_sector(n, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        self.check(n, 1)?;
+        self.device.write_sector(n, buf)
+    }
+
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.check(start, count)?;
+        self.device.read_sectors(start, count, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        self.check(start, count)?;
+        self.device.write_sectors(start, count, buf)
+    }
+}
@@ -34,6 +34,9 @@ impl File for Dummy {
     fn size(&self) -> u64 {
         panic!("Dummy")
     }
+    fn set_len(&mut self, _size: u64) -> io::Result<()> {
+        panic!("Dummy")
+    }
 }
 
 /// Trait implemented by directories in a file system.
@@ -1,5 +1,8 @@
-use shim::{io, path::Path};
+use shim::io;
+use shim::newioerr;
+use shim::path::{Path, PathBuf};
 
+use crate::normalize;
 use crate::traits::Metadata;
 
 /// Trait implemented by files in the file system.
@@ -9,6 +12,10 @@ pub trait File: io::Read + io::Write + io::Seek + Sized {
 
     /// Returns the size of the file in bytes.
     fn size(&self) -> u64;
+
+    /// Truncates or extends the file so that it is `size` bytes long.
+    /// Extending a file fills the new space with zeroes.
+    fn set_len(&mut self, size: u64) -> io::Result<()>;
 }
 
 /// Trait implemented by directories in a file system.
@@ -76,7 +83,8 @@ pub trait FileSystem: Sized {
     /// The type of directory entries in this file system.
     type Entry: Entry<File = Self::File, Dir = Self::Dir>;
 
-    /// Opens the entry at `path`. `path` must be absolute.
+    /// Opens the entry at `path`. `path` must be absolute. It is normalized
+    /// first, so it may contain `.`, `..` and repeated separators.
     ///
     /// # Errors
     ///
@@ -90,6 +98,19 @@ pub trait FileSystem: Sized {
     /// All other error values are implementation defined.
     fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry>;
 
+    /// Returns the normalized form of the absolute `path` after checking that
+    /// an entry exists there. See [`normalize`] for how `.` and `..` are
+    /// resolved.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the same errors as `open()`.
+    fn canonicalize<P: AsRef<Path>>(self, path: P) -> io::Result<PathBuf> {
+        let path = normalize(path);
+        self.open(&path)?;
+        Ok(path)
+    }
+
     /// Opens the file at `path`. `path` must be absolute.
     ///
     /// # Errors
@@ -99,7 +120,7 @@ pub trait FileSystem: Sized {
     fn open_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
         self.open(path)?
             .into_file()
-            .ok_or(io::Error::new(io::ErrorKind::Other, "not a regular file"))
+            .ok_or_else(|| newioerr!(Other, "not a regular file"))
     }
 
     /// Opens the directory at `path`. `path` must be absolute.
@@ -111,6 +132,66 @@ pub trait FileSystem: Sized {
     fn open_dir<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Dir> {
         self.open(path)?
             .into_dir()
-            .ok_or(io::Error::new(io::ErrorKind::Other, "not a directory"))
+            .ok_or_else(|| newioerr!(Other, "not a directory"))
     }
+
+    /// Creates a new, empty file at `path` and returns it. `path` must be
+    /// absolute.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the error conditions for `open()` on the parent of
+    /// `path`, this method returns an error kind of `AlreadyExists` if an
+    /// entry already exists at `path`.
+    ///
+    /// All other error values are implementation defined.
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File>;
+
+    /// Creates a new, empty directory at `path` and returns it. `path` must be
+    /// absolute.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the error conditions for `open()` on the parent of
+    /// `path`, this method returns an error kind of `AlreadyExists` if an
+    /// entry already exists at `path`.
+    ///
+    /// All other error values are implementation defined.
+    fn create_dir<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Dir>;
+
+    /// Removes the file or empty directory at `path`. `path` must be absolute.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the error conditions for `open()`, this method returns
+    /// an error if `path` refers to a directory that is not empty.
+    ///
+    /// All other error values are implementation defined.
+    fn remove<P: AsRef<Path>>(self, path: P) -> io::Result<()>;
+
+    /// Moves the entry at `from` to `to`, which may be in a different
+    /// directory. Both paths must be absolute.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the error conditions for `open()` on `from` and on the
+    /// parent of `to`, this method returns an error kind of `AlreadyExists` if
+    /// a different entry already exists at `to`, and an error kind of
+    /// `InvalidInput` if `from` is a directory and `to` is inside of it.
+    ///
+    /// All other error values are implementation defined.
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, from: P, to: Q) -> io::Result<()>;
+
+    /// Marks the entry at `path` as read only, or as writable if `read_only`
+    /// is `false`. `path` must be absolute. Writing to or truncating a read
+    /// only file fails with an error kind of `PermissionDenied`.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the error conditions for `open()`, this method returns
+    /// an error kind of `InvalidInput` if the entry's attributes cannot be
+    /// changed, such as those of the root directory.
+    ///
+    /// All other error values are implementation defined.
+    fn set_read_only<P: AsRef<Path>>(self, path: P, read_only: bool) -> io::Result<()>;
 }
@@ -1,9 +1,11 @@
 mod block_device;
+mod devices;
 mod dummy;
 mod fs;
 mod metadata;
 
 pub use self::block_device::BlockDevice;
+pub use self::devices::{Access, BadSectors, FailAfter, Fault, Tracing};
 pub use self::dummy::Dummy;
 pub use self::fs::{Dir, Entry, File, FileSystem};
 pub use self::metadata::{Metadata, Timestamp};
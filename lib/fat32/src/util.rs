@@ -2,6 +2,7 @@ use alloc::vec::Vec;
 use core::mem::{align_of, forget, size_of};
 use core::slice::{from_raw_parts, from_raw_parts_mut};
 
+#[allow(dead_code)]
 pub trait VecExt {
     /// Casts a `Vec<T>` into a `Vec<U>`.
     ///
@@ -20,6 +21,7 @@ pub trait VecExt {
     unsafe fn cast<U>(self) -> Vec<U>;
 }
 
+#[allow(dead_code)]
 pub trait SliceExt {
     /// Casts an `&[T]` into an `&[U]`.
     ///
@@ -35,7 +37,7 @@ pub trait SliceExt {
     ///
     /// Panics if the size of `T` and `U` differ or if the alignment of `T` is
     /// not an integer multiple of `U`.
-    unsafe fn cast<'a, U>(&'a self) -> &'a [U];
+    unsafe fn cast<U>(&self) -> &[U];
 
     /// Casts an `&mut [T]` into an `&mut [U]`.
     ///
@@ -51,16 +53,17 @@ pub trait SliceExt {
     ///
     /// Panics if the size of `T` and `U` differ or if the alignment of `T` is
     /// not an integer multiple of `U`.
-    unsafe fn cast_mut<'a, U>(&'a mut self) -> &'a mut [U];
+    unsafe fn cast_mut<U>(&mut self) -> &mut [U];
 }
 
+#[allow(dead_code)]
 fn calc_new_len_cap<T, U>(vec: &Vec<T>) -> (usize, usize) {
     if size_of::<T>() > size_of::<U>() {
-        assert!(size_of::<T>() % size_of::<U>() == 0);
+        assert!(size_of::<T>().is_multiple_of(size_of::<U>()));
         let factor = size_of::<T>() / size_of::<U>();
         (vec.len() * factor, vec.capacity() * factor)
     } else if size_of::<U>() > size_of::<T>() {
-        assert!(size_of::<U>() % size_of::<T>() == 0);
+        assert!(size_of::<U>().is_multiple_of(size_of::<T>()));
         let factor = size_of::<U>() / size_of::<T>();
         (vec.len() / factor, vec.capacity() / factor)
     } else {
@@ -80,13 +83,14 @@ impl<T> VecExt for Vec<T> {
     }
 }
 
+#[allow(dead_code)]
 fn calc_new_len<T, U>(slice: &[T]) -> usize {
     if size_of::<T>() > size_of::<U>() {
-        assert!(size_of::<T>() % size_of::<U>() == 0);
+        assert!(size_of::<T>().is_multiple_of(size_of::<U>()));
         let factor = size_of::<T>() / size_of::<U>();
         slice.len() * factor
     } else if size_of::<U>() > size_of::<T>() {
-        assert!(size_of::<U>() % size_of::<T>() == 0);
+        assert!(size_of::<U>().is_multiple_of(size_of::<T>()));
         let factor = size_of::<U>() / size_of::<T>();
         slice.len() / factor
     } else {
@@ -95,19 +99,32 @@ fn calc_new_len<T, U>(slice: &[T]) -> usize {
 }
 
 impl<T> SliceExt for [T] {
-    unsafe fn cast<'a, U>(&'a self) -> &'a [U] {
-        assert!(align_of::<T>() % align_of::<U>() == 0);
+    unsafe fn cast<U>(&self) -> &[U] {
+        assert!(align_of::<T>().is_multiple_of(align_of::<U>()));
 
         let new_len = calc_new_len::<T, U>(self);
         let new_ptr = self.as_ptr() as *const U;
         from_raw_parts(new_ptr, new_len)
     }
 
-    unsafe fn cast_mut<'a, U>(&'a mut self) -> &'a mut [U] {
-        assert!(align_of::<T>() % align_of::<U>() == 0);
+    unsafe fn cast_mut<U>(&mut self) -> &mut [U] {
+        assert!(align_of::<T>().is_multiple_of(align_of::<U>()));
 
         let new_len = calc_new_len::<T, U>(self);
         let new_ptr = self.as_mut_ptr() as *mut U;
         from_raw_parts_mut(new_ptr, new_len)
     }
 }
+
+/// The CRC-32 (IEEE 802.3) checksum of `bytes`, as used by GPT.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
@@ -1,8 +1,11 @@
 use alloc::boxed::Box;
 use alloc::vec::Vec;
+use core::cmp::min;
 use core::fmt;
 use hashbrown::HashMap;
 use shim::io;
+use shim::ioerr;
+use shim::newioerr;
 
 use crate::traits::BlockDevice;
 
@@ -10,8 +13,54 @@ use crate::traits::BlockDevice;
 struct CacheEntry {
     data: Vec<u8>,
     dirty: bool,
+    /// Set whenever the sector is accessed and cleared as the clock hand
+    /// passes. Sectors are evicted when the hand finds this clear.
+    referenced: bool,
+    /// Whether the sector is held back from the disk until the journal
+    /// commits it. Pinned sectors are never evicted.
+    pinned: bool,
 }
 
+/// Counters describing how well the cache is doing.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Accesses to sectors that were already cached.
+    pub hits: u64,
+    /// Accesses that had to read the sector from the disk.
+    pub misses: u64,
+    /// Sectors dropped from the cache to make room for others.
+    pub evictions: u64,
+    /// Dirty sectors written back to the disk.
+    pub write_backs: u64,
+}
+
+impl CacheStats {
+    /// The fraction of accesses that were hits, or 0 if there were none.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses ({:.1}% hit rate), {} evictions, {} write-backs",
+            self.hits,
+            self.misses,
+            self.hit_rate() * 100.0,
+            self.evictions,
+            self.write_backs
+        )
+    }
+}
+
+/// The number of sectors cached by default.
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
 pub struct Partition {
     /// The physical sector where the partition begins.
     pub start: u64,
@@ -25,6 +74,15 @@ pub struct CachedPartition {
     device: Box<dyn BlockDevice>,
     cache: HashMap<u64, CacheEntry>,
     partition: Partition,
+    /// The most sectors that are cached at once.
+    capacity: usize,
+    /// The cached sectors in the order the clock hand visits them.
+    clock: Vec<u64>,
+    /// The index into `clock` of the next sector considered for eviction.
+    hand: usize,
+    /// The number of pinned sectors.
+    pinned: usize,
+    stats: CacheStats,
 }
 
 impl CachedPartition {
@@ -41,22 +99,74 @@ impl CachedPartition {
     /// `partition.sector_size` must be an integer multiple of
     /// `device.sector_size()`.
     ///
+    /// At most `DEFAULT_CACHE_CAPACITY` sectors are cached at once.
+    ///
     /// # Panics
     ///
     /// Panics if the partition's sector size is < the device's sector size.
     pub fn new<T>(device: T, partition: Partition) -> CachedPartition
+    where
+        T: BlockDevice + 'static,
+    {
+        CachedPartition::with_capacity(device, partition, DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Like `new`, but caches at most `capacity` sectors at once. When the
+    /// cache is full, a sector that has not been used recently is evicted,
+    /// and written back first if it is dirty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the partition's sector size is < the device's sector size or
+    /// if `capacity` is 0.
+    pub fn with_capacity<T>(device: T, partition: Partition, capacity: usize) -> CachedPartition
     where
         T: BlockDevice + 'static,
     {
         assert!(partition.sector_size >= device.sector_size());
+        assert!(capacity > 0, "cache capacity must be at least one sector");
 
         CachedPartition {
             device: Box::new(device),
             cache: HashMap::new(),
-            partition: partition,
+            partition,
+            capacity,
+            clock: Vec::with_capacity(capacity),
+            hand: 0,
+            pinned: 0,
+            stats: CacheStats::default(),
         }
     }
 
+    /// Changes the most sectors that are cached at once, evicting sectors
+    /// until the cache fits or only pinned sectors are left.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing back an evicted sector fails. The capacity
+    /// is changed regardless.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn set_capacity(&mut self, capacity: usize) -> io::Result<()> {
+        assert!(capacity > 0, "cache capacity must be at least one sector");
+        self.capacity = capacity;
+        while self.cache.len() > self.capacity && self.evict()? {}
+        Ok(())
+    }
+
+    /// Hit, miss, eviction and write-back counts since the cache was created
+    /// or the counts were last reset.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Resets every count in `stats()` to 0.
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
     /// Returns the number of physical sectors that corresponds to
     /// one logical sector.
     fn factor(&self) -> u64 {
@@ -87,7 +197,68 @@ impl CachedPartition {
     ///
     /// Returns an error if there is an error reading the sector from the disk.
     pub fn get_mut(&mut self, sector: u64) -> io::Result<&mut [u8]> {
-        unimplemented!("CachedPartition::get_mut()")
+        let entry = self.load(sector)?;
+        entry.dirty = true;
+        Ok(&mut entry.data)
+    }
+
+    /// Like `get_mut()`, but also pins the sector: it is not written back by
+    /// `flush()` or evicted until it is passed to `write_back_pinned()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is an error reading the sector from the disk.
+    pub fn get_pinned_mut(&mut self, sector: u64) -> io::Result<&mut [u8]> {
+        let entry = self.load(sector)?;
+        entry.dirty = true;
+        if !entry.pinned {
+            entry.pinned = true;
+            self.pinned += 1;
+        }
+        Ok(&mut self.cache.get_mut(&sector).unwrap().data)
+    }
+
+    /// Whether `sector` is cached and pinned.
+    pub fn is_pinned(&self, sector: u64) -> bool {
+        self.cache.get(&sector).is_some_and(|entry| entry.pinned)
+    }
+
+    /// The number of pinned sectors.
+    pub fn pinned_count(&self) -> usize {
+        self.pinned
+    }
+
+    /// The pinned sectors, in ascending order.
+    pub fn pinned_sectors(&self) -> Vec<u64> {
+        let mut sectors: Vec<u64> = self
+            .cache
+            .iter()
+            .filter(|(_, entry)| entry.pinned)
+            .map(|(&sector, _)| sector)
+            .collect();
+        sectors.sort_unstable();
+        sectors
+    }
+
+    /// Writes the pinned sectors in `sectors` back to the disk and unpins
+    /// them, then evicts sectors until the cache fits in its capacity again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is an error writing a sector to the disk.
+    /// Sectors that were not written stay pinned.
+    pub fn write_back_pinned(&mut self, sectors: &[u64]) -> io::Result<()> {
+        for &sector in sectors {
+            self.write_back(sector)?;
+            if let Some(entry) = self.cache.get_mut(&sector) {
+                if entry.pinned {
+                    entry.pinned = false;
+                    self.pinned -= 1;
+                }
+            }
+        }
+        while self.cache.len() > self.capacity && self.evict()? {}
+        Ok(())
     }
 
     /// Returns a reference to the cached sector `sector`. If the sector is not
@@ -97,23 +268,221 @@ impl CachedPartition {
     ///
     /// Returns an error if there is an error reading the sector from the disk.
     pub fn get(&mut self, sector: u64) -> io::Result<&[u8]> {
-        unimplemented!("CachedPartition::get()")
+        Ok(&self.load(sector)?.data)
+    }
+
+    /// Returns the number of bytes in the `count` sectors starting at `start`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if any of the sectors is out of
+    /// range or if they don't fit in `buf_len` bytes.
+    fn check_run(&self, start: u64, count: u64, buf_len: usize) -> io::Result<usize> {
+        if start.saturating_add(count) > self.partition.num_sectors {
+            return ioerr!(InvalidInput, "sector out of range");
+        }
+
+        let len = count as usize * self.partition.sector_size as usize;
+        if len > buf_len {
+            return ioerr!(InvalidInput, "buffer is too short for the sectors");
+        }
+        Ok(len)
+    }
+
+    /// Writes every dirty sector in the cache back to the disk, except for
+    /// pinned sectors. Sectors stay cached but are no longer dirty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is an error writing a sector to the disk.
+    /// Sectors that were not written stay dirty.
+    pub fn flush(&mut self) -> io::Result<()> {
+        for i in 0..self.clock.len() {
+            let sector = self.clock[i];
+            if !self.cache[&sector].pinned {
+                self.write_back(sector)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Does what `flush()` does, then empties the cache of everything but
+    /// pinned sectors so that later accesses read the disk again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is an error writing a sector to the disk, in
+    /// which case nothing is dropped from the cache.
+    pub fn sync_all(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.cache.retain(|_, entry| entry.pinned);
+        let cache = &self.cache;
+        self.clock.retain(|sector| cache.contains_key(sector));
+        self.hand = 0;
+        Ok(())
+    }
+
+    /// Writes `sector` back to the disk if it is cached and dirty.
+    fn write_back(&mut self, sector: u64) -> io::Result<()> {
+        let physical = self.partition.start + sector * self.factor();
+        let device_sector_size = self.device.sector_size() as usize;
+        let entry = match self.cache.get_mut(&sector) {
+            Some(entry) if entry.dirty => entry,
+            _ => return Ok(()),
+        };
+
+        for (i, chunk) in entry.data.chunks(device_sector_size).enumerate() {
+            self.device.write_sector(physical + i as u64, chunk)?;
+        }
+
+        entry.dirty = false;
+        self.stats.write_backs += 1;
+        Ok(())
+    }
+
+    /// Evicts one sector using the CLOCK algorithm: sectors that were used
+    /// since the hand last passed get a second chance, and pinned sectors are
+    /// skipped. Returns `false` if every cached sector is pinned, in which
+    /// case nothing is evicted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sector chosen is dirty and writing it back
+    /// fails. The sector stays cached in that case.
+    fn evict(&mut self) -> io::Result<bool> {
+        // after two passes every unpinned sector has lost its second chance
+        for _ in 0..2 * self.clock.len() + 1 {
+            if self.hand >= self.clock.len() {
+                self.hand = 0;
+            }
+
+            let sector = self.clock[self.hand];
+            let entry = self.cache.get_mut(&sector).expect("clock sector is cached");
+            if entry.pinned || entry.referenced {
+                entry.referenced = false;
+                self.hand += 1;
+                continue;
+            }
+
+            self.write_back(sector)?;
+            self.cache.remove(&sector);
+            // the sector now at `hand` is the next one to visit
+            self.clock.swap_remove(self.hand);
+            self.stats.evictions += 1;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Returns the cache entry for `sector`, reading it from the disk first if
+    /// it is not already cached.
+    fn load(&mut self, sector: u64) -> io::Result<&mut CacheEntry> {
+        if self.cache.contains_key(&sector) {
+            self.stats.hits += 1;
+        } else {
+            let physical = self
+                .virtual_to_physical(sector)
+                .ok_or_else(|| newioerr!(InvalidInput, "sector out of range"))?;
+
+            let mut data = Vec::with_capacity(self.partition.sector_size as usize);
+            for i in 0..self.factor() {
+                self.device.read_all_sector(physical + i, &mut data)?;
+            }
+
+            while self.cache.len() >= self.capacity && self.evict()? {}
+
+            self.stats.misses += 1;
+            self.clock.push(sector);
+            self.cache.insert(
+                sector,
+                CacheEntry {
+                    data,
+                    dirty: false,
+                    referenced: false,
+                    pinned: false,
+                },
+            );
+        }
+
+        let entry = self.cache.get_mut(&sector).unwrap();
+        entry.referenced = true;
+        Ok(entry)
     }
 }
 
-// FIXME: Implement `BlockDevice` for `CacheDevice`. The `read_sector` and
-// `write_sector` methods should only read/write from/to cached sectors.
 impl BlockDevice for CachedPartition {
     fn sector_size(&self) -> u64 {
-        unimplemented!()
+        self.partition.sector_size
     }
 
     fn read_sector(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<usize> {
-        unimplemented!()
+        let data = self.get(sector)?;
+        let len = min(data.len(), buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len)
     }
 
     fn write_sector(&mut self, sector: u64, buf: &[u8]) -> io::Result<usize> {
-        unimplemented!()
+        let data = self.get_mut(sector)?;
+        let len = min(data.len(), buf.len());
+        data[..len].copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    /// Reads cached sectors from the cache and every run of uncached sectors
+    /// with a single read from the device. Sectors read from the device are
+    /// not added to the cache.
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.check_run(start, count, buf.len())?;
+        let sector_size = self.partition.sector_size as usize;
+        let end = start + count;
+
+        let mut sector = start;
+        while sector < end {
+            let offset = (sector - start) as usize * sector_size;
+            if let Some(entry) = self.cache.get_mut(&sector) {
+                entry.referenced = true;
+                buf[offset..offset + sector_size].copy_from_slice(&entry.data);
+                self.stats.hits += 1;
+                sector += 1;
+                continue;
+            }
+
+            let mut run_end = sector + 1;
+            while run_end < end && !self.cache.contains_key(&run_end) {
+                run_end += 1;
+            }
+
+            let run = run_end - sector;
+            let physical = self.partition.start + sector * self.factor();
+            let run_buf = &mut buf[offset..offset + run as usize * sector_size];
+            self.device
+                .read_sectors(physical, run * self.factor(), run_buf)?;
+            self.stats.misses += run;
+            sector = run_end;
+        }
+
+        Ok(len)
+    }
+
+    /// Writes the sectors to the device with a single write. Cached copies of
+    /// the sectors are updated and are no longer dirty; other sectors are not
+    /// added to the cache.
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        let len = self.check_run(start, count, buf.len())?;
+        let physical = self.partition.start + start * self.factor();
+        self.device
+            .write_sectors(physical, count * self.factor(), &buf[..len])?;
+
+        let sector_size = self.partition.sector_size as usize;
+        for (i, data) in buf[..len].chunks(sector_size).enumerate() {
+            if let Some(entry) = self.cache.get_mut(&(start + i as u64)) {
+                entry.data.copy_from_slice(data);
+                entry.dirty = false;
+            }
+        }
+
+        Ok(len)
     }
 }
 
@@ -121,7 +490,9 @@ impl fmt::Debug for CachedPartition {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("CachedPartition")
             .field("device", &"<block device>")
-            .field("cache", &self.cache)
+            .field("capacity", &self.capacity)
+            .field("cached", &self.cache.len())
+            .field("stats", &self.stats)
             .finish()
     }
 }
@@ -0,0 +1,593 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use shim::io;
+
+use crate::vfat::dir::{decode_lfn, VFatDirEntry, VFatRegularDirEntry};
+use crate::vfat::dir::{DELETED_ENTRY, END_OF_ENTRIES, ENTRY_SIZE};
+use crate::vfat::name;
+use crate::vfat::{Cluster, Status, VFat, VFatHandle};
+
+/// A problem found by [`VFat::check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// A chain of `len` clusters starting at cluster `start` is marked as in
+    /// use but belongs to no file or directory.
+    LostChain { start: u32, len: u32 },
+    /// The chain of `path` runs into `cluster`, which already belongs to
+    /// `other`.
+    CrossLinked {
+        path: String,
+        other: String,
+        cluster: u32,
+    },
+    /// The chain of `path` runs into `cluster`, which is free, reserved, bad,
+    /// out of range or already earlier in the chain.
+    BadChain { path: String, cluster: u32 },
+    /// The file `path` is `size` bytes long but its chain holds `clusters`
+    /// clusters.
+    SizeMismatch {
+        path: String,
+        size: u32,
+        clusters: u32,
+    },
+    /// `sectors` sectors of FAT number `copy` differ from the active FAT.
+    FatMismatch { copy: u8, sectors: u32 },
+    /// The long file name entries starting `offset` bytes into the directory
+    /// `dir` do not form a valid name for the entry after them.
+    InvalidLfn { dir: String, offset: u64 },
+    /// The FSInfo sector records `recorded` free clusters but `actual` are
+    /// free.
+    FsInfoFreeCount { recorded: u32, actual: u32 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::LostChain { start, len } => {
+                write!(f, "{} lost cluster(s) starting at cluster {}", len, start)
+            }
+            Problem::CrossLinked {
+                path,
+                other,
+                cluster,
+            } => write!(
+                f,
+                "{} is cross-linked with {} at cluster {}",
+                path, other, cluster
+            ),
+            Problem::BadChain { path, cluster } => {
+                write!(
+                    f,
+                    "{} has an invalid cluster {} in its chain",
+                    path, cluster
+                )
+            }
+            Problem::SizeMismatch {
+                path,
+                size,
+                clusters,
+            } => write!(
+                f,
+                "{} is {} bytes but has {} cluster(s)",
+                path, size, clusters
+            ),
+            Problem::FatMismatch { copy, sectors } => write!(
+                f,
+                "{} sector(s) of FAT {} differ from the active FAT",
+                sectors, copy
+            ),
+            Problem::InvalidLfn { dir, offset } => write!(
+                f,
+                "invalid long file name entries at offset {} of {}",
+                offset, dir
+            ),
+            Problem::FsInfoFreeCount { recorded, actual } => write!(
+                f,
+                "FSInfo records {} free cluster(s) but {} are free",
+                recorded, actual
+            ),
+        }
+    }
+}
+
+/// The result of [`VFat::check`] or [`VFat::repair`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CheckReport {
+    /// Every problem found, in the order they were found.
+    pub problems: Vec<Problem>,
+    /// Whether the problems were repaired.
+    pub repaired: bool,
+    /// The number of files visited.
+    pub files: u32,
+    /// The number of directories visited, including the root directory.
+    pub dirs: u32,
+}
+
+impl CheckReport {
+    /// Returns `true` if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// What was found when trying to add a cluster to a chain.
+enum Claim {
+    /// The cluster now belongs to the chain and is followed by the cluster
+    /// given, if any.
+    Claimed(Option<Cluster>),
+    /// The cluster already belongs to the owner given.
+    Owned(u32),
+    /// The cluster cannot be part of a chain.
+    Invalid,
+}
+
+/// Long file name entries waiting for the regular entry they belong to.
+struct PendingLfn {
+    /// The byte offset of the first entry.
+    start: u64,
+    checksum: u8,
+    /// The position of the most recent entry. Entries count down to 1.
+    position: usize,
+    name: Vec<u16>,
+}
+
+/// A directory waiting to be checked.
+struct PendingDir {
+    first_cluster: Cluster,
+    /// The clusters of the directory, or `None` for the fixed root directory
+    /// region of FAT12 and FAT16 volumes.
+    clusters: Option<Vec<Cluster>>,
+    path: String,
+}
+
+/// Walks a file system, recording which file or directory owns each cluster.
+pub(crate) struct Checker<'a, HANDLE: VFatHandle> {
+    vfat: &'a mut VFat<HANDLE>,
+    repair: bool,
+    /// The path of every file and directory visited.
+    paths: Vec<String>,
+    /// For each cluster, 1 + the index in `paths` of its owner, or 0 if no
+    /// file or directory has claimed it.
+    owners: Vec<u32>,
+    report: CheckReport,
+}
+
+impl<'a, HANDLE: VFatHandle> Checker<'a, HANDLE> {
+    pub(crate) fn new(vfat: &'a mut VFat<HANDLE>, repair: bool) -> Checker<'a, HANDLE> {
+        let owners = vec![0; vfat.num_clusters() as usize + 2];
+        Checker {
+            vfat,
+            repair,
+            paths: Vec::new(),
+            owners,
+            report: CheckReport::default(),
+        }
+    }
+
+    pub(crate) fn run(mut self) -> io::Result<CheckReport> {
+        // freeing clusters during repairs changes the count, so it is read
+        // before anything else
+        let recorded_free = self.vfat.fsinfo_free_clusters();
+
+        // the copies are compared before any repairs touch the first FAT
+        let mirrors: Vec<u8> = self.vfat.mirrored_fats().collect();
+        for &copy in &mirrors {
+            let sectors = self.vfat.compare_fat(copy).differing_sectors;
+            if sectors != 0 {
+                self.problem(Problem::FatMismatch { copy, sectors });
+            }
+        }
+
+        self.check_tree()?;
+        self.check_lost_clusters()?;
+
+        // the mirrored FATs may have been left out of date by the repairs
+        if self.repair {
+            for &copy in &mirrors {
+                self.vfat.copy_active_fat(copy)?;
+            }
+        }
+
+        // when repairing, this is compared against the repaired FAT
+        self.check_fsinfo(recorded_free)?;
+
+        if self.repair {
+            self.vfat.sync()?;
+        }
+        self.report.repaired = self.repair;
+        Ok(self.report)
+    }
+
+    fn problem(&mut self, problem: Problem) {
+        self.report.problems.push(problem);
+    }
+
+    fn add_owner(&mut self, path: String) -> u32 {
+        self.paths.push(path);
+        self.paths.len() as u32
+    }
+
+    fn path(&self, owner: u32) -> String {
+        self.paths[owner as usize - 1].clone()
+    }
+
+    /// Checks every directory reachable from the root directory and the
+    /// files in them.
+    fn check_tree(&mut self) -> io::Result<()> {
+        let root = self.vfat.root_cluster();
+        let owner = self.add_owner(String::from("/"));
+        let clusters = match root.num() {
+            // the fixed root directory region of FAT12 and FAT16 volumes
+            0 => None,
+            _ => Some(self.claim_chain(root, owner)?),
+        };
+        self.report.dirs += 1;
+
+        let mut pending = vec![PendingDir {
+            first_cluster: root,
+            clusters,
+            path: String::from("/"),
+        }];
+        while let Some(dir) = pending.pop() {
+            let subdirs = self.check_dir(&dir)?;
+            pending.extend(subdirs.into_iter().rev());
+        }
+
+        Ok(())
+    }
+
+    /// Checks the entries of `dir` and returns its subdirectories.
+    fn check_dir(&mut self, dir: &PendingDir) -> io::Result<Vec<PendingDir>> {
+        let mut buf = Vec::new();
+        match &dir.clusters {
+            None => {
+                self.vfat.read_chain(dir.first_cluster, &mut buf)?;
+            }
+            Some(clusters) => {
+                let cluster_size = self.vfat.cluster_size();
+                for &cluster in clusters {
+                    let len = buf.len();
+                    buf.resize(len + cluster_size, 0);
+                    self.vfat.read_cluster(cluster, 0, &mut buf[len..])?;
+                }
+            }
+        }
+
+        let mut subdirs = Vec::new();
+        let mut lfn: Option<PendingLfn> = None;
+        for (i, raw) in buf.as_chunks::<32>().0.iter().enumerate() {
+            let offset = i as u64 * ENTRY_SIZE;
+            let entry = VFatDirEntry::from_bytes(raw);
+            match entry.id() {
+                END_OF_ENTRIES => break,
+                DELETED_ENTRY => {
+                    if let Some(pending) = lfn.take() {
+                        self.invalid_lfn(dir, pending.start, offset)?;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            if entry.is_lfn() {
+                let part = entry.long_filename();
+                let continues = match &lfn {
+                    Some(pending) => {
+                        !part.is_last()
+                            && part.checksum() == pending.checksum
+                            && part.position() + 1 == pending.position
+                    }
+                    None => false,
+                };
+                if continues {
+                    let pending = lfn.as_mut().unwrap();
+                    pending.position -= 1;
+                    part.copy_name_into(&mut pending.name);
+                    continue;
+                }
+
+                // anything else ends the name before it was complete. a stray
+                // entry in the middle of a name is reported along with it
+                let mut start = offset;
+                if let Some(pending) = lfn.take() {
+                    match part.is_last() {
+                        true => self.invalid_lfn(dir, pending.start, offset)?,
+                        false => start = pending.start,
+                    }
+                }
+                if part.is_last() && (1..=20).contains(&part.position()) {
+                    let mut name = Vec::new();
+                    part.copy_name_into(&mut name);
+                    lfn = Some(PendingLfn {
+                        start: offset,
+                        checksum: part.checksum(),
+                        position: part.position(),
+                        name,
+                    });
+                } else {
+                    self.invalid_lfn(dir, start, offset + ENTRY_SIZE)?;
+                }
+                continue;
+            }
+
+            let regular = entry.regular();
+            let (start, long_name) = match lfn.take() {
+                Some(pending)
+                    if pending.position == 1
+                        && !regular.is_volume_id()
+                        && pending.checksum == name::lfn_checksum(&regular.raw_short_name()) =>
+                {
+                    (pending.start, Some(decode_lfn(&pending.name)))
+                }
+                Some(pending) => {
+                    self.invalid_lfn(dir, pending.start, offset)?;
+                    (offset, None)
+                }
+                None => (offset, None),
+            };
+            if regular.is_volume_id() || regular.is_dot_entry() {
+                continue;
+            }
+
+            let name = long_name.unwrap_or_else(|| regular.short_name());
+            let path = match dir.path.as_str() {
+                "/" => format!("/{}", name),
+                parent => format!("{}/{}", parent, name),
+            };
+            if regular.metadata().attributes.directory() {
+                if let Some(subdir) = self.check_subdir(dir, start, offset, regular, path)? {
+                    subdirs.push(subdir);
+                }
+            } else {
+                self.check_file(dir, offset, regular, path)?;
+            }
+        }
+
+        if let Some(pending) = lfn {
+            self.invalid_lfn(dir, pending.start, buf.len() as u64)?;
+        }
+
+        Ok(subdirs)
+    }
+
+    /// Reports the long file name entries from byte `start` to `end` of `dir`
+    /// and deletes them when repairing.
+    fn invalid_lfn(&mut self, dir: &PendingDir, start: u64, end: u64) -> io::Result<()> {
+        self.problem(Problem::InvalidLfn {
+            dir: dir.path.clone(),
+            offset: start,
+        });
+        if self.repair {
+            for offset in (start..end).step_by(ENTRY_SIZE as usize) {
+                self.vfat
+                    .write_chain_at(dir.first_cluster, offset, &[DELETED_ENTRY])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Claims the chain of the subdirectory whose entries run from byte
+    /// `start` to `offset` of `dir`. Returns the subdirectory unless its
+    /// chain is unusable, in which case its entries are removed when
+    /// repairing.
+    fn check_subdir(
+        &mut self,
+        dir: &PendingDir,
+        start: u64,
+        offset: u64,
+        entry: VFatRegularDirEntry,
+        path: String,
+    ) -> io::Result<Option<PendingDir>> {
+        self.report.dirs += 1;
+        let owner = self.add_owner(path.clone());
+        let clusters = self.claim_chain(entry.cluster(), owner)?;
+        if clusters.is_empty() {
+            if self.repair {
+                for offset in (start..=offset).step_by(ENTRY_SIZE as usize) {
+                    self.vfat
+                        .write_chain_at(dir.first_cluster, offset, &[DELETED_ENTRY])?;
+                }
+            }
+            return Ok(None);
+        }
+
+        Ok(Some(PendingDir {
+            first_cluster: entry.cluster(),
+            clusters: Some(clusters),
+            path,
+        }))
+    }
+
+    /// Claims the chain of the file whose entry is at byte `offset` of `dir`
+    /// and checks that it fits the file's size.
+    fn check_file(
+        &mut self,
+        dir: &PendingDir,
+        offset: u64,
+        mut entry: VFatRegularDirEntry,
+        path: String,
+    ) -> io::Result<()> {
+        self.report.files += 1;
+        let owner = self.add_owner(path.clone());
+        let first = entry.cluster();
+        let clusters = match first.num() {
+            0 => Vec::new(),
+            _ => self.claim_chain(first, owner)?,
+        };
+
+        let mut changed = false;
+        if first.num() != 0 && clusters.is_empty() {
+            // the first cluster is unusable so the file keeps none
+            entry.set_cluster(Cluster::from(0));
+            changed = true;
+        }
+
+        let cluster_size = self.vfat.cluster_size() as u64;
+        let size = entry.size();
+        let needed = (size as u64).div_ceil(cluster_size) as usize;
+        if clusters.len() != needed {
+            self.problem(Problem::SizeMismatch {
+                path,
+                size,
+                clusters: clusters.len() as u32,
+            });
+
+            if clusters.len() > needed {
+                // the clusters past the end of the file are given back. they
+                // stay claimed so that later chains running into them are
+                // still reported as cross-linked
+                if self.repair {
+                    match needed {
+                        0 => entry.set_cluster(Cluster::from(0)),
+                        _ => self
+                            .vfat
+                            .set_fat_status(clusters[needed - 1], Status::Eoc(0x0FFFFFFF))?,
+                    }
+                    for &cluster in &clusters[needed..] {
+                        self.vfat.set_fat_status(cluster, Status::Free)?;
+                    }
+                }
+            } else {
+                entry.set_size((clusters.len() as u64 * cluster_size) as u32);
+            }
+            changed = true;
+        }
+
+        if self.repair && changed {
+            entry.write_to(self.vfat, dir.first_cluster, offset)?;
+        }
+        Ok(())
+    }
+
+    /// Claims `cluster` for `owner` if it is an unclaimed cluster in use.
+    fn claim(&mut self, cluster: Cluster, owner: u32) -> io::Result<Claim> {
+        if self.vfat.check_cluster(cluster).is_err() {
+            return Ok(Claim::Invalid);
+        }
+        match self.owners[cluster.num() as usize] {
+            0 => {}
+            other => return Ok(Claim::Owned(other)),
+        }
+
+        let next = match self.vfat.fat_entry(cluster)?.status() {
+            Status::Data(next) => Some(next),
+            Status::Eoc(_) => None,
+            Status::Free | Status::Reserved | Status::Bad => return Ok(Claim::Invalid),
+        };
+        self.owners[cluster.num() as usize] = owner;
+        Ok(Claim::Claimed(next))
+    }
+
+    /// Claims the chain starting at `first` for `owner`, stopping at the
+    /// first cluster that cannot be part of it. When repairing, the chain is
+    /// cut short before that cluster. Returns the clusters claimed, which are
+    /// empty if `first` itself cannot be used.
+    fn claim_chain(&mut self, first: Cluster, owner: u32) -> io::Result<Vec<Cluster>> {
+        let mut clusters = Vec::new();
+        let mut current = first;
+        let problem = loop {
+            match self.claim(current, owner)? {
+                Claim::Claimed(Some(next)) => {
+                    clusters.push(current);
+                    current = next;
+                }
+                Claim::Claimed(None) => {
+                    clusters.push(current);
+                    return Ok(clusters);
+                }
+                Claim::Owned(other) if other != owner => {
+                    break Problem::CrossLinked {
+                        path: self.path(owner),
+                        other: self.path(other),
+                        cluster: current.num(),
+                    };
+                }
+                // a chain that loops back on itself
+                Claim::Owned(_) | Claim::Invalid => {
+                    break Problem::BadChain {
+                        path: self.path(owner),
+                        cluster: current.num(),
+                    };
+                }
+            }
+        };
+
+        self.problem(problem);
+        if self.repair {
+            if let Some(&last) = clusters.last() {
+                self.vfat.set_fat_status(last, Status::Eoc(0x0FFFFFFF))?;
+            }
+        }
+        Ok(clusters)
+    }
+
+    /// Reports the clusters that are in use but were not claimed by any file
+    /// or directory, grouped into chains, and frees them when repairing.
+    fn check_lost_clusters(&mut self) -> io::Result<()> {
+        let mut lost = BTreeMap::new();
+        for num in 2..self.owners.len() as u32 {
+            if self.owners[num as usize] != 0 {
+                continue;
+            }
+            let cluster = Cluster::from(num);
+            match self.vfat.fat_entry(cluster)?.status() {
+                Status::Data(next) => lost.insert(num, Some(next.num())),
+                Status::Eoc(_) => lost.insert(num, None),
+                _ => continue,
+            };
+        }
+
+        // chains are walked from clusters that nothing else points to, and
+        // then from whatever is left, which can only be loops
+        let pointed_to: BTreeSet<u32> = lost.values().filter_map(|&next| next).collect();
+        let starts: Vec<u32> = lost
+            .keys()
+            .filter(|num| !pointed_to.contains(num))
+            .chain(lost.keys())
+            .cloned()
+            .collect();
+        let mut visited = BTreeSet::new();
+        for start in starts {
+            let mut len = 0;
+            let mut current = Some(start);
+            while let Some(num) = current {
+                if !lost.contains_key(&num) || !visited.insert(num) {
+                    break;
+                }
+                len += 1;
+                current = lost[&num];
+            }
+            if len != 0 {
+                self.problem(Problem::LostChain { start, len });
+            }
+        }
+
+        if self.repair {
+            for &num in lost.keys() {
+                self.vfat.set_fat_status(Cluster::from(num), Status::Free)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares the free cluster count `recorded` in FSInfo when the check
+    /// started against the FAT. When repairing, the count is corrected even
+    /// if it was unknown.
+    fn check_fsinfo(&mut self, recorded: Option<u32>) -> io::Result<()> {
+        let actual = self.vfat.count_free_clusters()?;
+        match recorded {
+            Some(recorded) if recorded != actual => {
+                self.problem(Problem::FsInfoFreeCount { recorded, actual })
+            }
+            _ => {}
+        }
+
+        if self.repair {
+            self.vfat.set_free_clusters(actual);
+        }
+        Ok(())
+    }
+}
@@ -7,4 +7,31 @@ impl From<u32> for Cluster {
     }
 }
 
-// TODO: Implement any useful helper methods on `Cluster`.
+impl Cluster {
+    /// The raw cluster number.
+    pub fn num(&self) -> u32 {
+        self.0
+    }
+
+    /// The index of the cluster in the data region. Data clusters are numbered
+    /// starting at 2, so cluster 2 has index 0.
+    pub fn data_index(&self) -> u64 {
+        (self.0 - 2) as u64
+    }
+
+    /// Whether the cluster number refers to a cluster in the data region.
+    /// Cluster 0 is used by directory entries to mean "no cluster allocated".
+    pub fn is_valid(&self) -> bool {
+        self.0 >= 2
+    }
+
+    /// The high 16 bits of the cluster number, as stored in a directory entry.
+    pub fn high(&self) -> u16 {
+        (self.0 >> 16) as u16
+    }
+
+    /// The low 16 bits of the cluster number, as stored in a directory entry.
+    pub fn low(&self) -> u16 {
+        self.0 as u16
+    }
+}
@@ -1,26 +1,61 @@
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use core::mem::size_of;
 
 use shim::const_assert_size;
 use shim::ffi::OsStr;
 use shim::io;
+use shim::ioerr;
 use shim::newioerr;
+use shim::path::{Component, Path};
 
+use crate::normalize;
 use crate::traits;
-use crate::util::VecExt;
+use crate::vfat::name::{self, ShortName};
 use crate::vfat::{Attributes, Date, Metadata, Time, Timestamp};
-use crate::vfat::{Cluster, Entry, File, VFatHandle};
+use crate::vfat::{Cluster, Entry, File, VFat, VFatHandle};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Dir<HANDLE: VFatHandle> {
     pub vfat: HANDLE,
-    // FIXME: Fill me in.
+    pub(crate) name: String,
+    pub(crate) metadata: Metadata,
+    pub(crate) first_cluster: Cluster,
+    /// Where the directory's entry lives in its parent. `None` for the root
+    /// directory, which has no entry.
+    pub(crate) location: Option<EntryLocation>,
+}
+
+/// Where the regular directory entry describing a file or directory lives on
+/// disk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct EntryLocation {
+    /// The first cluster of the directory holding the entry.
+    pub dir: Cluster,
+    /// The byte offset of the first entry describing the file or directory.
+    /// This is the first of its LFN entries if it has any.
+    pub start: u64,
+    /// The byte offset of the regular entry inside of that directory.
+    pub offset: u64,
 }
 
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatRegularDirEntry {
-    // FIXME: Fill me in.
+    name: [u8; 8],
+    extension: [u8; 3],
+    attributes: Attributes,
+    case_flags: u8,
+    created_tenths: u8,
+    created_time: Time,
+    created_date: Date,
+    accessed_date: Date,
+    cluster_high: u16,
+    modified_time: Time,
+    modified_date: Date,
+    cluster_low: u16,
+    file_size: u32,
 }
 
 const_assert_size!(VFatRegularDirEntry, 32);
@@ -28,7 +63,14 @@ const_assert_size!(VFatRegularDirEntry, 32);
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatLfnDirEntry {
-    // FIXME: Fill me in.
+    sequence: u8,
+    name_1: [u16; 5],
+    attributes: Attributes,
+    entry_type: u8,
+    checksum: u8,
+    name_2: [u16; 6],
+    zero: u16,
+    name_3: [u16; 2],
 }
 
 const_assert_size!(VFatLfnDirEntry, 32);
@@ -36,20 +78,393 @@ const_assert_size!(VFatLfnDirEntry, 32);
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatUnknownDirEntry {
-    // FIXME: Fill me in.
+    id: u8,
+    reserved: [u8; 10],
+    attributes: Attributes,
+    reserved_2: [u8; 20],
 }
 
 const_assert_size!(VFatUnknownDirEntry, 32);
 
+#[derive(Copy, Clone)]
 pub union VFatDirEntry {
     unknown: VFatUnknownDirEntry,
     regular: VFatRegularDirEntry,
     long_filename: VFatLfnDirEntry,
 }
 
+const_assert_size!(VFatDirEntry, 32);
+
+/// Marks the end of the entries in a directory.
+pub(crate) const END_OF_ENTRIES: u8 = 0x00;
+/// Marks a deleted entry that may be reused.
+pub(crate) const DELETED_ENTRY: u8 = 0xE5;
+
+/// Set in the sequence number of the last LFN entry of a name.
+const LAST_LFN_ENTRY: u8 = 0x40;
+
+/// The number of UCS-2 characters stored in a single LFN entry.
+const LFN_CHARS_PER_ENTRY: usize = 13;
+
+/// The size of a single directory entry in bytes.
+pub(crate) const ENTRY_SIZE: u64 = size_of::<VFatDirEntry>() as u64;
+
+impl VFatDirEntry {
+    /// Reads an entry from the first 32 bytes of `bytes`.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> VFatDirEntry {
+        let mut raw = [0u8; 32];
+        raw.copy_from_slice(&bytes[..32]);
+        unsafe { core::mem::transmute::<[u8; 32], VFatDirEntry>(raw) }
+    }
+
+    /// The entry as the 32 bytes stored on disk.
+    pub fn to_bytes(self) -> [u8; 32] {
+        unsafe { core::mem::transmute::<VFatDirEntry, [u8; 32]>(self) }
+    }
+
+    /// The first byte of the entry, which marks deleted entries and the end
+    /// of the entries.
+    pub fn id(&self) -> u8 {
+        unsafe { self.unknown.id }
+    }
+
+    /// Whether the entry has been deleted and may be reused.
+    pub fn is_deleted(&self) -> bool {
+        self.id() == DELETED_ENTRY
+    }
+
+    /// Whether the entry marks the end of the entries in its directory.
+    pub fn is_end(&self) -> bool {
+        self.id() == END_OF_ENTRIES
+    }
+
+    /// The attribute byte, which is shared by every kind of entry.
+    pub fn attributes(&self) -> Attributes {
+        unsafe { self.unknown.attributes }
+    }
+
+    /// Whether the entry holds part of a long file name.
+    pub fn is_lfn(&self) -> bool {
+        self.attributes().raw() == Attributes::LFN
+    }
+
+    /// The entry read as a regular entry. Meaningful when `is_lfn()` is
+    /// `false`.
+    pub fn regular(&self) -> VFatRegularDirEntry {
+        unsafe { self.regular }
+    }
+
+    /// The entry read as part of a long file name. Meaningful when `is_lfn()`
+    /// is `true`.
+    pub fn long_filename(&self) -> VFatLfnDirEntry {
+        unsafe { self.long_filename }
+    }
+}
+
+impl VFatRegularDirEntry {
+    /// Returns a blank entry with attributes `attributes` whose data begins at
+    /// `cluster`.
+    pub(crate) fn new(attributes: u8, cluster: Cluster) -> VFatRegularDirEntry {
+        let mut entry = VFatRegularDirEntry {
+            name: [b' '; 8],
+            extension: [b' '; 3],
+            attributes: Attributes::from_raw(attributes),
+            case_flags: 0,
+            created_tenths: 0,
+            created_time: Time::default(),
+            created_date: Date::default(),
+            accessed_date: Date::default(),
+            cluster_high: 0,
+            modified_time: Time::default(),
+            modified_date: Date::default(),
+            cluster_low: 0,
+            file_size: 0,
+        };
+        entry.set_cluster(cluster);
+        entry
+    }
+
+    /// The cluster where the entry's data begins.
+    pub fn cluster(&self) -> Cluster {
+        Cluster::from(((self.cluster_high as u32) << 16) | self.cluster_low as u32)
+    }
+
+    pub(crate) fn set_cluster(&mut self, cluster: Cluster) {
+        self.cluster_high = cluster.high();
+        self.cluster_low = cluster.low();
+    }
+
+    /// The size of the file in bytes. Always 0 for directories.
+    pub fn size(&self) -> u32 {
+        self.file_size
+    }
+
+    pub(crate) fn set_size(&mut self, size: u32) {
+        self.file_size = size;
+    }
+
+    /// Whether the entry is the `.` or `..` entry of a subdirectory.
+    pub fn is_dot_entry(&self) -> bool {
+        let name = self.raw_short_name();
+        &name == b".          " || &name == b"..         "
+    }
+
+    /// Whether the entry holds the volume label rather than a file or
+    /// directory.
+    pub fn is_volume_id(&self) -> bool {
+        self.attributes.raw() & Attributes::VOLUME_ID != 0
+    }
+
+    /// Writes the entry into the directory starting at `dir`, `offset` bytes
+    /// in.
+    pub(crate) fn write_to<HANDLE: VFatHandle>(
+        &self,
+        vfat: &mut VFat<HANDLE>,
+        dir: Cluster,
+        offset: u64,
+    ) -> io::Result<()> {
+        vfat.write_chain_at(dir, offset, &self.to_bytes())?;
+        Ok(())
+    }
+
+    /// The entry as the 32 bytes stored on disk.
+    pub fn to_bytes(self) -> [u8; 32] {
+        VFatDirEntry { regular: self }.to_bytes()
+    }
+
+    /// The 8.3 name and extension fields as stored on disk.
+    pub fn raw_short_name(&self) -> [u8; 11] {
+        let mut short = [0u8; 11];
+        short[..8].copy_from_slice(&self.name);
+        short[8..].copy_from_slice(&self.extension);
+        short
+    }
+
+    pub(crate) fn set_short_name(&mut self, short: &[u8; 11]) {
+        self.name.copy_from_slice(&short[..8]);
+        self.extension.copy_from_slice(&short[8..]);
+    }
+
+    /// The checksum of the 8.3 name, which the LFN entries of a long file
+    /// name belonging to this entry carry.
+    pub fn lfn_checksum(&self) -> u8 {
+        name::lfn_checksum(&self.raw_short_name())
+    }
+
+    /// The name of the entry from its 8.3 fields, in the case recorded by
+    /// its case flags. The first character of a deleted entry's name is
+    /// lost and reads as U+FFFD.
+    pub fn short_name(&self) -> String {
+        let mut name = self.name;
+        // 0x05 is used as an escape for names that begin with 0xE5
+        if name[0] == 0x05 {
+            name[0] = DELETED_ENTRY;
+        }
+
+        let mut extension = self.extension;
+        if self.case_flags & name::LOWERCASE_BASE != 0 {
+            name.make_ascii_lowercase();
+        }
+        if self.case_flags & name::LOWERCASE_EXT != 0 {
+            extension.make_ascii_lowercase();
+        }
+
+        let mut short = String::from_utf8_lossy(trim_padding(&name)).into_owned();
+        let extension = trim_padding(&extension);
+        if !extension.is_empty() {
+            short.push('.');
+            short.push_str(&String::from_utf8_lossy(extension));
+        }
+        short
+    }
+
+    /// Stamps the entry as created, modified and accessed at `now`.
+    pub(crate) fn stamp_created(&mut self, now: Timestamp) {
+        self.created_tenths = 0;
+        self.created_time = now.time;
+        self.created_date = now.date;
+        self.stamp_modified(now);
+    }
+
+    /// Stamps the entry as modified and accessed at `now`.
+    pub(crate) fn stamp_modified(&mut self, now: Timestamp) {
+        self.modified_time = now.time;
+        self.modified_date = now.date;
+        self.accessed_date = now.date;
+    }
+
+    /// The entry's attributes and timestamps.
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            attributes: self.attributes,
+            created: Timestamp {
+                date: self.created_date,
+                time: self.created_time,
+            },
+            accessed: Timestamp {
+                date: self.accessed_date,
+                time: Time::default(),
+            },
+            modified: Timestamp {
+                date: self.modified_date,
+                time: self.modified_time,
+            },
+        }
+    }
+}
+
+impl VFatLfnDirEntry {
+    /// The 1-based position of this entry's characters in the full name.
+    pub fn position(&self) -> usize {
+        (self.sequence & 0x1F) as usize
+    }
+
+    /// Whether this entry holds the last part of the name. It is stored
+    /// first.
+    pub fn is_last(&self) -> bool {
+        self.sequence & LAST_LFN_ENTRY != 0
+    }
+
+    /// The checksum of the short name that the long name belongs to.
+    pub fn checksum(&self) -> u8 {
+        self.checksum
+    }
+
+    /// Copies the characters held by this entry into their position in `name`.
+    pub fn copy_name_into(&self, name: &mut Vec<u16>) {
+        let start = match self.position() {
+            0 => return,
+            position => (position - 1) * LFN_CHARS_PER_ENTRY,
+        };
+        if name.len() < start + LFN_CHARS_PER_ENTRY {
+            name.resize(start + LFN_CHARS_PER_ENTRY, 0xFFFF);
+        }
+
+        let (name_1, name_2, name_3) = ({ self.name_1 }, { self.name_2 }, { self.name_3 });
+        let chars = name_1.iter().chain(name_2.iter()).chain(name_3.iter());
+        for (slot, &c) in name[start..].iter_mut().zip(chars) {
+            *slot = c;
+        }
+    }
+}
+
+/// Strips the space padding from a short name field.
+fn trim_padding(field: &[u8]) -> &[u8] {
+    let len = field.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+    &field[..len]
+}
+
+/// Decodes a long file name, which ends at either a NUL or 0xFFFF padding.
+pub(crate) fn decode_lfn(name: &[u16]) -> String {
+    let len = name
+        .iter()
+        .position(|&c| c == 0x0000 || c == 0xFFFF)
+        .unwrap_or(name.len());
+    decode_utf16(name[..len].iter().cloned())
+        .map(|c| c.unwrap_or(REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Returns `name` as a `&str`.
+///
+/// # Errors
+///
+/// Returns an error of `InvalidInput` if `name` is not valid UTF-8.
+fn utf8_name(name: &OsStr) -> io::Result<&str> {
+    name.to_str()
+        .ok_or_else(|| newioerr!(InvalidInput, "name is not valid UTF-8"))
+}
+
+/// Builds the LFN entries holding the UCS-2 encoded `name` in the order they
+/// are stored on disk, which is last part of the name first.
+fn lfn_entries(name: &[u16], checksum: u8) -> Vec<VFatDirEntry> {
+    let mut chars = name.to_vec();
+    let count = chars.len().div_ceil(LFN_CHARS_PER_ENTRY);
+    // a name that doesn't fill its last entry is terminated by a NUL and then
+    // padded with 0xFFFF
+    if !chars.len().is_multiple_of(LFN_CHARS_PER_ENTRY) {
+        chars.push(0x0000);
+    }
+    chars.resize(count * LFN_CHARS_PER_ENTRY, 0xFFFF);
+
+    (0..count)
+        .rev()
+        .map(|i| {
+            let part = &chars[i * LFN_CHARS_PER_ENTRY..(i + 1) * LFN_CHARS_PER_ENTRY];
+            let mut sequence = (i + 1) as u8;
+            if i + 1 == count {
+                sequence |= LAST_LFN_ENTRY;
+            }
+
+            let (mut name_1, mut name_2, mut name_3) = ([0u16; 5], [0u16; 6], [0u16; 2]);
+            name_1.copy_from_slice(&part[..5]);
+            name_2.copy_from_slice(&part[5..11]);
+            name_3.copy_from_slice(&part[11..]);
+
+            VFatDirEntry {
+                long_filename: VFatLfnDirEntry {
+                    sequence,
+                    name_1,
+                    attributes: Attributes::from_raw(Attributes::LFN),
+                    entry_type: 0,
+                    checksum,
+                    name_2,
+                    zero: 0,
+                    name_3,
+                },
+            }
+        })
+        .collect()
+}
+
 impl<HANDLE: VFatHandle> Dir<HANDLE> {
+    pub(crate) fn new(
+        vfat: HANDLE,
+        name: String,
+        metadata: Metadata,
+        first_cluster: Cluster,
+        location: Option<EntryLocation>,
+    ) -> Dir<HANDLE> {
+        Dir {
+            vfat,
+            name,
+            metadata,
+            first_cluster,
+            location,
+        }
+    }
+
+    /// Returns the root directory of the file system behind `vfat`.
+    pub(crate) fn root(vfat: HANDLE) -> Dir<HANDLE> {
+        let first_cluster = vfat.lock(|vfat| vfat.root_cluster());
+        Dir::new(
+            vfat,
+            String::from("/"),
+            Metadata::default(),
+            first_cluster,
+            None,
+        )
+    }
+
+    /// Replaces the directory's read-only, hidden, system and archive
+    /// attributes with those in `attributes`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `self` is the root directory,
+    /// which has no attributes.
+    pub fn set_attributes(&mut self, attributes: Attributes) -> io::Result<()> {
+        self.vfat.lock(|vfat| vfat.begin_operation())?;
+        let location = self
+            .location
+            .ok_or_else(|| newioerr!(InvalidInput, "the root directory has no attributes"))?;
+        self.metadata = update_attributes(&self.vfat, location, attributes)?;
+        Ok(())
+    }
+
     /// Finds the entry named `name` in `self` and returns it. Comparison is
-    /// case-insensitive.
+    /// case-insensitive. Entries with long file names can also be found by
+    /// their 8.3 alias.
     ///
     /// # Errors
     ///
@@ -59,10 +474,811 @@ impl<HANDLE: VFatHandle> Dir<HANDLE> {
     /// If `name` contains invalid UTF-8 characters, an error of `InvalidInput`
     /// is returned.
     pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry<HANDLE>> {
-        unimplemented!("Dir::find()")
+        use crate::traits::{Dir as _, Entry as _};
+
+        let name = utf8_name(name.as_ref())?;
+        let mut entries = self.entries()?;
+        let mut alias = None;
+        while let Some(next) = entries.next_with_short_name() {
+            let (entry, short_name) = next?;
+            if name::names_match(entry.name(), name) {
+                return Ok(entry);
+            }
+            if alias.is_none() && name::names_match(&short_name, name) {
+                alias = Some(entry);
+            }
+        }
+
+        alias.ok_or_else(|| newioerr!(NotFound, "no entry with that name"))
+    }
+
+    /// Opens the entry at `path` relative to `self`. Absolute paths are
+    /// resolved from the root directory instead. The path is normalized first,
+    /// and `..` components left at its start are followed through the `..`
+    /// entries on disk, stopping at the root. An empty path or `.` opens
+    /// `self`.
+    ///
+    /// A parent directory reached through `..` is looked up in its own parent,
+    /// so it has its real name and changes to it reach its entry.
+    ///
+    /// # Errors
+    ///
+    /// If any component but the last in `path` does not refer to an existing
+    /// directory, an error of `InvalidInput` is returned.
+    ///
+    /// If there is no entry at `path`, an error of `NotFound` is returned.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<Entry<HANDLE>> {
+        use crate::traits::Entry as _;
+
+        let path = normalize(path);
+        let mut entry = Entry::Dir(self.clone());
+        for component in path.components() {
+            let dir = match entry.as_dir() {
+                Some(dir) => dir,
+                None => return ioerr!(InvalidInput, "path component is not a directory"),
+            };
+            entry = match component {
+                Component::RootDir => Entry::Dir(Dir::root(self.vfat.clone())),
+                Component::ParentDir => Entry::Dir(dir.parent()?),
+                component => dir.find(component.as_os_str())?,
+            };
+        }
+
+        Ok(entry)
+    }
+
+    /// Returns the parent of `self`, found through its `..` entry. That entry
+    /// only records where the parent starts, so the parent's own entry is
+    /// looked up in the grandparent, found through the parent's `..` entry.
+    /// The root directory is its own parent.
+    fn parent(&self) -> io::Result<Dir<HANDLE>> {
+        use crate::traits::Dir as _;
+
+        let parent = match self.dotdot()? {
+            Some(parent) => parent,
+            None => return Ok(Dir::root(self.vfat.clone())),
+        };
+        let grandparent = parent
+            .dotdot()?
+            .unwrap_or_else(|| Dir::root(self.vfat.clone()));
+
+        let mut entries = grandparent.entries()?;
+        while let Some(next) = entries.next_with_short_name() {
+            if let (Entry::Dir(dir), _) = next? {
+                if dir.first_cluster == parent.first_cluster && dir.name != "." && dir.name != ".."
+                {
+                    return Ok(dir);
+                }
+            }
+        }
+        ioerr!(
+            InvalidData,
+            "parent directory is missing from its own parent"
+        )
+    }
+
+    /// The directory that the `..` entry of `self` refers to, named `..`, or
+    /// `None` if that is the root directory.
+    fn dotdot(&self) -> io::Result<Option<Dir<HANDLE>>> {
+        let root = self.vfat.lock(|vfat| vfat.root_cluster());
+        if self.first_cluster == root {
+            return Ok(None);
+        }
+        match self.find("..")? {
+            Entry::Dir(dir) if dir.first_cluster == root => Ok(None),
+            Entry::Dir(dir) => Ok(Some(dir)),
+            Entry::File(_) => ioerr!(InvalidData, "`..` entry is not a directory"),
+        }
+    }
+
+    /// Returns an iterator over every entry slot in `self` along with its
+    /// byte offset in the directory. Unlike `entries()`, nothing is skipped or
+    /// combined: deleted entries, the LFN entries of long file names (including
+    /// orphaned ones), volume-ID entries and the slots after the end marker are
+    /// all yielded as stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the first cluster of the directory cannot be read.
+    /// Later read errors are yielded by the iterator, which then ends.
+    pub fn raw_entries(&self) -> io::Result<RawEntries<HANDLE>> {
+        RawEntries::new(self.vfat.clone(), self.first_cluster)
+    }
+
+    /// Creates a new, empty file named `name` in `self` and returns it. Names
+    /// that do not fit in an 8.3 short name are stored as long file names.
+    ///
+    /// # Errors
+    ///
+    /// If an entry named `name` already exists in `self`, an error of
+    /// `AlreadyExists` is returned.
+    ///
+    /// If `name` is not valid UTF-8 or is not a valid file name, an error of
+    /// `InvalidInput` is returned.
+    pub fn create_file<P: AsRef<OsStr>>(&self, name: P) -> io::Result<File<HANDLE>> {
+        self.vfat.lock(|vfat| vfat.begin_operation())?;
+        let name = utf8_name(name.as_ref())?;
+        self.check_new_name(name)?;
+
+        let mut entry = VFatRegularDirEntry::new(Attributes::ARCHIVE, Cluster::from(0));
+        if let Some(now) = self.vfat.lock(|vfat| vfat.now()) {
+            entry.stamp_created(now);
+        }
+        let location = self.insert_entry(name, entry, None)?;
+
+        Ok(File::new(
+            self.vfat.clone(),
+            String::from(name),
+            entry.metadata(),
+            entry.cluster(),
+            0,
+            location,
+        ))
+    }
+
+    /// Creates a new, empty directory named `name` in `self` and returns it.
+    ///
+    /// # Errors
+    ///
+    /// If an entry named `name` already exists in `self`, an error of
+    /// `AlreadyExists` is returned.
+    ///
+    /// If `name` is not valid UTF-8 or is not a valid file name, an error of
+    /// `InvalidInput` is returned.
+    pub fn create_dir<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Dir<HANDLE>> {
+        self.vfat.lock(|vfat| vfat.begin_operation())?;
+        let name = utf8_name(name.as_ref())?;
+        self.check_new_name(name)?;
+
+        // the new cluster is zeroed, so the directory ends after `.` and `..`
+        let cluster = self.vfat.lock(|vfat| vfat.alloc_cluster(None))?;
+        let mut entry = VFatRegularDirEntry::new(Attributes::DIRECTORY, cluster);
+        if let Some(now) = self.vfat.lock(|vfat| vfat.now()) {
+            entry.stamp_created(now);
+        }
+        let location = self
+            .write_dot_entries(&entry)
+            .and_then(|_| self.insert_entry(name, entry, None));
+
+        match location {
+            Ok(location) => Ok(Dir::new(
+                self.vfat.clone(),
+                String::from(name),
+                entry.metadata(),
+                cluster,
+                Some(location),
+            )),
+            Err(e) => {
+                // give the cluster back; the original error is the useful one
+                let _ = self.vfat.lock(|vfat| vfat.free_chain(cluster));
+                Err(e)
+            }
+        }
+    }
+
+    /// Removes the entry named `name` from `self` and frees its clusters. A
+    /// directory can only be removed if it is empty.
+    ///
+    /// # Errors
+    ///
+    /// If no entry with name `name` exists in `self`, an error of `NotFound` is
+    /// returned.
+    ///
+    /// If `name` is `.` or `..`, an error of `InvalidInput` is returned.
+    ///
+    /// If the entry is a directory that is not empty, an error of `Other` is
+    /// returned.
+    ///
+    /// If the entry is the file holding the volume's journal, an error of
+    /// `PermissionDenied` is returned.
+    pub fn remove<P: AsRef<OsStr>>(&self, name: P) -> io::Result<()> {
+        self.vfat.lock(|vfat| vfat.begin_operation())?;
+        let name = utf8_name(name.as_ref())?;
+        if name == "." || name == ".." {
+            return ioerr!(InvalidInput, "cannot remove `.` or `..`");
+        }
+
+        let entry = self.find(name)?;
+        if let Entry::Dir(dir) = &entry {
+            if !dir.is_empty()? {
+                return ioerr!(Other, "directory is not empty");
+            }
+        }
+
+        let first_cluster = match &entry {
+            Entry::File(file) => file.first_cluster,
+            Entry::Dir(dir) => dir.first_cluster,
+        };
+        if self.vfat.lock(|vfat| vfat.is_journal(first_cluster)) {
+            return ioerr!(PermissionDenied, "the journal cannot be removed");
+        }
+
+        let location = entry_location(&entry)?;
+        delete_entries(&self.vfat, location)?;
+
+        if first_cluster.is_valid() {
+            self.vfat.lock(|vfat| vfat.free_chain(first_cluster))?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the entry named `name` in `self` into the directory `to` under
+    /// the name `to_name`. The entry's data is not copied.
+    ///
+    /// # Errors
+    ///
+    /// If no entry with name `name` exists in `self`, an error of `NotFound` is
+    /// returned.
+    ///
+    /// If a different entry named `to_name` already exists in `to`, an error
+    /// of `AlreadyExists` is returned.
+    ///
+    /// If `name` is `.` or `..`, `to_name` is not a valid file name, or the
+    /// entry is a directory and `to` is inside of it, an error of
+    /// `InvalidInput` is returned.
+    ///
+    /// If the entry is the file holding the volume's journal, an error of
+    /// `PermissionDenied` is returned.
+    pub fn rename<P: AsRef<OsStr>, Q: AsRef<OsStr>>(
+        &self,
+        name: P,
+        to: &Dir<HANDLE>,
+        to_name: Q,
+    ) -> io::Result<()> {
+        use crate::traits::Entry as _;
+
+        self.vfat.lock(|vfat| vfat.begin_operation())?;
+        let name = utf8_name(name.as_ref())?;
+        let to_name = utf8_name(to_name.as_ref())?;
+        if name == "." || name == ".." {
+            return ioerr!(InvalidInput, "cannot rename `.` or `..`");
+        }
+        name::validate_name(to_name)?;
+
+        let entry = self.find(name)?;
+        if let Entry::File(file) = &entry {
+            if self.vfat.lock(|vfat| vfat.is_journal(file.first_cluster)) {
+                return ioerr!(PermissionDenied, "the journal cannot be renamed");
+            }
+        }
+        let location = entry_location(&entry)?;
+        match to.find(to_name) {
+            // the name is only changing case
+            Ok(existing) if existing.location() == Some(location) => {
+                if existing.name() == to_name {
+                    return Ok(());
+                }
+            }
+            Ok(_) => return ioerr!(AlreadyExists, "an entry with that name already exists"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        if let Entry::Dir(dir) = &entry {
+            if to.is_inside(dir.first_cluster)? {
+                return ioerr!(InvalidInput, "cannot move a directory inside of itself");
+            }
+        }
+
+        // add the new entry before removing the old one so that the entry is
+        // never lost
+        let raw = read_regular_entry(&self.vfat, location)?;
+        to.insert_entry(to_name, raw, Some(location))?;
+        delete_entries(&self.vfat, location)?;
+
+        if let Entry::Dir(dir) = &entry {
+            if to.first_cluster != self.first_cluster {
+                let dotdot = EntryLocation {
+                    dir: dir.first_cluster,
+                    start: ENTRY_SIZE,
+                    offset: ENTRY_SIZE,
+                };
+                let mut parent = read_regular_entry(&self.vfat, dotdot)?;
+                parent.set_cluster(to.parent_reference());
+                write_regular_entry(&self.vfat, dotdot, &parent)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the directory has no entries besides `.` and `..`.
+    fn is_empty(&self) -> io::Result<bool> {
+        use crate::traits::{Dir as _, Entry as _};
+
+        let mut entries = self.entries()?;
+        while let Some(next) = entries.next_with_short_name() {
+            let (entry, _) = next?;
+            if entry.name() != "." && entry.name() != ".." {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether `self` is the directory starting at `ancestor` or somewhere
+    /// beneath it.
+    fn is_inside(&self, ancestor: Cluster) -> io::Result<bool> {
+        let root = self.vfat.lock(|vfat| vfat.root_cluster());
+        let mut current = self.first_cluster;
+        loop {
+            if current == ancestor {
+                return Ok(true);
+            }
+            if current == root {
+                return Ok(false);
+            }
+
+            // follow the `..` entry up to the parent
+            let dotdot = EntryLocation {
+                dir: current,
+                start: ENTRY_SIZE,
+                offset: ENTRY_SIZE,
+            };
+            current = match read_regular_entry(&self.vfat, dotdot)?.cluster() {
+                cluster if cluster.is_valid() => cluster,
+                _ => root,
+            };
+        }
+    }
+
+    /// The cluster that `..` entries of subdirectories of `self` point to.
+    /// The root directory is referred to as cluster 0.
+    fn parent_reference(&self) -> Cluster {
+        match self.location {
+            Some(_) => self.first_cluster,
+            None => Cluster::from(0),
+        }
+    }
+
+    /// Writes the `.` and `..` entries of a new subdirectory of `self`
+    /// described by `entry`. Both take its timestamps.
+    fn write_dot_entries(&self, entry: &VFatRegularDirEntry) -> io::Result<()> {
+        let cluster = entry.cluster();
+        let mut dot = *entry;
+        dot.set_short_name(b".          ");
+        let mut dotdot = *entry;
+        dotdot.set_cluster(self.parent_reference());
+        dotdot.set_short_name(b"..         ");
+
+        write_raw_entry(&self.vfat, cluster, 0, &VFatDirEntry { regular: dot })?;
+        write_raw_entry(
+            &self.vfat,
+            cluster,
+            ENTRY_SIZE,
+            &VFatDirEntry { regular: dotdot },
+        )
+    }
+
+    /// Returns an error if `name` cannot be used for a new entry in `self`.
+    ///
+    /// # Errors
+    ///
+    /// If an entry named `name` already exists in `self`, an error of
+    /// `AlreadyExists` is returned. If `name` is not a valid file name, an
+    /// error of `InvalidInput` is returned.
+    fn check_new_name(&self, name: &str) -> io::Result<()> {
+        name::validate_name(name)?;
+        match self.find(name) {
+            Ok(_) => ioerr!(AlreadyExists, "an entry with that name already exists"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes `entry` into the directory under the name `name`. If `name`
+    /// does not fit in a short name, a unique short alias is generated and
+    /// `name` is stored in LFN entries ahead of it. The short name of the
+    /// entry at `replacing`, which is about to be deleted, does not count as
+    /// taken. Returns where the entries were written.
+    fn insert_entry(
+        &self,
+        name: &str,
+        mut entry: VFatRegularDirEntry,
+        replacing: Option<EntryLocation>,
+    ) -> io::Result<EntryLocation> {
+        let ShortName {
+            raw,
+            case,
+            needs_lfn,
+        } = name::generate_short_name(name, &self.short_names(replacing)?)?;
+        entry.set_short_name(&raw);
+        entry.case_flags = case;
+
+        let mut entries = match needs_lfn {
+            true => lfn_entries(&name::encode_ucs2(name)?, name::lfn_checksum(&raw)),
+            false => Vec::new(),
+        };
+        entries.push(VFatDirEntry { regular: entry });
+
+        let start = self.alloc_entries(entries.len())?;
+        for (i, raw) in entries.iter().enumerate() {
+            let offset = start + i as u64 * ENTRY_SIZE;
+            write_raw_entry(&self.vfat, self.first_cluster, offset, raw)?;
+        }
+
+        Ok(EntryLocation {
+            dir: self.first_cluster,
+            start,
+            offset: start + (entries.len() as u64 - 1) * ENTRY_SIZE,
+        })
+    }
+
+    /// The raw 8.3 names of every entry in the directory, except the entry
+    /// at `except`.
+    fn short_names(&self, except: Option<EntryLocation>) -> io::Result<Vec<[u8; 11]>> {
+        let skipped = except
+            .filter(|location| location.dir == self.first_cluster)
+            .map(|location| location.offset);
+        let mut names = Vec::new();
+        for raw in RawEntries::new(self.vfat.clone(), self.first_cluster)? {
+            let (offset, entry) = raw?;
+            match entry.id() {
+                END_OF_ENTRIES => break,
+                DELETED_ENTRY => {}
+                _ if entry.is_lfn() || skipped == Some(offset) => {}
+                _ => names.push(entry.regular().raw_short_name()),
+            }
+        }
+        Ok(names)
+    }
+
+    /// Finds room for `count` consecutive entries in the directory, growing
+    /// the directory by a cluster if there is none. Returns the byte offset of
+    /// the first entry.
+    fn alloc_entries(&self, count: usize) -> io::Result<u64> {
+        let mut run = 0;
+        let mut len = 0;
+        for raw in RawEntries::new(self.vfat.clone(), self.first_cluster)? {
+            let (offset, entry) = raw?;
+            let id = entry.id();
+            if id == END_OF_ENTRIES || id == DELETED_ENTRY {
+                run += 1;
+                if run == count {
+                    return Ok(offset - (count as u64 - 1) * ENTRY_SIZE);
+                }
+            } else {
+                run = 0;
+            }
+            len = offset + ENTRY_SIZE;
+        }
+
+        // not enough room so grow the directory. new clusters are zeroed so
+        // everything after the new entries is marked as the end of the entries
+        let start = len / ENTRY_SIZE - run as u64;
+        let end = (start + count as u64) * ENTRY_SIZE;
+        let cluster_size = self.vfat.lock(|vfat| vfat.cluster_size()) as u64;
+        let clusters = end.div_ceil(cluster_size);
+        self.vfat
+            .lock(|vfat| vfat.extend_chain(self.first_cluster, clusters))?;
+
+        Ok(start * ENTRY_SIZE)
+    }
+}
+
+/// An iterator over the raw entries of a directory along with their byte
+/// offsets, including deleted entries and those after the end of the
+/// entries. Returned by [`Dir::raw_entries`]. Only one cluster of the
+/// directory is held in memory at a time.
+pub struct RawEntries<HANDLE: VFatHandle> {
+    vfat: HANDLE,
+    dir: Cluster,
+    /// The cluster held in `data`.
+    cluster: Option<Cluster>,
+    data: Vec<u8>,
+    /// The offset of the start of `data` in the directory.
+    data_offset: u64,
+    /// The offset of the next entry in `data`.
+    position: usize,
+    done: bool,
+}
+
+impl<HANDLE: VFatHandle> RawEntries<HANDLE> {
+    /// Returns an iterator over the entries of the directory starting at
+    /// `dir`. The first cluster is read right away so that a directory that
+    /// cannot be read is reported here rather than ending the iteration.
+    pub(crate) fn new(vfat: HANDLE, dir: Cluster) -> io::Result<RawEntries<HANDLE>> {
+        let mut entries = RawEntries {
+            vfat,
+            dir,
+            cluster: None,
+            data: Vec::new(),
+            data_offset: 0,
+            position: 0,
+            done: false,
+        };
+        entries.read_next_cluster()?;
+        Ok(entries)
+    }
+
+    /// Replaces `data` with the next cluster of the directory, marking the
+    /// iteration as done at the end of the chain.
+    fn read_next_cluster(&mut self) -> io::Result<()> {
+        let (dir, prev, data) = (self.dir, self.cluster, &mut self.data);
+        let len = data.len() as u64;
+        match self
+            .vfat
+            .lock(|vfat| vfat.read_next_cluster(dir, prev, data))?
+        {
+            Some(cluster) => {
+                self.cluster = Some(cluster);
+                self.data_offset += len;
+                self.position = 0;
+            }
+            None => self.done = true,
+        }
+        Ok(())
+    }
+
+    /// Ends the iteration early.
+    fn stop(&mut self) {
+        self.done = true;
+    }
+}
+
+impl<HANDLE: VFatHandle> Iterator for RawEntries<HANDLE> {
+    type Item = io::Result<(u64, VFatDirEntry)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.done && self.position >= self.data.len() {
+            if let Err(e) = self.read_next_cluster() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        if self.done {
+            return None;
+        }
+
+        let entry = VFatDirEntry::from_bytes(&self.data[self.position..]);
+        let offset = self.data_offset + self.position as u64;
+        self.position += ENTRY_SIZE as usize;
+        Some(Ok((offset, entry)))
+    }
+}
+
+/// Reads the regular entry at `location`.
+pub(crate) fn read_regular_entry<HANDLE: VFatHandle>(
+    vfat: &HANDLE,
+    location: EntryLocation,
+) -> io::Result<VFatRegularDirEntry> {
+    let mut buf = [0u8; size_of::<VFatRegularDirEntry>()];
+    vfat.lock(|vfat| vfat.read_chain_at(location.dir, location.offset, &mut buf))?;
+    Ok(unsafe { core::mem::transmute::<[u8; 32], VFatRegularDirEntry>(buf) })
+}
+
+/// Overwrites the entry at `location` with `entry`.
+pub(crate) fn write_regular_entry<HANDLE: VFatHandle>(
+    vfat: &HANDLE,
+    location: EntryLocation,
+    entry: &VFatRegularDirEntry,
+) -> io::Result<()> {
+    let entry = VFatDirEntry { regular: *entry };
+    write_raw_entry(vfat, location.dir, location.offset, &entry)
+}
+
+/// Overwrites the entry `offset` bytes into the directory starting at `dir`
+/// with `entry`.
+fn write_raw_entry<HANDLE: VFatHandle>(
+    vfat: &HANDLE,
+    dir: Cluster,
+    offset: u64,
+    entry: &VFatDirEntry,
+) -> io::Result<()> {
+    let buf = entry.to_bytes();
+    vfat.lock(|vfat| vfat.write_chain_at(dir, offset, &buf))?;
+    Ok(())
+}
+
+/// Marks the regular entry at `location` and any LFN entries before it as
+/// deleted.
+fn delete_entries<HANDLE: VFatHandle>(vfat: &HANDLE, location: EntryLocation) -> io::Result<()> {
+    vfat.lock(|vfat| -> io::Result<()> {
+        for offset in (location.start..=location.offset).step_by(ENTRY_SIZE as usize) {
+            vfat.write_chain_at(location.dir, offset, &[DELETED_ENTRY])?;
+        }
+        Ok(())
+    })
+}
+
+/// Where `entry`'s regular directory entry lives.
+///
+/// # Errors
+///
+/// Returns an error of `InvalidInput` if `entry` is the root directory.
+fn entry_location<HANDLE: VFatHandle>(entry: &Entry<HANDLE>) -> io::Result<EntryLocation> {
+    entry
+        .location()
+        .ok_or_else(|| newioerr!(InvalidInput, "the root directory has no entry"))
+}
+
+/// Updates the size and first cluster recorded in the regular entry at
+/// `location`, and stamps it as modified if the file system has a clock.
+/// Returns the entry's new metadata.
+pub(crate) fn update_regular_entry<HANDLE: VFatHandle>(
+    vfat: &HANDLE,
+    location: EntryLocation,
+    first_cluster: Cluster,
+    size: u32,
+) -> io::Result<Metadata> {
+    let mut entry = read_regular_entry(vfat, location)?;
+    entry.set_cluster(first_cluster);
+    entry.file_size = size;
+    if let Some(now) = vfat.lock(|vfat| vfat.now()) {
+        entry.stamp_modified(now);
+    }
+    write_regular_entry(vfat, location, &entry)?;
+    Ok(entry.metadata())
+}
+
+/// Replaces the read-only, hidden, system and archive attributes of the
+/// regular entry at `location` with those in `attributes`. The directory and
+/// volume ID attributes are left as they are. Returns the entry's new
+/// metadata. Fails with `PermissionDenied` for the journal file.
+pub(crate) fn update_attributes<HANDLE: VFatHandle>(
+    vfat: &HANDLE,
+    location: EntryLocation,
+    attributes: Attributes,
+) -> io::Result<Metadata> {
+    let mut entry = read_regular_entry(vfat, location)?;
+    // the journal stays read only so that it is never written as a file
+    if vfat.lock(|vfat| vfat.is_journal(entry.cluster())) {
+        return ioerr!(
+            PermissionDenied,
+            "the journal's attributes cannot be changed"
+        );
+    }
+    let kept = entry.attributes.raw() & !Attributes::CHANGEABLE;
+    entry.attributes = Attributes::from_raw(kept | (attributes.raw() & Attributes::CHANGEABLE));
+    write_regular_entry(vfat, location, &entry)?;
+    Ok(entry.metadata())
+}
+
+/// Sets the access date of the regular entry at `location` to `date`.
+/// Returns the entry's new metadata.
+pub(crate) fn update_accessed<HANDLE: VFatHandle>(
+    vfat: &HANDLE,
+    location: EntryLocation,
+    date: Date,
+) -> io::Result<Metadata> {
+    let mut entry = read_regular_entry(vfat, location)?;
+    entry.accessed_date = date;
+    write_regular_entry(vfat, location, &entry)?;
+    Ok(entry.metadata())
+}
+
+/// An iterator over the entries in a `Dir`.
+pub struct EntryIter<HANDLE: VFatHandle> {
+    vfat: HANDLE,
+    dir: Cluster,
+    entries: RawEntries<HANDLE>,
+}
+
+impl<HANDLE: VFatHandle> Iterator for EntryIter<HANDLE> {
+    type Item = Entry<HANDLE>;
+
+    /// Ends the iteration if the directory cannot be read.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_short_name()?.ok().map(|(entry, _)| entry)
+    }
+}
+
+impl<HANDLE: VFatHandle> EntryIter<HANDLE> {
+    /// Returns the next entry along with its 8.3 short name. LFN entries
+    /// whose checksum does not match the short entry after them are
+    /// ignored. If the directory cannot be read, the error is returned and
+    /// the iteration ends.
+    fn next_with_short_name(&mut self) -> Option<io::Result<(Entry<HANDLE>, String)>> {
+        let mut lfn: Vec<u16> = Vec::new();
+        let mut checksum = None;
+        // the offset of the first LFN entry of the current name
+        let mut start = None;
+
+        while let Some(next) = self.entries.next() {
+            let (offset, raw) = match next {
+                Ok(next) => next,
+                Err(e) => return Some(Err(e)),
+            };
+            match raw.id() {
+                END_OF_ENTRIES => {
+                    self.entries.stop();
+                    return None;
+                }
+                DELETED_ENTRY => {
+                    lfn.clear();
+                    start = None;
+                    continue;
+                }
+                _ => {}
+            }
+
+            if raw.is_lfn() {
+                let entry = raw.long_filename();
+                // a new name starts at its last entry, and every entry of a
+                // name shares the same checksum
+                if entry.is_last() || checksum != Some(entry.checksum) {
+                    lfn.clear();
+                    start = Some(offset);
+                }
+                checksum = Some(entry.checksum);
+                entry.copy_name_into(&mut lfn);
+                continue;
+            }
+
+            let entry = raw.regular();
+            if entry.is_volume_id() {
+                lfn.clear();
+                start = None;
+                continue;
+            }
+
+            if checksum != Some(entry.lfn_checksum()) {
+                // orphaned LFN entries belong to nothing
+                lfn.clear();
+                start = None;
+            }
+
+            let short_name = entry.short_name();
+            let name = if lfn.is_empty() {
+                short_name.clone()
+            } else {
+                decode_lfn(&lfn)
+            };
+            let location = EntryLocation {
+                dir: self.dir,
+                start: start.unwrap_or(offset),
+                offset,
+            };
+
+            return Some(Ok((self.make_entry(name, &entry, location), short_name)));
+        }
+
+        None
+    }
+
+    fn make_entry(
+        &self,
+        name: String,
+        entry: &VFatRegularDirEntry,
+        location: EntryLocation,
+    ) -> Entry<HANDLE> {
+        let metadata = entry.metadata();
+        if metadata.attributes.directory() {
+            // a `..` entry pointing at cluster 0 refers to the root directory
+            let cluster = match entry.cluster() {
+                cluster if cluster.is_valid() => cluster,
+                _ => self.vfat.lock(|vfat| vfat.root_cluster()),
+            };
+            Entry::Dir(Dir::new(
+                self.vfat.clone(),
+                name,
+                metadata,
+                cluster,
+                Some(location),
+            ))
+        } else {
+            Entry::File(File::new(
+                self.vfat.clone(),
+                name,
+                metadata,
+                entry.cluster(),
+                entry.file_size,
+                location,
+            ))
+        }
     }
 }
 
 impl<HANDLE: VFatHandle> traits::Dir for Dir<HANDLE> {
-    // FIXME: Implement `trait::Dir` for `Dir`.
+    type Entry = Entry<HANDLE>;
+    type Iter = EntryIter<HANDLE>;
+
+    fn entries(&self) -> io::Result<Self::Iter> {
+        Ok(EntryIter {
+            vfat: self.vfat.clone(),
+            dir: self.first_cluster,
+            entries: RawEntries::new(self.vfat.clone(), self.first_cluster)?,
+        })
+    }
 }
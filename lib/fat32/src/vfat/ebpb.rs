@@ -1,12 +1,44 @@
 use core::fmt;
+use core::mem;
 use shim::const_assert_size;
 
 use crate::traits::BlockDevice;
-use crate::vfat::Error;
+use crate::vfat::{Error, FatType};
 
 #[repr(C, packed)]
 pub struct BiosParameterBlock {
-    // FIXME: Fill me in.
+    pub jump: [u8; 3],
+    pub oem_id: [u8; 8],
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub num_fats: u8,
+    pub max_dir_entries: u16,
+    pub total_logical_sectors_16: u16,
+    pub media_descriptor: u8,
+    pub sectors_per_fat_16: u16,
+    pub sectors_per_track: u16,
+    pub num_heads: u16,
+    pub hidden_sectors: u32,
+    pub total_logical_sectors_32: u32,
+
+    // extended section. only FAT32 volumes use this layout; on FAT12 and FAT16
+    // volumes these bytes hold a shorter extended boot record instead
+    pub sectors_per_fat: u32,
+    pub flags: u16,
+    pub version: u16,
+    pub root_cluster: u32,
+    pub fsinfo_sector: u16,
+    pub backup_boot_sector: u16,
+    pub reserved: [u8; 12],
+    pub drive_number: u8,
+    pub nt_flags: u8,
+    pub signature: u8,
+    pub volume_id: u32,
+    pub volume_label: [u8; 11],
+    pub system_id: [u8; 8],
+    pub boot_code: [u8; 420],
+    pub bootable_signature: [u8; 2],
 }
 
 const_assert_size!(BiosParameterBlock, 512);
@@ -19,12 +51,101 @@ impl BiosParameterBlock {
     ///
     /// If the EBPB signature is invalid, returns an error of `BadSignature`.
     pub fn from<T: BlockDevice>(mut device: T, sector: u64) -> Result<BiosParameterBlock, Error> {
-        unimplemented!("BiosParameterBlock::from()")
+        let mut buf = [0u8; 512];
+        device.read_sector(sector, &mut buf)?;
+
+        let ebpb: BiosParameterBlock = unsafe { mem::transmute(buf) };
+        if ebpb.bootable_signature != [0x55, 0xAA] {
+            return Err(Error::BadSignature);
+        }
+
+        Ok(ebpb)
+    }
+
+    /// Returns `true` if the fields describe a plausible FAT volume. Boot
+    /// sectors of other file systems, such as exFAT and NTFS, share the boot
+    /// signature but fail this check.
+    pub fn is_fat(&self) -> bool {
+        let bytes_per_sector = self.bytes_per_sector;
+        bytes_per_sector.is_power_of_two()
+            && (512..=4096).contains(&bytes_per_sector)
+            && self.sectors_per_cluster.is_power_of_two()
+            && self.reserved_sectors != 0
+            && self.num_fats != 0
+            && self.fat_size() != 0
+            && self.total_sectors() != 0
+    }
+
+    /// The total number of logical sectors in the volume.
+    pub fn total_sectors(&self) -> u32 {
+        match self.total_logical_sectors_16 {
+            0 => self.total_logical_sectors_32,
+            num => num as u32,
+        }
+    }
+
+    /// The number of sectors occupied by one FAT.
+    pub fn fat_size(&self) -> u32 {
+        match self.sectors_per_fat_16 {
+            0 => self.sectors_per_fat,
+            num => num as u32,
+        }
+    }
+
+    /// The number of sectors occupied by the fixed root directory region.
+    /// Always 0 on FAT32 volumes.
+    pub fn root_dir_sectors(&self) -> u32 {
+        let bytes_per_sector = self.bytes_per_sector as u32;
+        (self.max_dir_entries as u32 * 32).div_ceil(bytes_per_sector)
+    }
+
+    /// The logical sector where the data region begins.
+    pub fn data_start_sector(&self) -> u32 {
+        self.reserved_sectors as u32
+            + self.num_fats as u32 * self.fat_size()
+            + self.root_dir_sectors()
+    }
+
+    /// The number of clusters in the data region.
+    pub fn cluster_count(&self) -> u32 {
+        let data_sectors = self
+            .total_sectors()
+            .saturating_sub(self.data_start_sector());
+        data_sectors / self.sectors_per_cluster as u32
+    }
+
+    /// The type of FAT used by the volume, determined by its cluster count.
+    pub fn fat_type(&self) -> FatType {
+        FatType::from_cluster_count(self.cluster_count())
     }
 }
 
 impl fmt::Debug for BiosParameterBlock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        unimplemented!("BiosParameterBlock::fmt()")
+        f.debug_struct("BiosParameterBlock")
+            .field("oem_id", &self.oem_id)
+            .field("bytes_per_sector", &{ self.bytes_per_sector })
+            .field("sectors_per_cluster", &self.sectors_per_cluster)
+            .field("reserved_sectors", &{ self.reserved_sectors })
+            .field("num_fats", &self.num_fats)
+            .field("max_dir_entries", &{ self.max_dir_entries })
+            .field("total_sectors", &self.total_sectors())
+            .field("media_descriptor", &self.media_descriptor)
+            .field("sectors_per_track", &{ self.sectors_per_track })
+            .field("num_heads", &{ self.num_heads })
+            .field("hidden_sectors", &{ self.hidden_sectors })
+            .field("sectors_per_fat", &{ self.sectors_per_fat })
+            .field("flags", &{ self.flags })
+            .field("version", &{ self.version })
+            .field("root_cluster", &{ self.root_cluster })
+            .field("fsinfo_sector", &{ self.fsinfo_sector })
+            .field("backup_boot_sector", &{ self.backup_boot_sector })
+            .field("drive_number", &self.drive_number)
+            .field("signature", &self.signature)
+            .field("volume_id", &{ self.volume_id })
+            .field("volume_label", &self.volume_label)
+            .field("system_id", &self.system_id)
+            .field("bootable_signature", &self.bootable_signature)
+            .finish()
     }
 }
@@ -1,6 +1,8 @@
 use crate::traits;
-use crate::vfat::{Dir, File, Metadata, VFatHandle};
-use core::fmt;
+use crate::vfat::dir::EntryLocation;
+use shim::io;
+
+use crate::vfat::{Attributes, Dir, File, Metadata, VFatHandle};
 
 // You can change this definition if you want
 #[derive(Debug)]
@@ -9,8 +11,74 @@ pub enum Entry<HANDLE: VFatHandle> {
     Dir(Dir<HANDLE>),
 }
 
-// TODO: Implement any useful helper methods on `Entry`.
+impl<HANDLE: VFatHandle> Entry<HANDLE> {
+    /// Where the entry's regular directory entry lives on disk. `None` for the
+    /// root directory.
+    pub(crate) fn location(&self) -> Option<EntryLocation> {
+        match self {
+            Entry::File(file) => Some(file.location),
+            Entry::Dir(dir) => dir.location,
+        }
+    }
+
+    /// Replaces the entry's read-only, hidden, system and archive attributes
+    /// with those in `attributes`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if the entry is the root directory.
+    pub fn set_attributes(&mut self, attributes: Attributes) -> io::Result<()> {
+        match self {
+            Entry::File(file) => file.set_attributes(attributes),
+            Entry::Dir(dir) => dir.set_attributes(attributes),
+        }
+    }
+}
 
 impl<HANDLE: VFatHandle> traits::Entry for Entry<HANDLE> {
-    // FIXME: Implement `traits::Entry` for `Entry`.
+    type File = File<HANDLE>;
+    type Dir = Dir<HANDLE>;
+    type Metadata = Metadata;
+
+    fn name(&self) -> &str {
+        match self {
+            Entry::File(file) => &file.name,
+            Entry::Dir(dir) => &dir.name,
+        }
+    }
+
+    fn metadata(&self) -> &Self::Metadata {
+        match self {
+            Entry::File(file) => &file.metadata,
+            Entry::Dir(dir) => &dir.metadata,
+        }
+    }
+
+    fn as_file(&self) -> Option<&File<HANDLE>> {
+        match self {
+            Entry::File(file) => Some(file),
+            Entry::Dir(_) => None,
+        }
+    }
+
+    fn as_dir(&self) -> Option<&Dir<HANDLE>> {
+        match self {
+            Entry::File(_) => None,
+            Entry::Dir(dir) => Some(dir),
+        }
+    }
+
+    fn into_file(self) -> Option<File<HANDLE>> {
+        match self {
+            Entry::File(file) => Some(file),
+            Entry::Dir(_) => None,
+        }
+    }
+
+    fn into_dir(self) -> Option<Dir<HANDLE>> {
+        match self {
+            Entry::File(_) => None,
+            Entry::Dir(dir) => Some(dir),
+        }
+    }
 }
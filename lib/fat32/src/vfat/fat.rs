@@ -19,13 +19,93 @@ pub enum Status {
     Eoc(u32),
 }
 
+/// The variant of FAT used by a file system.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// Determines the FAT type of a volume with `count` data clusters, as
+    /// described in the FAT specification. The cluster count is the only
+    /// thing that determines the type.
+    pub fn from_cluster_count(count: u32) -> FatType {
+        match count {
+            0..=4084 => FatType::Fat12,
+            4085..=65524 => FatType::Fat16,
+            _ => FatType::Fat32,
+        }
+    }
+
+    /// The width of a FAT entry in bits.
+    pub fn entry_bits(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32,
+        }
+    }
+
+    /// Widens the raw FAT entry `raw` into a `FatEntry`, mapping the special
+    /// values of FAT12 and FAT16 onto their FAT32 equivalents.
+    pub(crate) fn widen(&self, raw: u32) -> FatEntry {
+        match self {
+            FatType::Fat12 if raw >= 0xFF0 => FatEntry(raw | 0x0FFFF000),
+            FatType::Fat16 if raw >= 0xFFF0 => FatEntry(raw | 0x0FFF0000),
+            _ => FatEntry(raw),
+        }
+    }
+
+    /// The bit of FAT[1] that is set when the volume was cleanly unmounted
+    /// and cleared while it is dirty. FAT12 has no such bit.
+    pub(crate) fn clean_shutdown_bit(&self) -> Option<u32> {
+        match self {
+            FatType::Fat12 => None,
+            FatType::Fat16 => Some(0x8000),
+            FatType::Fat32 => Some(0x0800_0000),
+        }
+    }
+
+    /// Narrows `entry` back into a raw FAT entry of this type.
+    pub(crate) fn narrow(&self, entry: &FatEntry) -> u32 {
+        match self {
+            FatType::Fat12 => entry.0 & 0xFFF,
+            FatType::Fat16 => entry.0 & 0xFFFF,
+            FatType::Fat32 => entry.0,
+        }
+    }
+}
+
 #[repr(C, packed)]
 pub struct FatEntry(pub u32);
 
 impl FatEntry {
     /// Returns the `Status` of the FAT entry `self`.
     pub fn status(&self) -> Status {
-        unimplemented!("FatEntry::status()")
+        // the top 4 bits of a FAT32 entry are reserved and ignored
+        match self.0 & 0x0FFFFFFF {
+            0x00000000 => Free,
+            0x00000001 => Reserved,
+            num @ 0x00000002..=0x0FFFFFEF => Data(Cluster::from(num)),
+            0x0FFFFFF0..=0x0FFFFFF6 => Reserved,
+            0x0FFFFFF7 => Bad,
+            num => Eoc(num),
+        }
+    }
+
+    /// Sets the status of the FAT entry `self` to `status`. The reserved top 4
+    /// bits of the entry are preserved.
+    pub fn set_status(&mut self, status: Status) {
+        let value = match status {
+            Free => 0x00000000,
+            Reserved => 0x0FFFFFF0,
+            Data(cluster) => cluster.num(),
+            Bad => 0x0FFFFFF7,
+            Eoc(num) => num & 0x0FFFFFFF,
+        };
+        self.0 = (self.0 & 0xF0000000) | value;
     }
 }
 
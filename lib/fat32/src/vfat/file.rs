@@ -1,17 +1,347 @@
+use alloc::collections::BTreeMap;
 use alloc::string::String;
 
 use shim::io::{self, SeekFrom};
+use shim::ioerr;
+use shim::newioerr;
 
 use crate::traits;
-use crate::vfat::{Cluster, Metadata, VFatHandle};
+use crate::vfat::dir::{self, EntryLocation};
+use crate::vfat::{Attributes, Cluster, Metadata, VFatHandle};
+
+/// How many clusters apart checkpoints are recorded at first, and the most
+/// checkpoints kept for a file. Once there would be more, the interval is
+/// doubled and the checkpoints that no longer fall on it are dropped.
+const CHECKPOINT_INTERVAL: u64 = 16;
+const MAX_CHECKPOINTS: usize = 256;
 
 #[derive(Debug)]
 pub struct File<HANDLE: VFatHandle> {
     pub vfat: HANDLE,
-    // FIXME: Fill me in.
+    pub(crate) name: String,
+    pub(crate) metadata: Metadata,
+    /// The first cluster of the file's data. Invalid if the file is empty.
+    pub(crate) first_cluster: Cluster,
+    pub(crate) size: u32,
+    pub(crate) location: EntryLocation,
+    /// The current position in the file.
+    position: u64,
+    /// The most recently visited cluster and its index in the chain.
+    cursor: Option<(u64, Cluster)>,
+    /// Clusters of the chain by their index, recorded every
+    /// `checkpoint_interval` clusters as the chain is walked, so that a seek
+    /// backwards does not walk the chain from its start.
+    checkpoints: BTreeMap<u64, Cluster>,
+    checkpoint_interval: u64,
+}
+
+impl<HANDLE: VFatHandle> File<HANDLE> {
+    pub(crate) fn new(
+        vfat: HANDLE,
+        name: String,
+        metadata: Metadata,
+        first_cluster: Cluster,
+        size: u32,
+        location: EntryLocation,
+    ) -> File<HANDLE> {
+        File {
+            vfat,
+            name,
+            metadata,
+            first_cluster,
+            size,
+            location,
+            position: 0,
+            cursor: None,
+            checkpoints: BTreeMap::new(),
+            checkpoint_interval: CHECKPOINT_INTERVAL,
+        }
+    }
+
+    /// Returns the cluster holding byte `position` of the file, walking the
+    /// chain from the closest known cluster before it: the cursor or a
+    /// checkpoint, and the first cluster otherwise.
+    fn cluster_at(&mut self, position: u64, cluster_size: u64) -> io::Result<Cluster> {
+        let index = position / cluster_size;
+        let mut start = match self.checkpoints.range(..=index).next_back() {
+            Some((&i, &cluster)) => (i, cluster),
+            None => (0, self.first_cluster),
+        };
+        if let Some((i, cluster)) = self.cursor {
+            if i <= index && i > start.0 {
+                start = (i, cluster);
+            }
+        }
+
+        let (mut i, mut cluster) = start;
+        let interval = self.checkpoint_interval;
+        let checkpoints = &mut self.checkpoints;
+        let cluster = self.vfat.lock(|vfat| -> io::Result<Cluster> {
+            while i < index {
+                cluster = vfat
+                    .next_cluster(cluster)?
+                    .ok_or_else(|| newioerr!(UnexpectedEof, "cluster chain ended early"))?;
+                i += 1;
+                if i.is_multiple_of(interval) {
+                    checkpoints.insert(i, cluster);
+                }
+            }
+            Ok(cluster)
+        })?;
+
+        while self.checkpoints.len() > MAX_CHECKPOINTS {
+            let interval = self.checkpoint_interval * 2;
+            self.checkpoints.retain(|i, _| i.is_multiple_of(interval));
+            self.checkpoint_interval = interval;
+        }
+
+        self.cursor = Some((index, cluster));
+        Ok(cluster)
+    }
+
+    /// Makes sure the file has enough clusters allocated to hold `len` bytes.
+    /// Returns the number of bytes it can hold, which is less than `len` if
+    /// the journal ran out of room first.
+    fn reserve(&mut self, len: u64, cluster_size: u64) -> io::Result<u64> {
+        let allocated = (self.size as u64).div_ceil(cluster_size);
+        let needed = len.div_ceil(cluster_size);
+        if needed <= allocated && self.first_cluster.is_valid() {
+            return Ok(len);
+        }
+
+        let first_cluster = self.first_cluster;
+        let (first_cluster, clusters) = self.vfat.lock(|vfat| -> io::Result<(Cluster, u64)> {
+            let first_cluster = if first_cluster.is_valid() {
+                first_cluster
+            } else {
+                vfat.alloc_cluster(None)?
+            };
+            let clusters = vfat.reserve_chain(first_cluster, needed)?;
+            Ok((first_cluster, clusters))
+        })?;
+        self.first_cluster = first_cluster;
+
+        Ok((clusters * cluster_size).min(len))
+    }
+
+    /// Records the current size and first cluster in the file's directory
+    /// entry.
+    fn update_entry(&mut self) -> io::Result<()> {
+        self.metadata =
+            dir::update_regular_entry(&self.vfat, self.location, self.first_cluster, self.size)?;
+        Ok(())
+    }
+
+    /// Replaces the file's read-only, hidden, system and archive attributes
+    /// with those in `attributes`.
+    pub fn set_attributes(&mut self, attributes: Attributes) -> io::Result<()> {
+        self.vfat.lock(|vfat| vfat.begin_operation())?;
+        self.metadata = dir::update_attributes(&self.vfat, self.location, attributes)?;
+        Ok(())
+    }
+
+    /// Starts an operation that changes the file. Returns an error of
+    /// `PermissionDenied` if the file or the file system is read only.
+    fn begin_operation(&self) -> io::Result<()> {
+        self.vfat.lock(|vfat| vfat.begin_operation())?;
+        match self.metadata.attributes.read_only() {
+            true => ioerr!(PermissionDenied, "file is read only"),
+            false => Ok(()),
+        }
+    }
+
+    /// Records today as the file's access date if the file system updates
+    /// access dates and the file was last accessed on another day.
+    fn mark_accessed(&mut self) -> io::Result<()> {
+        let now = self
+            .vfat
+            .lock(|vfat| vfat.now().filter(|_| vfat.updates_access_time()));
+        let today = match now {
+            Some(now) => now.date,
+            None => return Ok(()),
+        };
+        if self.metadata.accessed.date != today {
+            self.vfat.lock(|vfat| vfat.begin_operation())?;
+            self.metadata = dir::update_accessed(&self.vfat, self.location, today)?;
+        }
+        Ok(())
+    }
+
+    /// Truncates or extends the file so that it is `size` bytes long. Extended
+    /// files are filled with zeroes. The position in the file is clamped to
+    /// the new size.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `size` is larger than the largest
+    /// file FAT32 can hold and an error of `PermissionDenied` if the file is
+    /// read only.
+    pub fn set_len(&mut self, size: u64) -> io::Result<()> {
+        self.begin_operation()?;
+        if size > u32::MAX as u64 {
+            return ioerr!(InvalidInput, "file size is too large for FAT32");
+        }
+
+        let current = self.size as u64;
+        let cluster_size = self.vfat.lock(|vfat| vfat.cluster_size()) as u64;
+        if size > current {
+            // new clusters are zeroed as they are allocated, so only the rest
+            // of the old last cluster, which may hold stale data, is cleared
+            let tail = current.next_multiple_of(cluster_size).min(size) - current;
+            if tail > 0 {
+                let position = self.position;
+                self.position = current;
+                self.write_data(&vec![0u8; tail as usize])?;
+                self.position = position;
+            }
+
+            // with a journal, the file grows in steps that each fit in it
+            while (self.size as u64) < size {
+                let reserved = self.reserve(size, cluster_size)?;
+                if reserved <= self.size as u64 {
+                    return ioerr!(Other, "the journal is too small to grow the file");
+                }
+                self.size = reserved.min(size) as u32;
+                self.update_entry()?;
+                if (self.size as u64) < size {
+                    self.vfat.lock(|vfat| vfat.commit())?;
+                }
+            }
+            return Ok(());
+        }
+
+        // the entry is updated before the clusters are freed, which with a
+        // journal may be committed in several steps
+        let keep = size.div_ceil(cluster_size);
+        let first_cluster = self.first_cluster;
+        if keep == 0 {
+            self.first_cluster = Cluster::from(0);
+        }
+        self.size = size as u32;
+        self.position = self.position.min(size);
+        self.cursor = None;
+        self.checkpoints.retain(|&i, _| i < keep);
+        self.update_entry()?;
+
+        if first_cluster.is_valid() {
+            if keep == 0 {
+                self.vfat.lock(|vfat| vfat.free_chain(first_cluster))?;
+            } else {
+                self.vfat
+                    .lock(|vfat| vfat.truncate_chain(first_cluster, keep))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `buf` at the current position as part of an operation that
+    /// has already started, extending the file if needed.
+    fn write_data(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // FAT32 file sizes are limited to 32 bits
+        let available = (u32::MAX as u64).saturating_sub(self.position);
+        let len = (buf.len() as u64).min(available) as usize;
+        if len == 0 {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            return ioerr!(Other, "file size is too large for FAT32");
+        }
+
+        // with a journal, a write that would not fit in it stops short
+        let cluster_size = self.vfat.lock(|vfat| vfat.cluster_size()) as u64;
+        let reserved = self.reserve(self.position + len as u64, cluster_size)?;
+        let len = match reserved.saturating_sub(self.position) as usize {
+            0 => return ioerr!(Other, "the journal is too small for the write"),
+            reserved => len.min(reserved),
+        };
+
+        let mut written = 0;
+        while written < len {
+            let cluster = self.cluster_at(self.position, cluster_size)?;
+            let offset = (self.position % cluster_size) as usize;
+            let n = if offset == 0 && (len - written) as u64 >= cluster_size {
+                // whole clusters are written a contiguous run at a time
+                let (clusters, last) = self
+                    .vfat
+                    .lock(|vfat| vfat.write_clusters(cluster, &buf[written..len]))?;
+                self.cursor = Some((self.position / cluster_size + clusters - 1, last));
+                (clusters * cluster_size) as usize
+            } else {
+                self.vfat
+                    .lock(|vfat| vfat.write_cluster(cluster, offset, &buf[written..len]))?
+            };
+
+            written += n;
+            self.position += n as u64;
+        }
+
+        if self.position > self.size as u64 {
+            self.size = self.position as u32;
+        }
+        self.update_entry()?;
+
+        Ok(written)
+    }
+}
+
+impl<HANDLE: VFatHandle> traits::File for File<HANDLE> {
+    fn sync(&mut self) -> io::Result<()> {
+        self.vfat.lock(|vfat| vfat.sync())
+    }
+
+    fn size(&self) -> u64 {
+        self.size as u64
+    }
+
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        File::set_len(self, size)
+    }
+}
+
+impl<HANDLE: VFatHandle> io::Read for File<HANDLE> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = (self.size as u64).saturating_sub(self.position);
+        let len = (buf.len() as u64).min(remaining) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let cluster_size = self.vfat.lock(|vfat| vfat.cluster_size()) as u64;
+        let mut read = 0;
+        while read < len {
+            let cluster = self.cluster_at(self.position, cluster_size)?;
+            let offset = (self.position % cluster_size) as usize;
+            let n = if offset == 0 && (len - read) as u64 >= cluster_size {
+                // whole clusters are read a contiguous run at a time
+                let (clusters, last) = self
+                    .vfat
+                    .lock(|vfat| vfat.read_clusters(cluster, &mut buf[read..len]))?;
+                self.cursor = Some((self.position / cluster_size + clusters - 1, last));
+                (clusters * cluster_size) as usize
+            } else {
+                self.vfat
+                    .lock(|vfat| vfat.read_cluster(cluster, offset, &mut buf[read..len]))?
+            };
+
+            read += n;
+            self.position += n as u64;
+        }
+
+        self.mark_accessed()?;
+        Ok(read)
+    }
 }
 
-// FIXME: Implement `traits::File` (and its supertraits) for `File`.
+impl<HANDLE: VFatHandle> io::Write for File<HANDLE> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.begin_operation()?;
+        self.write_data(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        traits::File::sync(self)
+    }
+}
 
 impl<HANDLE: VFatHandle> io::Seek for File<HANDLE> {
     /// Seek to offset `pos` in the file.
@@ -27,7 +357,18 @@ impl<HANDLE: VFatHandle> io::Seek for File<HANDLE> {
     ///
     /// Seeking before the start of a file or beyond the end of the file results
     /// in an `InvalidInput` error.
-    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
-        unimplemented!("File::seek()")
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => offset as i128,
+            SeekFrom::End(offset) => self.size as i128 + offset as i128,
+            SeekFrom::Current(offset) => self.position as i128 + offset as i128,
+        };
+
+        if position < 0 || position > self.size as i128 {
+            return ioerr!(InvalidInput, "seek outside of the file");
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
     }
 }
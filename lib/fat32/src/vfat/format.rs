@@ -0,0 +1,250 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem;
+
+use shim::io;
+use shim::ioerr;
+
+use crate::mbr::{MasterBootRecord, PartitionEntry};
+use crate::traits::BlockDevice;
+use crate::vfat::dir::VFatRegularDirEntry;
+use crate::vfat::name;
+use crate::vfat::{Attributes, BiosParameterBlock, Cluster, FsInfo};
+
+/// Options for [`format`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The size of a cluster in bytes. It must be a power of two multiple of
+    /// the sector size and at most 64 KiB. `None` picks a size from the size
+    /// of the volume, following Microsoft's defaults.
+    pub cluster_size: Option<u32>,
+    /// The volume label, which is stored in uppercase. `None` leaves the
+    /// volume unlabelled.
+    pub volume_label: Option<String>,
+    /// The volume serial number.
+    pub volume_id: u32,
+    /// Whether to write an MBR with a single FAT32 partition covering the
+    /// device. Otherwise the device is formatted as a "superfloppy", with the
+    /// boot sector at sector 0.
+    pub partitioned: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions {
+            cluster_size: None,
+            volume_label: None,
+            volume_id: 0,
+            partitioned: true,
+        }
+    }
+}
+
+/// The first sector of the partition written by `format`, which keeps it
+/// aligned to 1 MiB with 512 byte sectors.
+const PARTITION_START: u64 = 2048;
+
+/// The number of reserved sectors before the first FAT.
+const RESERVED_SECTORS: u16 = 32;
+const NUM_FATS: u8 = 2;
+/// Where the FSInfo structure and the backup boot sector are stored. The
+/// backup FSInfo structure follows the backup boot sector.
+const FSINFO_SECTOR: u16 = 1;
+const BACKUP_BOOT_SECTOR: u16 = 6;
+
+const MEDIA_DESCRIPTOR: u8 = 0xF8;
+
+/// The fewest and most clusters a FAT32 volume may have.
+const MIN_CLUSTERS: u64 = 65525;
+const MAX_CLUSTERS: u64 = 0x0FFFFFF5;
+
+/// The most sectors written at once while zeroing a region.
+const ZERO_CHUNK_SECTORS: u64 = 64;
+
+/// Formats the first `num_sectors` sectors of `device` as an empty FAT32 file
+/// system: an MBR if `options.partitioned` is set, the boot sector and its
+/// backup, FSInfo, both FATs and an empty root directory. The data region is
+/// not cleared.
+///
+/// # Errors
+///
+/// Returns an error of `InvalidInput` if the device is too small or too
+/// large to hold a FAT32 file system with the chosen cluster size, if the
+/// cluster size or sector size is invalid or if the volume label is invalid.
+/// Returns any error that occurs while writing to `device`.
+pub fn format<T: BlockDevice>(
+    mut device: T,
+    num_sectors: u64,
+    options: &FormatOptions,
+) -> io::Result<()> {
+    let sector_size = device.sector_size();
+    if !sector_size.is_power_of_two() || !(512..=4096).contains(&sector_size) {
+        return ioerr!(InvalidInput, "sector size must be 512 to 4096 bytes");
+    }
+
+    let label = match &options.volume_label {
+        Some(label) => Some(name::volume_label(label)?),
+        None => None,
+    };
+
+    let start = match options.partitioned {
+        true => PARTITION_START,
+        false => 0,
+    };
+    let total_sectors = num_sectors.saturating_sub(start);
+    if total_sectors > u32::MAX as u64 {
+        return ioerr!(InvalidInput, "device is too large for FAT32");
+    }
+
+    let sectors_per_cluster = match options.cluster_size {
+        Some(size) => {
+            let size = size as u64;
+            if !size.is_power_of_two() || size < sector_size || size > 64 * 1024 {
+                return ioerr!(InvalidInput, "invalid cluster size");
+            }
+            size / sector_size
+        }
+        None => (default_cluster_size(total_sectors * sector_size) / sector_size).max(1),
+    };
+
+    let (sectors_per_fat, clusters) = fat_layout(total_sectors, sector_size, sectors_per_cluster);
+    if !(MIN_CLUSTERS..=MAX_CLUSTERS).contains(&clusters) {
+        return ioerr!(
+            InvalidInput,
+            "device size does not fit FAT32 with this cluster size"
+        );
+    }
+
+    let ebpb = BiosParameterBlock {
+        jump: [0xEB, 0x58, 0x90],
+        oem_id: *b"RUSTOS  ",
+        bytes_per_sector: sector_size as u16,
+        sectors_per_cluster: sectors_per_cluster as u8,
+        reserved_sectors: RESERVED_SECTORS,
+        num_fats: NUM_FATS,
+        max_dir_entries: 0,
+        total_logical_sectors_16: 0,
+        media_descriptor: MEDIA_DESCRIPTOR,
+        sectors_per_fat_16: 0,
+        sectors_per_track: 63,
+        num_heads: 255,
+        hidden_sectors: start as u32,
+        total_logical_sectors_32: total_sectors as u32,
+        sectors_per_fat: sectors_per_fat as u32,
+        flags: 0,
+        version: 0,
+        root_cluster: 2,
+        fsinfo_sector: FSINFO_SECTOR,
+        backup_boot_sector: BACKUP_BOOT_SECTOR,
+        reserved: [0; 12],
+        drive_number: 0x80,
+        nt_flags: 0,
+        signature: 0x29,
+        volume_id: options.volume_id,
+        volume_label: label.unwrap_or(*b"NO NAME    "),
+        system_id: *b"FAT32   ",
+        boot_code: [0; 420],
+        bootable_signature: [0x55, 0xAA],
+    };
+
+    // the root directory takes the first cluster
+    let fsinfo = FsInfo::new(clusters as u32 - 1, 3);
+
+    // everything is written through a sector sized buffer so that devices
+    // with larger sectors get zero padding
+    let mut sector = vec![0u8; sector_size as usize];
+
+    if options.partitioned {
+        let mut mbr = MasterBootRecord::empty();
+        mbr.disk_id[4..8].copy_from_slice(&options.volume_id.to_le_bytes());
+        mbr.partition_table[0] = PartitionEntry::new(0x0C, start as u32, total_sectors as u32);
+        mbr.signature = [0x55, 0xAA];
+        put(&mut sector, &mbr.into_bytes());
+        device.write_sector(0, &sector)?;
+    }
+
+    zero_sectors(&mut device, start, RESERVED_SECTORS as u64)?;
+    let boot: [u8; 512] = unsafe { mem::transmute(ebpb) };
+    for boot_sector in [0, BACKUP_BOOT_SECTOR as u64] {
+        put(&mut sector, &boot);
+        device.write_sector(start + boot_sector, &sector)?;
+        put(&mut sector, &fsinfo.to_bytes());
+        device.write_sector(start + boot_sector + FSINFO_SECTOR as u64, &sector)?;
+    }
+
+    // every cluster is free except the root directory's
+    let fat_start = start + RESERVED_SECTORS as u64;
+    let mut first_entries = Vec::new();
+    first_entries.extend_from_slice(&(0x0FFFFF00 | MEDIA_DESCRIPTOR as u32).to_le_bytes());
+    first_entries.extend_from_slice(&0x0FFFFFFFu32.to_le_bytes());
+    first_entries.extend_from_slice(&0x0FFFFFFFu32.to_le_bytes());
+    for fat in 0..NUM_FATS as u64 {
+        let fat_sector = fat_start + fat * sectors_per_fat;
+        zero_sectors(&mut device, fat_sector, sectors_per_fat)?;
+        put(&mut sector, &first_entries);
+        device.write_sector(fat_sector, &sector)?;
+    }
+
+    let root_start = fat_start + NUM_FATS as u64 * sectors_per_fat;
+    zero_sectors(&mut device, root_start, sectors_per_cluster)?;
+    if let Some(label) = label {
+        let mut entry = VFatRegularDirEntry::new(Attributes::VOLUME_ID, Cluster::from(0));
+        entry.set_short_name(&label);
+        put(&mut sector, &entry.to_bytes());
+        device.write_sector(root_start, &sector)?;
+    }
+
+    Ok(())
+}
+
+/// Clears `sector` and copies `bytes` into its start.
+fn put(sector: &mut [u8], bytes: &[u8]) {
+    sector.fill(0);
+    sector[..bytes.len()].copy_from_slice(bytes);
+}
+
+/// Overwrites the `count` sectors starting at `start` with zeroes.
+fn zero_sectors<T: BlockDevice>(mut device: T, start: u64, count: u64) -> io::Result<()> {
+    let sector_size = device.sector_size();
+    let zeroes = vec![0u8; (ZERO_CHUNK_SECTORS * sector_size) as usize];
+    let mut done = 0;
+    while done < count {
+        let n = (count - done).min(ZERO_CHUNK_SECTORS);
+        device.write_sectors(start + done, n, &zeroes[..(n * sector_size) as usize])?;
+        done += n;
+    }
+    Ok(())
+}
+
+/// The cluster size Microsoft's tools pick for a FAT32 volume of `size`
+/// bytes.
+fn default_cluster_size(size: u64) -> u64 {
+    const MIB: u64 = 1024 * 1024;
+    match size {
+        s if s <= 260 * MIB => 512,
+        s if s <= 8 * 1024 * MIB => 4096,
+        s if s <= 16 * 1024 * MIB => 8192,
+        s if s <= 32 * 1024 * MIB => 16384,
+        _ => 32768,
+    }
+}
+
+/// Returns the sectors per FAT and the number of clusters of a volume of
+/// `total_sectors` sectors. Each FAT must have an entry for every cluster,
+/// and every sector given to the FATs is taken away from the clusters.
+fn fat_layout(total_sectors: u64, sector_size: u64, sectors_per_cluster: u64) -> (u64, u64) {
+    let clusters_for = |sectors_per_fat: u64| {
+        let used = RESERVED_SECTORS as u64 + NUM_FATS as u64 * sectors_per_fat;
+        total_sectors.saturating_sub(used) / sectors_per_cluster
+    };
+
+    let mut sectors_per_fat = 1;
+    loop {
+        let clusters = clusters_for(sectors_per_fat);
+        let needed = ((clusters + 2) * 4).div_ceil(sector_size);
+        if needed <= sectors_per_fat {
+            return (sectors_per_fat, clusters);
+        }
+        sectors_per_fat = needed;
+    }
+}
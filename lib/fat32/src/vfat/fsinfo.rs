@@ -0,0 +1,99 @@
+use core::fmt;
+use core::mem;
+use shim::const_assert_size;
+
+/// The FSInfo sector of a FAT32 volume, which caches the number of free
+/// clusters and where to start looking for one.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct FsInfo {
+    lead_signature: u32,
+    reserved_1: [u8; 480],
+    struct_signature: u32,
+    free_count: u32,
+    next_free: u32,
+    reserved_2: [u8; 12],
+    trail_signature: u32,
+}
+
+const_assert_size!(FsInfo, 512);
+
+const LEAD_SIGNATURE: u32 = 0x41615252;
+const STRUCT_SIGNATURE: u32 = 0x61417272;
+const TRAIL_SIGNATURE: u32 = 0xAA550000;
+
+/// Stored in place of a count or cluster number that is not known.
+const UNKNOWN: u32 = 0xFFFFFFFF;
+
+impl FsInfo {
+    /// Returns an FSInfo structure recording `free_count` free clusters and
+    /// `next_free` as the place to start looking for one.
+    pub(crate) fn new(free_count: u32, next_free: u32) -> FsInfo {
+        FsInfo {
+            lead_signature: LEAD_SIGNATURE,
+            reserved_1: [0; 480],
+            struct_signature: STRUCT_SIGNATURE,
+            free_count,
+            next_free,
+            reserved_2: [0; 12],
+            trail_signature: TRAIL_SIGNATURE,
+        }
+    }
+
+    /// Reads the FSInfo structure from the first 512 bytes of `bytes`.
+    /// Returns `None` if any of its signatures are wrong.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<FsInfo> {
+        let mut raw = [0u8; 512];
+        raw.copy_from_slice(bytes.get(..512)?);
+
+        let fsinfo: FsInfo = unsafe { mem::transmute(raw) };
+        match (
+            fsinfo.lead_signature,
+            fsinfo.struct_signature,
+            fsinfo.trail_signature,
+        ) {
+            (LEAD_SIGNATURE, STRUCT_SIGNATURE, TRAIL_SIGNATURE) => Some(fsinfo),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_bytes(self) -> [u8; 512] {
+        unsafe { mem::transmute(self) }
+    }
+
+    /// The recorded number of free clusters, or `None` if it is not known.
+    pub(crate) fn free_count(&self) -> Option<u32> {
+        match self.free_count {
+            UNKNOWN => None,
+            count => Some(count),
+        }
+    }
+
+    /// Records `count` free clusters, or that the count is unknown if `count`
+    /// is `None`.
+    pub(crate) fn set_free_count(&mut self, count: Option<u32>) {
+        self.free_count = count.unwrap_or(UNKNOWN);
+    }
+
+    /// The recorded cluster to start looking for a free cluster at, or `None`
+    /// if it is not known.
+    pub(crate) fn next_free(&self) -> Option<u32> {
+        match self.next_free {
+            UNKNOWN => None,
+            next => Some(next),
+        }
+    }
+
+    pub(crate) fn set_next_free(&mut self, next: u32) {
+        self.next_free = next;
+    }
+}
+
+impl fmt::Debug for FsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FsInfo")
+            .field("free_count", &{ self.free_count })
+            .field("next_free", &{ self.next_free })
+            .finish()
+    }
+}
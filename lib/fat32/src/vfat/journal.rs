@@ -0,0 +1,195 @@
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+use crate::util::crc32;
+use crate::vfat::Cluster;
+
+/// The 8.3 name of the file holding the journal in the root directory.
+pub(crate) const JOURNAL_NAME: &[u8; 11] = b"JOURNAL SYS";
+
+/// Identifies the header sector of a journal.
+const MAGIC: &[u8; 8] = b"FATJRNL1";
+
+/// The size of the header fields that come before the list of sectors.
+const HEADER_SIZE: usize = 24;
+
+/// A write-ahead journal of metadata changes, kept in a hidden system file.
+///
+/// The first sector of the file holds a header and the sectors after it hold
+/// the new contents of the sectors that a transaction changes. A list of
+/// sectors too long for the header continues in the sectors right after it,
+/// before the contents. A transaction is committed by writing those contents,
+/// then a header listing where they belong. Only then are the sectors written in place, after which the header
+/// is cleared. A header that lists sectors therefore describes changes that
+/// may be only partly in place and are replayed on the next mount, while a
+/// transaction that was interrupted before its header was written never
+/// touched its sectors and is dropped.
+#[derive(Debug)]
+pub(crate) struct Journal {
+    /// The first cluster of the journal file.
+    first_cluster: Cluster,
+    /// The logical sectors of the journal file in order, starting with the
+    /// header.
+    sectors: Vec<u64>,
+    /// The sequence number of the next transaction.
+    sequence: u64,
+}
+
+impl Journal {
+    /// Returns the journal held in the file starting at `first_cluster`,
+    /// whose sectors are `sectors`. There must be at least two.
+    pub(crate) fn new(first_cluster: Cluster, sectors: Vec<u64>) -> Journal {
+        assert!(sectors.len() >= 2, "a journal needs at least two sectors");
+        Journal {
+            first_cluster,
+            sectors,
+            sequence: 0,
+        }
+    }
+
+    pub(crate) fn first_cluster(&self) -> Cluster {
+        self.first_cluster
+    }
+
+    /// The sector holding the header.
+    pub(crate) fn header_sector(&self) -> u64 {
+        self.sectors[0]
+    }
+
+    /// The sectors that hold the contents of the sectors a transaction
+    /// changes.
+    pub(crate) fn log_sectors(&self) -> &[u64] {
+        &self.sectors[1..]
+    }
+
+    /// The most sectors a single transaction can change. Past the number
+    /// listed in the header, every sector of the journal holds either the
+    /// contents of one changed sector or the list entries of many.
+    pub(crate) fn capacity(&self, sector_size: usize) -> usize {
+        let log = self.log_sectors().len();
+        let in_header = (sector_size - HEADER_SIZE) / 8;
+        if log <= in_header {
+            return log;
+        }
+        let per_sector = sector_size / 8;
+        let rest = log - in_header;
+        let groups = rest / (per_sector + 1);
+        in_header + groups * per_sector + (rest % (per_sector + 1)).saturating_sub(1)
+    }
+
+    /// Returns the sequence number for a new transaction.
+    pub(crate) fn next_sequence(&mut self) -> u64 {
+        let sequence = self.sequence;
+        self.sequence += 1;
+        sequence
+    }
+
+    /// Continues numbering transactions after `sequence`.
+    pub(crate) fn resume_after(&mut self, sequence: u64) {
+        self.sequence = self.sequence.max(sequence + 1);
+    }
+}
+
+/// The contents of a journal header: which sectors a committed transaction
+/// changes and a checksum of their new contents. A record without sectors
+/// marks an empty journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Record {
+    pub sequence: u64,
+    /// The sectors changed, in the order their contents are logged.
+    pub targets: Vec<u64>,
+    checksum: u32,
+}
+
+impl Record {
+    /// Returns the record for transaction `sequence`, which changes `targets`
+    /// to the sector sized chunks of `data`.
+    pub(crate) fn new(sequence: u64, targets: Vec<u64>, data: &[u8]) -> Record {
+        let checksum = checksum(&targets, data);
+        Record {
+            sequence,
+            targets,
+            checksum,
+        }
+    }
+
+    /// Returns the record of an empty journal.
+    pub(crate) fn empty(sequence: u64) -> Record {
+        Record::new(sequence, Vec::new(), &[])
+    }
+
+    /// The number of sectors after the header that hold the rest of the list
+    /// of `count` changed sectors.
+    pub(crate) fn spilled_sectors(count: usize, sector_size: usize) -> usize {
+        (HEADER_SIZE + count * 8).div_ceil(sector_size) - 1
+    }
+
+    /// The number of sectors after the header that the record in header
+    /// sector `header` continues into. `None` if it is not a journal header.
+    pub(crate) fn spilled_from(header: &[u8]) -> Option<usize> {
+        if header.len() < HEADER_SIZE || &header[..8] != MAGIC {
+            return None;
+        }
+        let count = u32::from_le_bytes(header[16..20].try_into().unwrap()) as usize;
+        Some(Record::spilled_sectors(count, header.len()))
+    }
+
+    /// Parses a header sector followed by the sectors its list continues
+    /// into. Returns `None` if it is not a journal header or the list runs
+    /// past the end of `bytes`.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Record> {
+        if bytes.len() < HEADER_SIZE || &bytes[..8] != MAGIC {
+            return None;
+        }
+
+        let sequence = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let count = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(bytes[20..24].try_into().unwrap());
+        let list = bytes.get(HEADER_SIZE..HEADER_SIZE + count * 8)?;
+        let targets = list
+            .as_chunks::<8>()
+            .0
+            .iter()
+            .map(|&target| u64::from_le_bytes(target))
+            .collect();
+
+        Some(Record {
+            sequence,
+            targets,
+            checksum,
+        })
+    }
+
+    /// The header sector holding the record, followed by the sectors its list
+    /// continues into.
+    pub(crate) fn to_bytes(&self, sector_size: usize) -> Vec<u8> {
+        let spilled = Record::spilled_sectors(self.targets.len(), sector_size);
+        let mut bytes = vec![0u8; (1 + spilled) * sector_size];
+        bytes[..8].copy_from_slice(MAGIC);
+        bytes[8..16].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[16..20].copy_from_slice(&(self.targets.len() as u32).to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.checksum.to_le_bytes());
+        for (slot, target) in bytes[HEADER_SIZE..]
+            .as_chunks_mut::<8>()
+            .0
+            .iter_mut()
+            .zip(&self.targets)
+        {
+            *slot = target.to_le_bytes();
+        }
+        bytes
+    }
+
+    /// Whether `data` is the logged contents the record was committed with.
+    /// A mismatch means the journal was torn while being written.
+    pub(crate) fn matches(&self, data: &[u8]) -> bool {
+        checksum(&self.targets, data) == self.checksum
+    }
+}
+
+/// The checksum of a transaction changing `targets` to `data`.
+fn checksum(targets: &[u64], data: &[u8]) -> u32 {
+    let mut bytes: Vec<u8> = targets.iter().flat_map(|t| t.to_le_bytes()).collect();
+    bytes.extend_from_slice(data);
+    crc32(&bytes)
+}
@@ -1,7 +1,5 @@
 use core::fmt;
 
-use alloc::string::String;
-
 use crate::traits;
 
 /// A date as represented in FAT32 on-disk structures.
@@ -14,11 +12,162 @@ pub struct Date(u16);
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Time(u16);
 
+/// The earliest and latest years FAT timestamps can hold.
+const MIN_YEAR: usize = 1980;
+const MAX_YEAR: usize = 2107;
+
+/// Seconds between the Unix epoch and 1980-01-01 00:00:00.
+const FAT_EPOCH: u64 = 315_532_800;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+impl Date {
+    /// Returns the date `year`-`month`-`day`, or `None` if it is not a valid
+    /// date between 1980 and 2107.
+    pub fn new(year: usize, month: u8, day: u8) -> Option<Date> {
+        if !(MIN_YEAR..=MAX_YEAR).contains(&year)
+            || !(1..=12).contains(&month)
+            || day == 0
+            || day > days_in_month(year, month)
+        {
+            return None;
+        }
+        Some(Date(
+            (((year - MIN_YEAR) as u16) << 9) | ((month as u16) << 5) | day as u16,
+        ))
+    }
+}
+
+impl Time {
+    /// Returns the time `hour`:`minute`:`second`, or `None` if it is not a
+    /// valid time. Odd seconds are rounded down, as FAT stores seconds in 2
+    /// second intervals.
+    pub fn new(hour: u8, minute: u8, second: u8) -> Option<Time> {
+        if hour >= 24 || minute >= 60 || second >= 60 {
+            return None;
+        }
+        Some(Time(
+            ((hour as u16) << 11) | ((minute as u16) << 5) | (second / 2) as u16,
+        ))
+    }
+}
+
+/// The number of days in `month` of `year`.
+fn days_in_month(year: usize, month: u8) -> u8 {
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The number of days from 1970-01-01 to `year`-`month`-`day`, for dates
+/// after 1970.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    // years start in March so that the leap day is last
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The year, month and day `days` days after 1970-01-01.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    (year, month, day)
+}
+
 /// File attributes as represented in FAT32 on-disk structures.
 #[repr(C, packed)]
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Attributes(u8);
 
+impl Attributes {
+    pub(crate) const READ_ONLY: u8 = 0x01;
+    pub(crate) const HIDDEN: u8 = 0x02;
+    pub(crate) const SYSTEM: u8 = 0x04;
+    pub(crate) const VOLUME_ID: u8 = 0x08;
+    pub(crate) const DIRECTORY: u8 = 0x10;
+    pub(crate) const ARCHIVE: u8 = 0x20;
+    pub(crate) const LFN: u8 = Self::READ_ONLY | Self::HIDDEN | Self::SYSTEM | Self::VOLUME_ID;
+    /// The attributes that can be changed on an existing entry.
+    pub(crate) const CHANGEABLE: u8 = Self::READ_ONLY | Self::HIDDEN | Self::SYSTEM | Self::ARCHIVE;
+
+    pub(crate) fn from_raw(raw: u8) -> Attributes {
+        Attributes(raw)
+    }
+
+    pub(crate) fn raw(&self) -> u8 {
+        self.0
+    }
+
+    /// Whether the entry is read only.
+    pub fn read_only(&self) -> bool {
+        self.0 & Self::READ_ONLY != 0
+    }
+
+    /// Whether the entry is hidden.
+    pub fn hidden(&self) -> bool {
+        self.0 & Self::HIDDEN != 0
+    }
+
+    /// Whether the entry belongs to the operating system.
+    pub fn system(&self) -> bool {
+        self.0 & Self::SYSTEM != 0
+    }
+
+    /// Whether the entry is the volume label rather than a file or directory.
+    pub fn volume_id(&self) -> bool {
+        self.0 & Self::VOLUME_ID != 0
+    }
+
+    /// Whether the entry is a directory.
+    pub fn directory(&self) -> bool {
+        self.0 & Self::DIRECTORY != 0
+    }
+
+    /// Whether the entry has changed since it was last backed up.
+    pub fn archive(&self) -> bool {
+        self.0 & Self::ARCHIVE != 0
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.set(Self::READ_ONLY, read_only);
+    }
+
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.set(Self::HIDDEN, hidden);
+    }
+
+    pub fn set_system(&mut self, system: bool) {
+        self.set(Self::SYSTEM, system);
+    }
+
+    pub fn set_archive(&mut self, archive: bool) {
+        self.set(Self::ARCHIVE, archive);
+    }
+
+    fn set(&mut self, flag: u8, value: bool) {
+        match value {
+            true => self.0 |= flag,
+            false => self.0 &= !flag,
+        }
+    }
+}
+
 /// A structure containing a date and time.
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Timestamp {
@@ -29,11 +178,171 @@ pub struct Timestamp {
 /// Metadata for a directory entry.
 #[derive(Default, Debug, Clone)]
 pub struct Metadata {
-    // FIXME: Fill me in.
+    pub attributes: Attributes,
+    pub created: Timestamp,
+    pub accessed: Timestamp,
+    pub modified: Timestamp,
+}
+
+impl Timestamp {
+    /// Returns the timestamp for `year`-`month`-`day` `hour`:`minute`:`second`,
+    /// or `None` if it is not a valid date and time between 1980 and 2107.
+    pub fn new(
+        year: usize,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Option<Timestamp> {
+        Some(Timestamp {
+            date: Date::new(year, month, day)?,
+            time: Time::new(hour, minute, second)?,
+        })
+    }
+
+    /// Returns the timestamp `secs` seconds after the Unix epoch. FAT
+    /// timestamps have no time zone, so `secs` should already be adjusted to
+    /// local time. Times outside of 1980 to 2107 are clamped to that range.
+    pub fn from_unix(secs: u64) -> Timestamp {
+        let max = days_from_civil(MAX_YEAR as u64, 12, 31) * SECONDS_PER_DAY + SECONDS_PER_DAY - 1;
+        let secs = secs.clamp(FAT_EPOCH, max);
+
+        let (year, month, day) = civil_from_days(secs / SECONDS_PER_DAY);
+        let secs = secs % SECONDS_PER_DAY;
+        Timestamp {
+            date: Date::new(year as usize, month as u8, day as u8).unwrap_or_default(),
+            time: Time::new(
+                (secs / 3600) as u8,
+                (secs / 60 % 60) as u8,
+                (secs % 60) as u8,
+            )
+            .unwrap_or_default(),
+        }
+    }
+
+    /// The number of seconds between the Unix epoch and this timestamp, taken
+    /// to be in UTC. An unset day or month is read as 1.
+    pub fn to_unix(&self) -> u64 {
+        use traits::Timestamp;
+        let days = days_from_civil(
+            self.year() as u64,
+            self.month().max(1) as u64,
+            self.day().max(1) as u64,
+        );
+        days * SECONDS_PER_DAY
+            + self.hour() as u64 * 3600
+            + self.minute() as u64 * 60
+            + self.second() as u64
+    }
+
+    /// Unpacks a timestamp stored as a single 32 bit value with the date in
+    /// the high half, as exFAT does.
+    pub(crate) fn from_packed(raw: u32) -> Timestamp {
+        Timestamp {
+            date: Date((raw >> 16) as u16),
+            time: Time(raw as u16),
+        }
+    }
+}
+
+/// A source of the current time, used to stamp entries as they are created,
+/// written and read. Closures returning a `Timestamp` are clocks, so a kernel
+/// can use a timer and a configured epoch:
+///
+/// ```ignore
+/// vfat.set_clock(move || Timestamp::from_unix(EPOCH + timer::current_time().as_secs()));
+/// ```
+pub trait Clock: Send {
+    /// The current local time.
+    fn now(&self) -> Timestamp;
+}
+
+impl<F: Fn() -> Timestamp + Send> Clock for F {
+    fn now(&self) -> Timestamp {
+        self()
+    }
+}
+
+impl traits::Timestamp for Timestamp {
+    fn year(&self) -> usize {
+        ((self.date.0 >> 9) & 0b111_1111) as usize + 1980
+    }
+
+    fn month(&self) -> u8 {
+        ((self.date.0 >> 5) & 0b1111) as u8
+    }
+
+    fn day(&self) -> u8 {
+        (self.date.0 & 0b1_1111) as u8
+    }
+
+    fn hour(&self) -> u8 {
+        ((self.time.0 >> 11) & 0b1_1111) as u8
+    }
+
+    fn minute(&self) -> u8 {
+        ((self.time.0 >> 5) & 0b11_1111) as u8
+    }
+
+    fn second(&self) -> u8 {
+        // stored in 2 second intervals
+        (self.time.0 & 0b1_1111) as u8 * 2
+    }
 }
 
-// FIXME: Implement `traits::Timestamp` for `Timestamp`.
+impl traits::Metadata for Metadata {
+    type Timestamp = Timestamp;
+
+    fn read_only(&self) -> bool {
+        self.attributes.read_only()
+    }
+
+    fn hidden(&self) -> bool {
+        self.attributes.hidden()
+    }
 
-// FIXME: Implement `traits::Metadata` for `Metadata`.
+    fn created(&self) -> Self::Timestamp {
+        self.created
+    }
 
-// FIXME: Implement `fmt::Display` (to your liking) for `Metadata`.
+    fn accessed(&self) -> Self::Timestamp {
+        self.accessed
+    }
+
+    fn modified(&self) -> Self::Timestamp {
+        self.modified
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use traits::Timestamp;
+        write!(
+            f,
+            "{:02}/{:02}/{} {:02}:{:02}:{:02}",
+            self.month(),
+            self.day(),
+            self.year(),
+            self.hour(),
+            self.minute(),
+            self.second()
+        )
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |set: bool, c: char| if set { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}  created: {}  modified: {}  accessed: {}",
+            flag(self.attributes.directory(), 'd'),
+            flag(self.attributes.read_only(), 'r'),
+            flag(self.attributes.hidden(), 'h'),
+            self.created,
+            self.modified,
+            self.accessed
+        )
+    }
+}
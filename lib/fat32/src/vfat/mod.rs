@@ -1,4 +1,5 @@
 pub(crate) mod cache;
+pub(crate) mod check;
 pub(crate) mod cluster;
 pub(crate) mod dir;
 pub(crate) mod ebpb;
@@ -6,17 +7,27 @@ pub(crate) mod entry;
 pub(crate) mod error;
 pub(crate) mod fat;
 pub(crate) mod file;
+pub(crate) mod format;
+pub(crate) mod fsinfo;
+pub(crate) mod journal;
 pub(crate) mod metadata;
+pub(crate) mod name;
+#[allow(clippy::module_inception)]
 pub(crate) mod vfat;
 
-pub use self::dir::Dir;
+pub use self::cache::{CacheStats, DEFAULT_CACHE_CAPACITY};
+pub use self::check::{CheckReport, Problem};
+pub use self::dir::{Dir, RawEntries, VFatDirEntry, VFatLfnDirEntry, VFatRegularDirEntry};
 pub use self::ebpb::BiosParameterBlock;
 pub use self::entry::Entry;
 pub use self::error::Error;
+pub use self::fat::FatType;
 pub use self::file::File;
-pub use self::metadata::{Attributes, Date, Metadata, Time, Timestamp};
-pub use self::vfat::{VFat, VFatHandle};
+pub use self::format::{format, FormatOptions};
+pub use self::metadata::{Attributes, Clock, Date, Metadata, Time, Timestamp};
+pub use self::vfat::{FatCopy, MountOptions, VFat, VFatHandle};
 
 pub(crate) use self::cache::{CachedPartition, Partition};
 pub(crate) use self::cluster::Cluster;
 pub(crate) use self::fat::{FatEntry, Status};
+pub(crate) use self::fsinfo::FsInfo;
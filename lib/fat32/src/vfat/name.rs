@@ -0,0 +1,282 @@
+use alloc::vec::Vec;
+
+use shim::io;
+use shim::ioerr;
+use shim::newioerr;
+
+/// The maximum length of a long file name in UCS-2 characters.
+const MAX_LFN_LEN: usize = 255;
+
+/// Characters that may not appear in any file name.
+const INVALID_NAME_CHARS: &str = "\"*/:<>?\\|";
+
+/// Set in a short entry's case flags when its base name is displayed in
+/// lowercase.
+pub(crate) const LOWERCASE_BASE: u8 = 0x08;
+/// Set in a short entry's case flags when its extension is displayed in
+/// lowercase.
+pub(crate) const LOWERCASE_EXT: u8 = 0x10;
+
+/// How many `~N` numeric tails are tried on the plain basis name before
+/// switching to a basis that includes a hash of the long name, as Windows
+/// does.
+const PLAIN_TAILS: u32 = 4;
+
+/// The 8.3 short name an entry is stored under.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct ShortName {
+    /// The space padded name and extension fields.
+    pub raw: [u8; 11],
+    /// The case flags to store in the entry.
+    pub case: u8,
+    /// Whether the full name must also be stored in LFN entries.
+    pub needs_lfn: bool,
+}
+
+/// The primary and extension portions of a short name generated from a long
+/// name.
+struct Basis {
+    base: Vec<u8>,
+    extension: Vec<u8>,
+    /// Whether the long name converts to the basis without losing anything.
+    lossless: bool,
+}
+
+/// Checks that `name` can be given to a new entry.
+///
+/// # Errors
+///
+/// Returns an error of `InvalidInput` if `name` is empty, is `.` or `..`,
+/// ends in a `.` or space, contains a character that is not allowed in file
+/// names, cannot be encoded in UCS-2 or is too long.
+pub(crate) fn validate_name(name: &str) -> io::Result<()> {
+    if name.is_empty() || name == "." || name == ".." {
+        return ioerr!(InvalidInput, "invalid file name");
+    }
+    if name.ends_with('.') || name.ends_with(' ') {
+        return ioerr!(InvalidInput, "file name ends in a `.` or space");
+    }
+    if name
+        .chars()
+        .any(|c| c.is_control() || INVALID_NAME_CHARS.contains(c))
+    {
+        return ioerr!(InvalidInput, "file name contains an invalid character");
+    }
+    if encode_ucs2(name)?.len() > MAX_LFN_LEN {
+        return ioerr!(InvalidInput, "file name is too long");
+    }
+    Ok(())
+}
+
+/// Encodes `name` as UCS-2, the encoding used by LFN entries.
+///
+/// # Errors
+///
+/// Returns an error of `InvalidInput` if `name` contains a character outside
+/// of the Basic Multilingual Plane.
+pub(crate) fn encode_ucs2(name: &str) -> io::Result<Vec<u16>> {
+    name.chars()
+        .map(|c| match c as u32 {
+            c @ 0..=0xFFFF => Ok(c as u16),
+            _ => Err(newioerr!(
+                InvalidInput,
+                "file name cannot be encoded in UCS-2"
+            )),
+        })
+        .collect()
+}
+
+/// Whether `a` and `b` name the same entry. Names are compared without
+/// regard to case.
+pub(crate) fn names_match(a: &str, b: &str) -> bool {
+    a.chars()
+        .flat_map(char::to_uppercase)
+        .eq(b.chars().flat_map(char::to_uppercase))
+}
+
+/// Whether `b` may appear in an 8.3 short name.
+pub(crate) fn is_short_name_char(b: u8) -> bool {
+    b.is_ascii_uppercase() || b.is_ascii_digit() || b"!#$%&'()-@^_`{}~".contains(&b)
+}
+
+/// Converts `label` into the space padded form volume labels are stored in.
+/// Labels are stored in uppercase.
+///
+/// # Errors
+///
+/// Returns an error of `InvalidInput` if `label` is empty, starts with a
+/// space, is longer than 11 characters or contains a character that is not
+/// allowed in short names.
+pub(crate) fn volume_label(label: &str) -> io::Result<[u8; 11]> {
+    let upper = label.to_ascii_uppercase();
+    let bytes = upper.as_bytes();
+    if bytes.is_empty() || bytes[0] == b' ' || bytes.len() > 11 {
+        return ioerr!(InvalidInput, "invalid volume label");
+    }
+    if !bytes.iter().all(|&b| b == b' ' || is_short_name_char(b)) {
+        return ioerr!(InvalidInput, "volume label contains an invalid character");
+    }
+
+    let mut raw = [b' '; 11];
+    raw[..bytes.len()].copy_from_slice(bytes);
+    Ok(raw)
+}
+
+/// The checksum of a short name stored in each of its LFN entries.
+pub(crate) fn lfn_checksum(short: &[u8; 11]) -> u8 {
+    short.iter().fold(0u8, |sum, &b| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b)
+    })
+}
+
+/// Chooses the short name for a new entry named `name` that does not collide
+/// with any of the short names in `taken`.
+///
+/// Names that fit in 8.3 are stored as is, using the case flags to keep an
+/// all lowercase base or extension, and only get LFN entries if their case is
+/// mixed. Every other name gets a `~N` numeric tail alias following the
+/// Windows basis name rules. `name` should already have passed
+/// `validate_name`.
+///
+/// # Errors
+///
+/// Returns an error of `AlreadyExists` if every alias is taken.
+pub(crate) fn generate_short_name(name: &str, taken: &[[u8; 11]]) -> io::Result<ShortName> {
+    let basis = Basis::from(name);
+
+    if basis.lossless {
+        let raw = pack(&basis.base, &basis.extension);
+        if !taken.contains(&raw) {
+            return Ok(match case_flags(name) {
+                Some(case) => ShortName {
+                    raw,
+                    case,
+                    needs_lfn: false,
+                },
+                None => ShortName {
+                    raw,
+                    case: 0,
+                    needs_lfn: true,
+                },
+            });
+        }
+    }
+
+    let alias = |base: &[u8], n: u32| -> Option<ShortName> {
+        let raw = pack(&with_tail(base, n), &basis.extension);
+        match taken.contains(&raw) {
+            true => None,
+            false => Some(ShortName {
+                raw,
+                case: 0,
+                needs_lfn: true,
+            }),
+        }
+    };
+
+    if let Some(short) = (1..=PLAIN_TAILS).find_map(|n| alias(&basis.base, n)) {
+        return Ok(short);
+    }
+
+    // like Windows, keep the first two characters of the basis and replace the
+    // rest with a hash of the long name so there are fewer collisions
+    let mut hashed: Vec<u8> = basis.base.iter().take(2).cloned().collect();
+    hashed.extend_from_slice(format!("{:04X}", name_hash(name)).as_bytes());
+    (1..1_000_000)
+        .find_map(|n| alias(&hashed, n))
+        .ok_or_else(|| newioerr!(AlreadyExists, "no unused short name for that name"))
+}
+
+impl Basis {
+    /// Generates the basis name for `name` as described in the "Basis-Name
+    /// Generation Algorithm" section of the FAT specification.
+    fn from(name: &str) -> Basis {
+        // characters outside of ASCII have no short name equivalent
+        let mut lossy = false;
+        let mut converted: Vec<u8> = name
+            .chars()
+            .filter(|&c| c != ' ')
+            .map(|c| match c.to_ascii_uppercase() {
+                '.' => b'.',
+                c if c.is_ascii() && is_short_name_char(c as u8) => c as u8,
+                _ => {
+                    lossy = true;
+                    b'_'
+                }
+            })
+            .collect();
+        let leading_periods = converted.iter().take_while(|&&b| b == b'.').count();
+        converted.drain(..leading_periods);
+
+        let (base, extension) = match converted.iter().rposition(|&b| b == b'.') {
+            Some(i) => (&converted[..i], &converted[i + 1..]),
+            None => (&converted[..], &[][..]),
+        };
+        let base: Vec<u8> = base
+            .iter()
+            .filter(|&&b| b != b'.')
+            .take(8)
+            .cloned()
+            .collect();
+        let extension: Vec<u8> = extension.iter().take(3).cloned().collect();
+
+        // the name fits if nothing was stripped or truncated on the way
+        let mut fitted = base.clone();
+        if !extension.is_empty() {
+            fitted.push(b'.');
+            fitted.extend_from_slice(&extension);
+        }
+        let fits = name.to_ascii_uppercase().as_bytes() == &fitted[..];
+
+        Basis {
+            lossless: !lossy && fits && !base.is_empty(),
+            base,
+            extension,
+        }
+    }
+}
+
+/// Returns the case flags that make a short name display as `name`, or
+/// `None` if the base or extension of `name` mixes upper and lowercase.
+fn case_flags(name: &str) -> Option<u8> {
+    let flag = |part: &str, lowercase: u8| -> Option<u8> {
+        let lower = part.bytes().any(|b| b.is_ascii_lowercase());
+        let upper = part.bytes().any(|b| b.is_ascii_uppercase());
+        match (lower, upper) {
+            (true, true) => None,
+            (true, false) => Some(lowercase),
+            (false, _) => Some(0),
+        }
+    };
+
+    let (base, extension) = match name.rfind('.') {
+        Some(i) => (&name[..i], &name[i + 1..]),
+        None => (name, ""),
+    };
+    Some(flag(base, LOWERCASE_BASE)? | flag(extension, LOWERCASE_EXT)?)
+}
+
+/// Appends the numeric tail `~n` to `base`, truncating it so that the result
+/// fits in 8 characters.
+fn with_tail(base: &[u8], n: u32) -> Vec<u8> {
+    let tail = format!("~{}", n);
+    let keep = base.len().min(8 - tail.len());
+    let mut name = base[..keep].to_vec();
+    name.extend_from_slice(tail.as_bytes());
+    name
+}
+
+/// Packs a base name and extension into space padded 8.3 fields.
+fn pack(base: &[u8], extension: &[u8]) -> [u8; 11] {
+    let mut raw = [b' '; 11];
+    raw[..base.len()].copy_from_slice(base);
+    raw[8..8 + extension.len()].copy_from_slice(extension);
+    raw
+}
+
+/// A 16 bit hash of `name` used in aliases once the plain numeric tails are
+/// used up.
+fn name_hash(name: &str) -> u16 {
+    name.encode_utf16()
+        .fold(0u16, |hash, c| hash.wrapping_mul(0x25).wrapping_add(c))
+}
@@ -1,20 +1,31 @@
-use core::fmt::Debug;
+use core::fmt::{self, Debug};
 use core::marker::PhantomData;
-use core::mem::size_of;
 
+use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec::Vec;
 
+use shim::ffi::OsStr;
 use shim::io;
 use shim::ioerr;
 use shim::newioerr;
-use shim::path;
 use shim::path::Path;
 
-use crate::mbr::MasterBootRecord;
-use crate::traits::{BlockDevice, FileSystem};
-use crate::util::SliceExt;
-use crate::vfat::{BiosParameterBlock, CachedPartition, Partition};
-use crate::vfat::{Cluster, Dir, Entry, Error, FatEntry, File, Status};
+use crate::normalize;
+use crate::partition::{find_fat_partition, partitions};
+use crate::traits::{BlockDevice, Entry as _, FileSystem};
+use crate::vfat::check::Checker;
+use crate::vfat::dir::{
+    VFatDirEntry, VFatRegularDirEntry, DELETED_ENTRY, END_OF_ENTRIES, ENTRY_SIZE,
+};
+use crate::vfat::journal::{Journal, Record, JOURNAL_NAME};
+use crate::vfat::name;
+use crate::vfat::{
+    Attributes, Clock, Cluster, Dir, Entry, Error, FatEntry, FatType, File, Status, Timestamp,
+};
+use crate::vfat::{
+    BiosParameterBlock, CacheStats, CachedPartition, CheckReport, FsInfo, Partition,
+};
 
 /// A generic trait that handles a critical section as a closure
 pub trait VFatHandle: Clone + Debug + Send + Sync {
@@ -22,6 +33,73 @@ pub trait VFatHandle: Clone + Debug + Send + Sync {
     fn lock<R>(&self, f: impl FnOnce(&mut VFat<Self>) -> R) -> R;
 }
 
+/// The EBPB flag that disables FAT mirroring on FAT32 volumes, leaving only
+/// the FAT numbered by the low 4 bits active.
+const NO_FAT_MIRRORING: u16 = 1 << 7;
+const ACTIVE_FAT_MASK: u16 = 0xF;
+
+/// Where the boot sector's volume serial number and label are found, relative
+/// to the start of its extended fields, which begin at different offsets on
+/// FAT32 volumes and on FAT12 and FAT16 volumes.
+const EXTENDED_SIGNATURE: usize = 2;
+const VOLUME_ID: usize = 3;
+const VOLUME_LABEL: usize = 7;
+
+/// The extended signature values that record a serial number, and a serial
+/// number followed by a label.
+const SERIAL_ONLY: u8 = 0x28;
+const SERIAL_AND_LABEL: u8 = 0x29;
+
+/// The label stored in the boot sector of an unlabelled volume.
+const NO_LABEL: &[u8; 11] = b"NO NAME    ";
+
+/// The result of searching the root directory for a regular entry.
+struct RootSearch {
+    /// The offset of the first matching entry and the entry itself.
+    entry: Option<(u64, VFatRegularDirEntry)>,
+    /// The offset of the first free entry.
+    free: Option<u64>,
+    /// The size of the root directory in bytes.
+    len: u64,
+}
+
+/// Options for [`VFat::from_with_options`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MountOptions {
+    /// Compare every copy of the FAT against the one in use when mounting.
+    /// If the FAT in use has sectors that cannot be read and the FATs are
+    /// mirrored, the first copy that can be read in full is used instead.
+    /// The comparison is available from [`VFat::fat_copies`].
+    pub compare_fats: bool,
+    /// Never write to the device. Every call that would change the volume
+    /// returns an error of `PermissionDenied`, and a transaction left in the
+    /// journal is finished in memory only.
+    pub read_only: bool,
+    /// Do not record the date files are read on.
+    pub no_access_time: bool,
+}
+
+/// How one copy of the FAT compares against the FAT in use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FatCopy {
+    /// The number of the copy, starting at 0.
+    pub copy: u8,
+    /// The number of sectors that differ from the FAT in use. Sectors that
+    /// cannot be read in either copy are not compared.
+    pub differing_sectors: u32,
+    /// The number of sectors of this copy that cannot be read.
+    pub unreadable_sectors: u32,
+}
+
+/// The clock entries are stamped with.
+struct ClockSource(Box<dyn Clock>);
+
+impl Debug for ClockSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ClockSource").field(&self.0.now()).finish()
+    }
+}
+
 #[derive(Debug)]
 pub struct VFat<HANDLE: VFatHandle> {
     phantom: PhantomData<HANDLE>,
@@ -29,51 +107,1804 @@ pub struct VFat<HANDLE: VFatHandle> {
     bytes_per_sector: u16,
     sectors_per_cluster: u8,
     sectors_per_fat: u32,
+    num_fats: u8,
     fat_start_sector: u64,
+    /// The FAT that is read from.
+    active_fat: u8,
+    /// Whether every FAT is kept up to date. Otherwise only the active FAT
+    /// is read and written.
+    mirror_fats: bool,
+    /// The comparison of the FATs made when mounting, if one was made.
+    fat_copies: Vec<FatCopy>,
     data_start_sector: u64,
     rootdir_cluster: Cluster,
+    num_clusters: u32,
+    fat_type: FatType,
+    /// The first sector of the fixed root directory region on FAT12 and
+    /// FAT16 volumes.
+    root_dir_start_sector: u64,
+    /// The number of sectors in the fixed root directory region. 0 on FAT32.
+    root_dir_sectors: u64,
+    /// The sector holding the backup copy of the boot sector, if any.
+    backup_boot_sector: Option<u64>,
+    /// The sector holding the FSInfo structure. Only FAT32 volumes have one,
+    /// and it is ignored if its signatures are invalid.
+    fsinfo_sector: Option<u64>,
+    /// The number of free clusters, if known. It is read from FSInfo or
+    /// counted by scanning the FAT, then kept up to date as clusters are
+    /// allocated and freed.
+    free_clusters: Option<u32>,
+    /// The cluster the search for a free cluster starts at.
+    next_free: u32,
+    /// Whether `free_clusters` or `next_free` changed since they were last
+    /// written to FSInfo.
+    fsinfo_dirty: bool,
+    /// Where the current time comes from. Entries are not stamped without
+    /// one.
+    clock: Option<ClockSource>,
+    /// The journal that metadata changes are committed through, if the volume
+    /// has one.
+    journal: Option<Journal>,
+    /// The sectors the current operation has changed since the last commit,
+    /// with their contents from before, so that an operation too large for
+    /// the journal can be undone.
+    undo: Vec<(u64, Vec<u8>)>,
+    /// Whether the volume was mounted read only.
+    read_only: bool,
+    /// Whether reading files leaves their access date alone.
+    no_access_time: bool,
+    /// Whether FAT[1] recorded the volume as dirty when it was mounted, and
+    /// it has not been repaired since.
+    was_dirty: bool,
+    /// Whether FAT[1] records the volume as dirty, either because it already
+    /// did or because it was changed since the last sync.
+    marked_dirty: bool,
 }
 
 impl<HANDLE: VFatHandle> VFat<HANDLE> {
-    pub fn from<T>(mut device: T) -> Result<HANDLE, Error>
+    /// Mounts the first FAT partition on `device`, which may be partitioned
+    /// with an MBR or a GUID partition table.
+    pub fn from<T>(device: T) -> Result<HANDLE, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        VFat::from_with_options(device, &MountOptions::default())
+    }
+
+    /// Mounts the first FAT partition on `device` like [`VFat::from`], with
+    /// the given options.
+    pub fn from_with_options<T>(mut device: T, options: &MountOptions) -> Result<HANDLE, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        let partition = find_fat_partition(&mut device)?;
+        VFat::mount(device, partition.start, options)
+    }
+
+    /// Mounts the partition with index `index`, as listed by
+    /// [`partitions`](crate::partitions).
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if there is no partition with that index and
+    /// `BadSignature` if it does not hold a FAT file system.
+    pub fn from_partition<T>(device: T, index: usize) -> Result<HANDLE, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        VFat::from_partition_with_options(device, index, &MountOptions::default())
+    }
+
+    /// Mounts the partition with index `index` like [`VFat::from_partition`],
+    /// with the given options.
+    pub fn from_partition_with_options<T>(
+        mut device: T,
+        index: usize,
+        options: &MountOptions,
+    ) -> Result<HANDLE, Error>
     where
         T: BlockDevice + 'static,
     {
-        unimplemented!("VFat::from()")
-    }
-
-    // TODO: The following methods may be useful here:
-    //
-    //  * A method to read from an offset of a cluster into a buffer.
-    //
-    //    fn read_cluster(
-    //        &mut self,
-    //        cluster: Cluster,
-    //        offset: usize,
-    //        buf: &mut [u8]
-    //    ) -> io::Result<usize>;
-    //
-    //  * A method to read all of the clusters chained from a starting cluster
-    //    into a vector.
-    //
-    //    fn read_chain(
-    //        &mut self,
-    //        start: Cluster,
-    //        buf: &mut Vec<u8>
-    //    ) -> io::Result<usize>;
-    //
-    //  * A method to return a reference to a `FatEntry` for a cluster where the
-    //    reference points directly into a cached sector.
-    //
-    //    fn fat_entry(&mut self, cluster: Cluster) -> io::Result<&FatEntry>;
+        let partition = partitions(&mut device)?
+            .into_iter()
+            .find(|partition| partition.index == index)
+            .ok_or(Error::NotFound)?;
+        VFat::mount(device, partition.start, options)
+    }
+
+    /// Mounts a "superfloppy": a device without a partition table that has
+    /// the FAT boot sector at sector 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BadSignature` if sector 0 is not a FAT boot sector.
+    pub fn from_superfloppy<T>(device: T) -> Result<HANDLE, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        VFat::from_superfloppy_with_options(device, &MountOptions::default())
+    }
+
+    /// Mounts a superfloppy like [`VFat::from_superfloppy`], with the given
+    /// options.
+    pub fn from_superfloppy_with_options<T>(
+        device: T,
+        options: &MountOptions,
+    ) -> Result<HANDLE, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        VFat::mount(device, 0, options)
+    }
+
+    /// Mounts the FAT file system whose boot sector is at sector `start`.
+    fn mount<T>(mut device: T, start: u64, options: &MountOptions) -> Result<HANDLE, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        let ebpb = BiosParameterBlock::from(&mut device, start)?;
+        if !ebpb.is_fat() {
+            return Err(Error::BadSignature);
+        }
+        let fat_type = ebpb.fat_type();
+
+        let fat_start_sector = ebpb.reserved_sectors as u64;
+        let root_dir_start_sector =
+            fat_start_sector + ebpb.num_fats as u64 * ebpb.fat_size() as u64;
+
+        // FAT12 and FAT16 root directories live in a fixed region rather than
+        // a cluster chain. they are referred to as cluster 0
+        let rootdir_cluster = match fat_type {
+            FatType::Fat32 => Cluster::from(ebpb.root_cluster),
+            FatType::Fat12 | FatType::Fat16 => Cluster::from(0),
+        };
+
+        let fsinfo_sector = match (fat_type, ebpb.fsinfo_sector) {
+            (FatType::Fat32, sector) if sector != 0 && sector < ebpb.reserved_sectors => {
+                Some(sector as u64)
+            }
+            _ => None,
+        };
+
+        // FAT12 and FAT16 volumes always mirror their FATs
+        let (active_fat, mirror_fats) = match fat_type {
+            FatType::Fat32 if ebpb.flags & NO_FAT_MIRRORING != 0 => {
+                let active = (ebpb.flags & ACTIVE_FAT_MASK) as u8;
+                if active >= ebpb.num_fats {
+                    return Err(Error::BadSignature);
+                }
+                (active, false)
+            }
+            _ => (0, true),
+        };
+
+        let backup_boot_sector = match (fat_type, ebpb.backup_boot_sector) {
+            (FatType::Fat32, sector) if sector != 0 && sector < ebpb.reserved_sectors => {
+                Some(sector as u64)
+            }
+            _ => None,
+        };
+
+        let partition = Partition {
+            start,
+            num_sectors: ebpb.total_sectors() as u64,
+            sector_size: ebpb.bytes_per_sector as u64,
+        };
+
+        let mut vfat = VFat {
+            phantom: PhantomData,
+            device: CachedPartition::new(device, partition),
+            bytes_per_sector: ebpb.bytes_per_sector,
+            sectors_per_cluster: ebpb.sectors_per_cluster,
+            sectors_per_fat: ebpb.fat_size(),
+            num_fats: ebpb.num_fats,
+            fat_start_sector,
+            active_fat,
+            mirror_fats,
+            fat_copies: Vec::new(),
+            data_start_sector: ebpb.data_start_sector() as u64,
+            rootdir_cluster,
+            num_clusters: ebpb.cluster_count(),
+            fat_type,
+            root_dir_start_sector,
+            root_dir_sectors: ebpb.root_dir_sectors() as u64,
+            backup_boot_sector,
+            fsinfo_sector,
+            free_clusters: None,
+            next_free: 2,
+            fsinfo_dirty: false,
+            clock: None,
+            journal: None,
+            undo: Vec::new(),
+            read_only: options.read_only,
+            no_access_time: options.no_access_time,
+            was_dirty: false,
+            marked_dirty: false,
+        };
+        vfat.load_journal()?;
+        if options.compare_fats {
+            vfat.compare_fats();
+        }
+        vfat.load_fsinfo()?;
+        vfat.was_dirty = !vfat.volume_clean()?;
+        vfat.marked_dirty = vfat.was_dirty;
+        Ok(HANDLE::new(vfat))
+    }
+
+    /* ------------- Geometry ------------- */
+    /// The size of a cluster in bytes.
+    pub fn cluster_size(&self) -> usize {
+        self.bytes_per_sector as usize * self.sectors_per_cluster as usize
+    }
+
+    /// The first cluster of the root directory. On FAT12 and FAT16 volumes
+    /// the root directory is not in a cluster and this is cluster 0.
+    pub fn root_cluster(&self) -> Cluster {
+        self.rootdir_cluster
+    }
+
+    /// The type of FAT used by the file system.
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    /// The number of data clusters.
+    pub(crate) fn num_clusters(&self) -> u32 {
+        self.num_clusters
+    }
+
+    /// The number of the FAT that is read from.
+    pub fn active_fat(&self) -> u8 {
+        self.active_fat
+    }
+
+    /// The comparison of every FAT against the active one made when
+    /// mounting with [`MountOptions::compare_fats`], or an empty slice if
+    /// none was made.
+    pub fn fat_copies(&self) -> &[FatCopy] {
+        &self.fat_copies
+    }
+
+    /// The FATs other than the active one that are kept identical to it.
+    pub(crate) fn mirrored_fats(&self) -> impl Iterator<Item = u8> {
+        let active = self.active_fat;
+        let count = match self.mirror_fats {
+            true => self.num_fats,
+            false => 0,
+        };
+        (0..count).filter(move |&copy| copy != active)
+    }
+
+    /// Whether `start` refers to the fixed root directory region of a FAT12
+    /// or FAT16 volume rather than to a cluster chain.
+    fn is_fixed_root(&self, start: Cluster) -> bool {
+        self.fat_type != FatType::Fat32 && start.num() == 0
+    }
+
+    /// The size of the fixed root directory region in bytes.
+    fn fixed_root_size(&self) -> usize {
+        self.root_dir_sectors as usize * self.bytes_per_sector as usize
+    }
+
+    /// The logical sector where `cluster` begins.
+    fn cluster_sector(&self, cluster: Cluster) -> u64 {
+        self.data_start_sector + cluster.data_index() * self.sectors_per_cluster as u64
+    }
+
+    /// Returns an error if `cluster` is not a data cluster of this file system.
+    pub(crate) fn check_cluster(&self, cluster: Cluster) -> io::Result<()> {
+        if !cluster.is_valid() || cluster.num() >= self.num_clusters + 2 {
+            return ioerr!(InvalidData, "cluster number out of range");
+        }
+        Ok(())
+    }
+
+    /* ------------- Cluster I/O ------------- */
+    /// Reads from `offset` bytes into the region of `len` bytes starting at
+    /// sector `first_sector` into `buf`. Reads until either `buf` is full or
+    /// the end of the region is reached. Returns the number of bytes read.
+    fn read_region(
+        &mut self,
+        first_sector: u64,
+        len: usize,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        let sector_size = self.bytes_per_sector as usize;
+        let len = buf.len().min(len.saturating_sub(offset));
+
+        let mut read = 0;
+        while read < len {
+            let sector_offset = (offset + read) % sector_size;
+            let sector = first_sector + ((offset + read) / sector_size) as u64;
+            let data = self.device.get(sector)?;
+
+            let n = (sector_size - sector_offset).min(len - read);
+            buf[read..read + n].copy_from_slice(&data[sector_offset..sector_offset + n]);
+            read += n;
+        }
+
+        Ok(read)
+    }
+
+    /// Writes `buf` to the region of `len` bytes starting at sector
+    /// `first_sector`, `offset` bytes in. Writes until either all of `buf` is
+    /// written or the end of the region is reached. Returns the number of
+    /// bytes written. `metadata` sectors go through the journal, if there is
+    /// one.
+    fn write_region(
+        &mut self,
+        first_sector: u64,
+        len: usize,
+        offset: usize,
+        buf: &[u8],
+        metadata: bool,
+    ) -> io::Result<usize> {
+        self.begin_write()?;
+        let sector_size = self.bytes_per_sector as usize;
+        let len = buf.len().min(len.saturating_sub(offset));
+
+        let mut written = 0;
+        while written < len {
+            let sector_offset = (offset + written) % sector_size;
+            let sector = first_sector + ((offset + written) / sector_size) as u64;
+            let data = match metadata {
+                true => self.metadata_sector_mut(sector)?,
+                false => self.device.get_mut(sector)?,
+            };
+
+            let n = (sector_size - sector_offset).min(len - written);
+            data[sector_offset..sector_offset + n].copy_from_slice(&buf[written..written + n]);
+            written += n;
+        }
+
+        Ok(written)
+    }
+
+    /// Reads from `offset` bytes into `cluster` into `buf`. Reads until either
+    /// `buf` is full or the end of the cluster is reached. Returns the number
+    /// of bytes read.
+    pub(crate) fn read_cluster(
+        &mut self,
+        cluster: Cluster,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        self.check_cluster(cluster)?;
+        let first_sector = self.cluster_sector(cluster);
+        self.read_region(first_sector, self.cluster_size(), offset, buf)
+    }
+
+    /// Writes `buf` to `cluster` starting `offset` bytes in. Writes until either
+    /// all of `buf` is written or the end of the cluster is reached. Returns
+    /// the number of bytes written.
+    pub(crate) fn write_cluster(
+        &mut self,
+        cluster: Cluster,
+        offset: usize,
+        buf: &[u8],
+    ) -> io::Result<usize> {
+        self.check_cluster(cluster)?;
+        let first_sector = self.cluster_sector(cluster);
+        self.write_region(first_sector, self.cluster_size(), offset, buf, false)
+    }
+
+    /// Reads whole clusters into `buf`, starting with `start` and continuing
+    /// for as long as the chain runs through consecutive clusters, up to as
+    /// many clusters as fit in `buf`. The run is transferred with a single
+    /// multi-sector read. Returns the number of clusters read and the last
+    /// cluster read.
+    pub(crate) fn read_clusters(
+        &mut self,
+        start: Cluster,
+        buf: &mut [u8],
+    ) -> io::Result<(u64, Cluster)> {
+        let (len, last) = self.contiguous_run(start, (buf.len() / self.cluster_size()) as u64)?;
+        let sectors = len * self.sectors_per_cluster as u64;
+        self.device
+            .read_sectors(self.cluster_sector(start), sectors, buf)?;
+        Ok((len, last))
+    }
+
+    /// Writes whole clusters from `buf`, starting with `start` and continuing
+    /// for as long as the chain runs through consecutive clusters, up to as
+    /// many clusters as `buf` holds. The run is transferred with a single
+    /// multi-sector write. Returns the number of clusters written and the last
+    /// cluster written.
+    pub(crate) fn write_clusters(
+        &mut self,
+        start: Cluster,
+        buf: &[u8],
+    ) -> io::Result<(u64, Cluster)> {
+        self.begin_write()?;
+        let (len, last) = self.contiguous_run(start, (buf.len() / self.cluster_size()) as u64)?;
+        let sectors = len * self.sectors_per_cluster as u64;
+        self.device
+            .write_sectors(self.cluster_sector(start), sectors, buf)?;
+        Ok((len, last))
+    }
+
+    /// Returns the length, at most `max`, of the run of consecutive clusters
+    /// in the chain starting at `start`, along with the run's last cluster.
+    fn contiguous_run(&mut self, start: Cluster, max: u64) -> io::Result<(u64, Cluster)> {
+        self.check_cluster(start)?;
+        if max == 0 {
+            return ioerr!(InvalidInput, "buffer is smaller than a cluster");
+        }
+
+        let mut len = 1;
+        let mut last = start;
+        while len < max {
+            match self.next_cluster(last)? {
+                Some(next) if next.num() == last.num() + 1 => last = next,
+                _ => break,
+            }
+            len += 1;
+        }
+        Ok((len, last))
+    }
+
+    /// Appends the contents of every cluster in the chain starting at `start`
+    /// to `buf`. Returns the number of bytes read.
+    ///
+    /// On FAT12 and FAT16 volumes, chains starting at cluster 0 are the fixed
+    /// root directory region. The same is true of every method that operates
+    /// on a chain.
+    pub(crate) fn read_chain(&mut self, start: Cluster, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.is_fixed_root(start) {
+            let (len, root_size) = (buf.len(), self.fixed_root_size());
+            buf.resize(len + root_size, 0);
+            return self.read_region(self.root_dir_start_sector, root_size, 0, &mut buf[len..]);
+        }
+
+        let cluster_size = self.cluster_size();
+        let mut read = 0;
+        let mut current = Some(start);
+        while let Some(cluster) = current {
+            let len = buf.len();
+            buf.resize(len + cluster_size, 0);
+            read += self.read_cluster(cluster, 0, &mut buf[len..])?;
+            current = self.next_cluster(cluster)?;
+        }
+        Ok(read)
+    }
+
+    /// Reads the cluster after `prev` in the chain starting at `start` into
+    /// `buf`, which is resized to fit it, or the first cluster if `prev` is
+    /// `None`. Returns the cluster that was read, or `None` if `prev` was the
+    /// last cluster of the chain. The fixed root directory region is read as
+    /// a single cluster.
+    pub(crate) fn read_next_cluster(
+        &mut self,
+        start: Cluster,
+        prev: Option<Cluster>,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<Option<Cluster>> {
+        if self.is_fixed_root(start) {
+            if prev.is_some() {
+                return Ok(None);
+            }
+            let root_size = self.fixed_root_size();
+            buf.resize(root_size, 0);
+            self.read_region(self.root_dir_start_sector, root_size, 0, buf)?;
+            return Ok(Some(start));
+        }
+
+        let cluster = match prev {
+            Some(prev) => match self.next_cluster(prev)? {
+                Some(cluster) => cluster,
+                None => return Ok(None),
+            },
+            None => start,
+        };
+        buf.resize(self.cluster_size(), 0);
+        self.read_cluster(cluster, 0, buf)?;
+        Ok(Some(cluster))
+    }
+
+    /// Reads from the chain starting at `start`, `offset` bytes in. The read
+    /// does not cross a cluster boundary. Returns the number of bytes read.
+    pub(crate) fn read_chain_at(
+        &mut self,
+        start: Cluster,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        if self.is_fixed_root(start) {
+            let (first_sector, root_size) = (self.root_dir_start_sector, self.fixed_root_size());
+            return self.read_region(first_sector, root_size, offset as usize, buf);
+        }
+
+        let cluster_size = self.cluster_size() as u64;
+        let cluster = self.walk_chain(start, offset / cluster_size)?;
+        self.read_cluster(cluster, (offset % cluster_size) as usize, buf)
+    }
+
+    /// Writes to the directory entries in the chain starting at `start`,
+    /// `offset` bytes in. The write does not cross a cluster boundary.
+    /// Returns the number of bytes written.
+    pub(crate) fn write_chain_at(
+        &mut self,
+        start: Cluster,
+        offset: u64,
+        buf: &[u8],
+    ) -> io::Result<usize> {
+        if self.is_fixed_root(start) {
+            let (first_sector, root_size) = (self.root_dir_start_sector, self.fixed_root_size());
+            return self.write_region(first_sector, root_size, offset as usize, buf, true);
+        }
+
+        let cluster_size = self.cluster_size() as u64;
+        let cluster = self.walk_chain(start, offset / cluster_size)?;
+        self.check_cluster(cluster)?;
+        let first_sector = self.cluster_sector(cluster);
+        let offset = (offset % cluster_size) as usize;
+        self.write_region(first_sector, cluster_size as usize, offset, buf, true)
+    }
+
+    /* ------------- FAT ------------- */
+    /// Returns the byte offset of the FAT entry for `cluster` from the start
+    /// of the FAT, and the number of bytes that must be accessed to read it.
+    fn fat_entry_location(&self, cluster: Cluster) -> io::Result<(usize, usize)> {
+        let bits = self.fat_type.entry_bits() as usize;
+        let offset = cluster.num() as usize * bits / 8;
+        // FAT12 entries share bytes with their neighbours
+        let len = bits.div_ceil(8).max(2);
+        if offset + len > self.sectors_per_fat as usize * self.bytes_per_sector as usize {
+            return ioerr!(InvalidData, "cluster has no entry in the FAT");
+        }
+        Ok((offset, len))
+    }
+
+    /// The first sector of FAT number `copy`.
+    fn fat_copy_start(&self, copy: u8) -> u64 {
+        self.fat_start_sector + copy as u64 * self.sectors_per_fat as u64
+    }
+
+    /// Reads `buf` from `offset` bytes into the active FAT. If that fails,
+    /// the mirrored FATs are tried in turn.
+    fn read_fat(&mut self, offset: usize, buf: &mut [u8]) -> io::Result<()> {
+        let fat_size = self.sectors_per_fat as usize * self.bytes_per_sector as usize;
+        let start = self.fat_copy_start(self.active_fat);
+        let err = match self.read_region(start, fat_size, offset, buf) {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+
+        let mirrors: Vec<u8> = self.mirrored_fats().collect();
+        for copy in mirrors {
+            let start = self.fat_copy_start(copy);
+            if self.read_region(start, fat_size, offset, buf).is_ok() {
+                return Ok(());
+            }
+        }
+        Err(err)
+    }
+
+    /// Writes `buf` to `offset` bytes into the active FAT and every mirrored
+    /// FAT.
+    fn write_fat(&mut self, offset: usize, buf: &[u8]) -> io::Result<()> {
+        let fat_size = self.sectors_per_fat as usize * self.bytes_per_sector as usize;
+        let copies: Vec<u8> = Some(self.active_fat)
+            .into_iter()
+            .chain(self.mirrored_fats())
+            .collect();
+        for copy in copies {
+            let start = self.fat_copy_start(copy);
+            self.write_region(start, fat_size, offset, buf, true)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the FAT entry for `cluster` and returns its raw value along with
+    /// the bytes it was read from.
+    fn read_raw_fat_entry(&mut self, cluster: Cluster) -> io::Result<(u32, [u8; 4])> {
+        let (offset, len) = self.fat_entry_location(cluster)?;
+        let mut bytes = [0u8; 4];
+        self.read_fat(offset, &mut bytes[..len])?;
+
+        let value = u32::from_le_bytes(bytes);
+        let raw = match self.fat_type {
+            FatType::Fat12 if cluster.num() % 2 == 1 => (value >> 4) & 0xFFF,
+            FatType::Fat12 => value & 0xFFF,
+            FatType::Fat16 => value & 0xFFFF,
+            FatType::Fat32 => value,
+        };
+        Ok((raw, bytes))
+    }
+
+    /// Returns the `FatEntry` for `cluster`. FAT12 and FAT16 entries are
+    /// widened to 32 bits so that they have the same statuses as FAT32
+    /// entries.
+    pub(crate) fn fat_entry(&mut self, cluster: Cluster) -> io::Result<FatEntry> {
+        let (raw, _) = self.read_raw_fat_entry(cluster)?;
+        Ok(self.fat_type.widen(raw))
+    }
+
+    /// Sets the status of the FAT entry for `cluster` to `status`.
+    pub(crate) fn set_fat_status(&mut self, cluster: Cluster, status: Status) -> io::Result<()> {
+        let (raw, bytes) = self.read_raw_fat_entry(cluster)?;
+        let mut entry = self.fat_type.widen(raw);
+        let was_free = entry.status() == Status::Free;
+        let now_free = status == Status::Free;
+        if was_free != now_free {
+            if let Some(count) = self.free_clusters {
+                self.free_clusters = Some(match now_free {
+                    true => count.saturating_add(1),
+                    false => count.saturating_sub(1),
+                });
+                self.fsinfo_dirty = true;
+            }
+        }
+        entry.set_status(status);
+        let raw = self.fat_type.narrow(&entry);
+
+        let value = u32::from_le_bytes(bytes);
+        let value = match self.fat_type {
+            FatType::Fat12 if cluster.num() % 2 == 1 => (value & !0xFFF0) | (raw << 4),
+            FatType::Fat12 => (value & !0x0FFF) | raw,
+            FatType::Fat16 => (value & !0xFFFF) | raw,
+            FatType::Fat32 => raw,
+        };
+
+        let (offset, len) = self.fat_entry_location(cluster)?;
+        self.write_fat(offset, &value.to_le_bytes()[..len])
+    }
+
+    /// Returns the cluster following `cluster` in its chain, or `None` if
+    /// `cluster` is the last cluster of the chain.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if the FAT entry for `cluster` does
+    /// not belong to a chain.
+    pub(crate) fn next_cluster(&mut self, cluster: Cluster) -> io::Result<Option<Cluster>> {
+        self.check_cluster(cluster)?;
+        match self.fat_entry(cluster)?.status() {
+            Status::Data(next) => Ok(Some(next)),
+            Status::Eoc(_) => Ok(None),
+            Status::Free => ioerr!(InvalidData, "cluster chain contains a free cluster"),
+            Status::Reserved => ioerr!(InvalidData, "cluster chain contains a reserved cluster"),
+            Status::Bad => ioerr!(InvalidData, "cluster chain contains a bad cluster"),
+        }
+    }
+
+    /// Returns the cluster `steps` clusters after `start` in its chain.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `UnexpectedEof` if the chain ends before `steps`
+    /// clusters have been walked.
+    pub(crate) fn walk_chain(&mut self, start: Cluster, steps: u64) -> io::Result<Cluster> {
+        let mut cluster = start;
+        for _ in 0..steps {
+            cluster = self
+                .next_cluster(cluster)?
+                .ok_or_else(|| newioerr!(UnexpectedEof, "cluster chain ended early"))?;
+        }
+        Ok(cluster)
+    }
+
+    /// Compares FAT number `copy` against the active FAT, sector by sector.
+    /// Sectors that cannot be read are counted rather than treated as
+    /// errors.
+    pub(crate) fn compare_fat(&mut self, copy: u8) -> FatCopy {
+        let active_start = self.fat_copy_start(self.active_fat);
+        let copy_start = self.fat_copy_start(copy);
+        let mut result = FatCopy {
+            copy,
+            differing_sectors: 0,
+            unreadable_sectors: 0,
+        };
+        for i in 0..self.sectors_per_fat as u64 {
+            let active = self.device.get(active_start + i).map(|data| data.to_vec());
+            match (active, self.device.get(copy_start + i)) {
+                (Ok(active), Ok(data)) if data != &active[..] => result.differing_sectors += 1,
+                (_, Err(_)) => result.unreadable_sectors += 1,
+                _ => {}
+            }
+        }
+        result
+    }
+
+    /// Compares every FAT against the active one. If the FATs are mirrored
+    /// and the active FAT has unreadable sectors, the first FAT without any
+    /// becomes the active FAT and the comparison is made against it.
+    fn compare_fats(&mut self) {
+        let mut copies: Vec<FatCopy> = (0..self.num_fats).map(|c| self.compare_fat(c)).collect();
+        if self.mirror_fats && copies[self.active_fat as usize].unreadable_sectors != 0 {
+            if let Some(readable) = copies.iter().find(|c| c.unreadable_sectors == 0) {
+                self.active_fat = readable.copy;
+                copies = (0..self.num_fats).map(|c| self.compare_fat(c)).collect();
+            }
+        }
+        self.fat_copies = copies;
+    }
+
+    /// Overwrites FAT number `copy` with the active FAT. Sectors of the
+    /// active FAT that cannot be read are left alone.
+    pub(crate) fn copy_active_fat(&mut self, copy: u8) -> io::Result<()> {
+        let active_start = self.fat_copy_start(self.active_fat);
+        let copy_start = self.fat_copy_start(copy);
+        for i in 0..self.sectors_per_fat as u64 {
+            let active = match self.device.get(active_start + i) {
+                Ok(data) => data.to_vec(),
+                Err(_) => continue,
+            };
+            // copying the same FAT again gives the same result, so a copy too
+            // large for the journal is committed in parts
+            if self.journal_full(copy_start + i) {
+                self.commit()?;
+            }
+            self.metadata_sector_mut(copy_start + i)?
+                .copy_from_slice(&active);
+        }
+        Ok(())
+    }
+
+    /// Counts the free clusters by scanning the FAT.
+    pub(crate) fn count_free_clusters(&mut self) -> io::Result<u32> {
+        let mut free = 0;
+        for num in 2..self.num_clusters + 2 {
+            if self.fat_entry(Cluster::from(num))?.status() == Status::Free {
+                free += 1;
+            }
+        }
+        Ok(free)
+    }
+
+    /* ------------- FSInfo ------------- */
+    /// Reads the free cluster count and next free cluster hint from FSInfo.
+    /// Values that are unknown or out of range are ignored, as is FSInfo
+    /// entirely if its signatures are invalid.
+    fn load_fsinfo(&mut self) -> io::Result<()> {
+        let sector = match self.fsinfo_sector {
+            Some(sector) => sector,
+            None => return Ok(()),
+        };
+        let fsinfo = match FsInfo::from_bytes(self.device.get(sector)?) {
+            Some(fsinfo) => fsinfo,
+            None => {
+                self.fsinfo_sector = None;
+                return Ok(());
+            }
+        };
+
+        self.free_clusters = fsinfo
+            .free_count()
+            .filter(|&count| count <= self.num_clusters);
+        if let Some(next) = fsinfo.next_free() {
+            if self.check_cluster(Cluster::from(next)).is_ok() {
+                self.next_free = next;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the free cluster count and next free cluster hint to FSInfo if
+    /// they changed.
+    fn store_fsinfo(&mut self) -> io::Result<()> {
+        let sector = match self.fsinfo_sector {
+            Some(sector) if self.fsinfo_dirty => sector,
+            _ => return Ok(()),
+        };
+
+        let (free_clusters, next_free) = (self.free_clusters, self.next_free);
+        let data = self.metadata_sector_mut(sector)?;
+        if let Some(mut fsinfo) = FsInfo::from_bytes(data) {
+            fsinfo.set_free_count(free_clusters);
+            fsinfo.set_next_free(next_free);
+            data[..512].copy_from_slice(&fsinfo.to_bytes());
+        }
+        self.fsinfo_dirty = false;
+        Ok(())
+    }
+
+    /// The free cluster count recorded in FSInfo, kept up to date since the
+    /// volume was mounted. `None` if the volume has no valid FSInfo or the
+    /// count was unknown.
+    pub(crate) fn fsinfo_free_clusters(&self) -> Option<u32> {
+        self.fsinfo_sector.and(self.free_clusters)
+    }
+
+    /// Replaces the free cluster count with `count`, which is written to
+    /// FSInfo on the next sync.
+    pub(crate) fn set_free_clusters(&mut self, count: u32) {
+        self.free_clusters = Some(count);
+        self.fsinfo_dirty = true;
+    }
+
+    /// The number of free clusters. When FSInfo did not record a usable
+    /// count, the FAT is scanned the first time this is called.
+    pub fn free_clusters(&mut self) -> io::Result<u32> {
+        match self.free_clusters {
+            Some(count) => Ok(count),
+            None => {
+                let count = self.count_free_clusters()?;
+                self.set_free_clusters(count);
+                Ok(count)
+            }
+        }
+    }
+
+    /// The number of bytes in free clusters.
+    pub fn free_space(&mut self) -> io::Result<u64> {
+        Ok(self.free_clusters()? as u64 * self.cluster_size() as u64)
+    }
+
+    /// The number of bytes in the data region, used or not.
+    pub fn total_space(&self) -> u64 {
+        self.num_clusters as u64 * self.cluster_size() as u64
+    }
+
+    /* ------------- Allocation ------------- */
+    /// Allocates a free cluster, fills it with zeroes and marks it as the end
+    /// of a chain. If `prev` is `Some`, the new cluster is linked after it.
+    /// The search for a free cluster starts after the last one allocated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` if there are no free clusters left.
+    pub(crate) fn alloc_cluster(&mut self, prev: Option<Cluster>) -> io::Result<Cluster> {
+        // the search starts at the hint and wraps around to cluster 2
+        let mut found = None;
+        for i in 0..self.num_clusters {
+            let num = 2 + (self.next_free - 2 + i) % self.num_clusters;
+            let cluster = Cluster::from(num);
+            if self.fat_entry(cluster)?.status() == Status::Free {
+                found = Some(cluster);
+                break;
+            }
+        }
+        let cluster = found.ok_or_else(|| newioerr!(Other, "no free clusters left"))?;
+        self.next_free = match cluster.num() + 1 {
+            next if next < self.num_clusters + 2 => next,
+            _ => 2,
+        };
+        self.fsinfo_dirty = true;
+
+        self.set_fat_status(cluster, Status::Eoc(0x0FFFFFFF))?;
+        if let Some(prev) = prev {
+            self.set_fat_status(prev, Status::Data(cluster))?;
+        }
+
+        let zeroes = vec![0u8; self.cluster_size()];
+        self.write_cluster(cluster, 0, &zeroes)?;
+
+        Ok(cluster)
+    }
+
+    /// Makes sure the chain starting at `start` is at least `len` clusters
+    /// long, allocating and linking new clusters onto its end as needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` if `start` is the fixed root directory
+    /// region, which cannot grow.
+    pub(crate) fn extend_chain(&mut self, start: Cluster, len: u64) -> io::Result<()> {
+        if self.is_fixed_root(start) {
+            return ioerr!(Other, "the root directory is full");
+        }
+
+        let (mut last, mut count) = self.chain_end(start)?;
+        while count < len {
+            last = self.alloc_cluster(Some(last))?;
+            count += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Extends the chain starting at `start` towards `len` clusters like
+    /// [`VFat::extend_chain`], but stops early instead of failing once the
+    /// journal has no room for another cluster and an update to the entry
+    /// that refers to the chain. Returns the length of the chain.
+    pub(crate) fn reserve_chain(&mut self, start: Cluster, len: u64) -> io::Result<u64> {
+        let (mut last, mut count) = self.chain_end(start)?;
+        // allocating changes the entries of the new and the previous cluster
+        while count < len && self.journal_room() > 2 * self.fat_change_sectors() {
+            last = self.alloc_cluster(Some(last))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// The last cluster of the chain starting at `start` and the number of
+    /// clusters in it.
+    fn chain_end(&mut self, start: Cluster) -> io::Result<(Cluster, u64)> {
+        let mut last = start;
+        let mut count = 1;
+        while let Some(next) = self.next_cluster(last)? {
+            last = next;
+            count += 1;
+        }
+        Ok((last, count))
+    }
+
+    /// Marks every cluster in the chain starting at `start` as free. With a
+    /// journal, the changes are committed in parts if they do not fit in it,
+    /// so nothing may refer to the chain any more. An interrupted commit then
+    /// leaves only lost clusters behind.
+    pub(crate) fn free_chain(&mut self, start: Cluster) -> io::Result<()> {
+        let mut current = Some(start);
+        while let Some(cluster) = current {
+            current = self.next_cluster(cluster)?;
+            self.make_room_for_fat_change()?;
+            self.set_fat_status(cluster, Status::Free)?;
+        }
+        Ok(())
+    }
+
+    /// Shortens the chain starting at `start` to `len` clusters, freeing the
+    /// clusters after it. `len` must be at least 1. The clusters are freed as
+    /// with [`VFat::free_chain`], so the entry that refers to the chain must
+    /// already record its new size.
+    pub(crate) fn truncate_chain(&mut self, start: Cluster, len: u64) -> io::Result<()> {
+        let last = self.walk_chain(start, len - 1)?;
+        if let Some(rest) = self.next_cluster(last)? {
+            self.make_room_for_fat_change()?;
+            self.set_fat_status(last, Status::Eoc(0x0FFFFFFF))?;
+            self.free_chain(rest)?;
+        }
+        Ok(())
+    }
+
+    /* ------------- Syncing ------------- */
+    /// Writes every modified sector back to the underlying device, along with
+    /// the free cluster count and next free cluster hint in FSInfo. On a
+    /// volume with a journal, the metadata changed since the last commit is
+    /// committed as one transaction. The volume is then marked clean, unless
+    /// it was dirty when it was mounted and has not been repaired since.
+    ///
+    /// Nothing is written to a read-only volume.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        // updating FSInfo is an operation of its own, which the journal must
+        // have room for
+        self.begin_operation()?;
+        self.store_fsinfo()?;
+        self.commit()?;
+        self.device.flush()?;
+        self.mark_clean()
+    }
+
+    /// Does what `sync()` does and also drops every cached sector, so that
+    /// later accesses read the device again.
+    pub fn sync_all(&mut self) -> io::Result<()> {
+        self.sync()?;
+        self.device.sync_all()
+    }
+
+    /* ------------- Mount state ------------- */
+    /// Whether the volume was mounted read only.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Whether the access dates of files are updated when they are read. They
+    /// are not on read-only volumes, those mounted with
+    /// [`MountOptions::no_access_time`], or without a clock.
+    pub(crate) fn updates_access_time(&self) -> bool {
+        !self.read_only && !self.no_access_time && self.clock.is_some()
+    }
+
+    /// Whether the dirty volume flag in FAT[1] was set when the volume was
+    /// mounted, meaning that it was not cleanly unmounted and may have
+    /// problems that `repair()` would fix. The flag stays set until then.
+    /// FAT12 volumes have no such flag and are never dirty.
+    pub fn is_dirty(&self) -> bool {
+        self.was_dirty
+    }
+
+    /// Returns an error of `PermissionDenied` if the volume was mounted read
+    /// only.
+    pub(crate) fn check_writable(&self) -> io::Result<()> {
+        match self.read_only {
+            true => ioerr!(PermissionDenied, "file system is mounted read only"),
+            false => Ok(()),
+        }
+    }
+
+    /// Called at the start of every operation that changes the volume. Fails
+    /// on read-only volumes. Once the journal is half full, the changes of
+    /// the operations before this one are committed so that it has room.
+    pub(crate) fn begin_operation(&mut self) -> io::Result<()> {
+        self.check_writable()?;
+        self.undo.clear();
+        let capacity = match &self.journal {
+            Some(journal) => journal.capacity(self.bytes_per_sector as usize),
+            None => return Ok(()),
+        };
+        if self.device.pinned_count() * 2 >= capacity {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Called before anything is changed. Fails on read-only volumes, and
+    /// marks the volume dirty on disk before its first change since it was
+    /// last synced. Volumes with a journal are kept consistent by it instead.
+    fn begin_write(&mut self) -> io::Result<()> {
+        self.check_writable()?;
+        if self.marked_dirty || self.journal.is_some() {
+            return Ok(());
+        }
+
+        // nothing else has changed since the last sync, so this is all that
+        // the flush writes
+        self.marked_dirty = true;
+        self.set_volume_clean(false)?;
+        self.device.flush()
+    }
+
+    /// Marks a volume that was marked dirty by a change as clean again, once
+    /// everything has been written back.
+    fn mark_clean(&mut self) -> io::Result<()> {
+        if !self.marked_dirty || self.was_dirty {
+            return Ok(());
+        }
+        self.set_volume_clean(true)?;
+        self.commit()?;
+        self.device.flush()?;
+        self.marked_dirty = false;
+        Ok(())
+    }
+
+    /// Whether the clean shutdown bit is set in FAT[1]. Always `true` on
+    /// FAT12 volumes, which have no such bit.
+    fn volume_clean(&mut self) -> io::Result<bool> {
+        let bit = match self.fat_type.clean_shutdown_bit() {
+            Some(bit) => bit,
+            None => return Ok(true),
+        };
+        let (offset, len) = self.fat_entry_location(Cluster::from(1))?;
+        let mut bytes = [0u8; 4];
+        self.read_fat(offset, &mut bytes[..len])?;
+        Ok(u32::from_le_bytes(bytes) & bit != 0)
+    }
+
+    /// Sets or clears the clean shutdown bit in FAT[1].
+    fn set_volume_clean(&mut self, clean: bool) -> io::Result<()> {
+        let bit = match self.fat_type.clean_shutdown_bit() {
+            Some(bit) => bit,
+            None => return Ok(()),
+        };
+        let (offset, len) = self.fat_entry_location(Cluster::from(1))?;
+        let mut bytes = [0u8; 4];
+        self.read_fat(offset, &mut bytes[..len])?;
+        let value = match clean {
+            true => u32::from_le_bytes(bytes) | bit,
+            false => u32::from_le_bytes(bytes) & !bit,
+        };
+        self.write_fat(offset, &value.to_le_bytes()[..len])
+    }
+
+    /* ------------- Checking ------------- */
+    /// Walks every directory and cluster chain and reports lost clusters,
+    /// cross-linked and broken chains, files whose size disagrees with their
+    /// chain, invalid long file name entries, FAT copies that differ from the
+    /// first FAT and a wrong free cluster count in the FSInfo sector. Nothing
+    /// is changed.
+    pub fn check(&mut self) -> io::Result<CheckReport> {
+        Checker::new(self, false).run()
+    }
+
+    /// Finds the same problems as `check()` and repairs them, then writes the
+    /// changes back to the device:
+    ///
+    ///   * lost clusters are freed
+    ///   * cross-linked and broken chains are cut short before the offending
+    ///     cluster, and entries whose first cluster is unusable are emptied
+    ///     (files) or removed (directories)
+    ///   * chains longer than their file are truncated and files longer than
+    ///     their chain are shrunk
+    ///   * invalid long file name entries are deleted
+    ///   * the first FAT is copied over the others
+    ///   * the FSInfo free cluster count is corrected
+    ///   * the dirty volume flag is cleared
+    ///
+    /// Repairs may change more sectors than a journal holds, so they are not
+    /// journaled. The volume is marked dirty while they are made instead.
+    pub fn repair(&mut self) -> io::Result<CheckReport> {
+        self.check_writable()?;
+        self.sync()?;
+        let journal = self.journal.take();
+        let report = Checker::new(self, true).run();
+        self.journal = journal;
+        let report = report?;
+        self.was_dirty = false;
+        self.sync()?;
+        Ok(report)
+    }
+
+    /* ------------- Journal ------------- */
+    /// Creates a journal of `sectors` sectors, rounded up to whole clusters,
+    /// and starts committing metadata changes through it. The journal is kept
+    /// in a hidden, read-only system file named `JOURNAL.SYS` in the root
+    /// directory, and is used whenever the volume is mounted from then on.
+    ///
+    /// Changes to the FAT, to directory entries and to FSInfo are held in the
+    /// cache until `sync()`, which writes them to the journal before writing
+    /// them in place. If that is interrupted, the next mount either finishes
+    /// the changes or drops all of them, so the metadata is never left half
+    /// updated. File contents are written before the metadata that refers to
+    /// them but are not journaled themselves. Once the changes fill half of
+    /// the journal, they are committed before the next operation, such as
+    /// creating a file or a write to one, starts. Writes that would need more
+    /// room than is left stop short, and freeing or growing a long chain is
+    /// committed in steps that each leave the metadata consistent. Any other
+    /// operation that changes more sectors than the journal holds fails, and
+    /// its metadata changes are dropped.
+    ///
+    /// Other implementations ignore the journal, and a journal left
+    /// uncommitted by an interrupted sync is replayed over whatever they
+    /// changed since.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `AlreadyExists` if the root directory already has
+    /// an entry named `JOURNAL.SYS`, and an error of `InvalidInput` if
+    /// `sectors` is less than 2.
+    pub fn create_journal(&mut self, sectors: u32) -> io::Result<()> {
+        self.check_writable()?;
+        if sectors < 2 {
+            return ioerr!(InvalidInput, "a journal needs at least two sectors");
+        }
+        let search = self.find_root_entry(|entry| {
+            !entry.is_volume_id() && &entry.raw_short_name() == JOURNAL_NAME
+        })?;
+        if search.entry.is_some() {
+            return ioerr!(AlreadyExists, "the volume already has a journal");
+        }
+
+        // everything before the journal exists is written directly
+        self.sync()?;
+        let clusters = sectors.div_ceil(self.sectors_per_cluster as u32);
+        let first = self.alloc_cluster(None)?;
+        self.extend_chain(first, clusters as u64)?;
+
+        let attributes = Attributes::READ_ONLY | Attributes::HIDDEN | Attributes::SYSTEM;
+        let mut entry = VFatRegularDirEntry::new(attributes, first);
+        entry.set_short_name(JOURNAL_NAME);
+        entry.set_size(clusters * self.cluster_size() as u32);
+        if let Some(now) = self.now() {
+            entry.stamp_created(now);
+        }
+        let offset = self.free_root_entry(&search)?;
+        let root = self.rootdir_cluster;
+        entry.write_to(self, root, offset)?;
+        self.sync()?;
+
+        self.journal = Some(Journal::new(first, self.chain_sectors(first)?));
+        Ok(())
+    }
+
+    /// Whether metadata changes are committed through a journal.
+    pub fn has_journal(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Whether the chain starting at `cluster` holds the journal.
+    pub(crate) fn is_journal(&self, cluster: Cluster) -> bool {
+        self.journal
+            .as_ref()
+            .is_some_and(|journal| journal.first_cluster() == cluster)
+    }
+
+    /// The logical sectors of every cluster in the chain starting at `start`.
+    fn chain_sectors(&mut self, start: Cluster) -> io::Result<Vec<u64>> {
+        let mut sectors = Vec::new();
+        let mut current = Some(start);
+        while let Some(cluster) = current {
+            let first = self.cluster_sector(cluster);
+            sectors.extend(first..first + self.sectors_per_cluster as u64);
+            current = self.next_cluster(cluster)?;
+        }
+        Ok(sectors)
+    }
+
+    /// Looks for the journal file in the root directory and, if there is one,
+    /// finishes or drops the transaction left in it.
+    fn load_journal(&mut self) -> io::Result<()> {
+        let search = self.find_root_entry(|entry| {
+            let attributes = entry.metadata().attributes;
+            &entry.raw_short_name() == JOURNAL_NAME
+                && attributes.system()
+                && !attributes.directory()
+                && !attributes.volume_id()
+        })?;
+        let first = match search.entry {
+            Some((_, entry)) => entry.cluster(),
+            None => return Ok(()),
+        };
+        let sectors = self.chain_sectors(first)?;
+        if sectors.len() < 2 {
+            return ioerr!(InvalidData, "the journal is too small");
+        }
+
+        self.journal = Some(Journal::new(first, sectors));
+        self.replay_journal()
+    }
+
+    /// Writes the sectors of a committed transaction in place and clears the
+    /// journal. A transaction whose contents do not match its checksum was
+    /// torn before it committed, so none of its sectors were written in place
+    /// and it is dropped.
+    ///
+    /// On a read-only volume the sectors are pinned in the cache instead, so
+    /// they are seen but never written back, and the journal is left as is.
+    fn replay_journal(&mut self) -> io::Result<()> {
+        let (header, log) = match &self.journal {
+            Some(journal) => (journal.header_sector(), journal.log_sectors().to_vec()),
+            None => return Ok(()),
+        };
+        let sector_size = self.bytes_per_sector as usize;
+
+        let mut buf = vec![0u8; sector_size];
+        self.device.read_sectors(header, 1, &mut buf)?;
+        // a list too long for the header continues in the first log sectors
+        let spilled = Record::spilled_from(&buf).unwrap_or(0).min(log.len());
+        for &sector in &log[..spilled] {
+            let start = buf.len();
+            buf.resize(start + sector_size, 0);
+            self.device.read_sectors(sector, 1, &mut buf[start..])?;
+        }
+        let record = match Record::from_bytes(&buf) {
+            Some(record) => record,
+            // a new journal has a zeroed header
+            None if self.read_only => Record::empty(0),
+            None => {
+                let record = Record::empty(0);
+                self.device
+                    .write_sectors(header, 1, &record.to_bytes(sector_size))?;
+                record
+            }
+        };
+        if let Some(journal) = self.journal.as_mut() {
+            journal.resume_after(record.sequence);
+        }
+        if record.targets.is_empty() {
+            return Ok(());
+        }
+
+        let mut data = Vec::new();
+        if spilled + record.targets.len() <= log.len() {
+            data = vec![0u8; record.targets.len() * sector_size];
+            for (chunk, &sector) in data.chunks_mut(sector_size).zip(&log[spilled..]) {
+                self.device.read_sectors(sector, 1, chunk)?;
+            }
+        }
+        if record.matches(&data) {
+            for (chunk, &target) in data.chunks(sector_size).zip(&record.targets) {
+                match self.read_only {
+                    true => self.device.get_pinned_mut(target)?.copy_from_slice(chunk),
+                    false => {
+                        self.device.write_sectors(target, 1, chunk)?;
+                    }
+                }
+            }
+        }
+        if self.read_only {
+            return Ok(());
+        }
+
+        let empty = Record::empty(record.sequence);
+        self.device
+            .write_sectors(header, 1, &empty.to_bytes(sector_size))?;
+        Ok(())
+    }
+
+    /// Returns the cached sector `sector` for a metadata change. With a
+    /// journal, the sector is pinned until the next commit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` if the sector would be one more than the
+    /// journal holds. The metadata changes of the current operation are
+    /// undone, since committing only part of them would leave the metadata
+    /// half updated.
+    fn metadata_sector_mut(&mut self, sector: u64) -> io::Result<&mut [u8]> {
+        self.begin_write()?;
+        if self.journal.is_none() {
+            return self.device.get_mut(sector);
+        }
+        if self.journal_full(sector) {
+            self.undo_operation()?;
+            return ioerr!(
+                Other,
+                "operation changes more sectors than the journal holds"
+            );
+        }
+        if !self.undo.iter().any(|&(changed, _)| changed == sector) {
+            let before = self.device.get(sector)?.to_vec();
+            self.undo.push((sector, before));
+        }
+        self.device.get_pinned_mut(sector)
+    }
+
+    /// Restores the sectors changed by the current operation to what they
+    /// held before it started.
+    fn undo_operation(&mut self) -> io::Result<()> {
+        for (sector, before) in core::mem::take(&mut self.undo) {
+            self.device.get_pinned_mut(sector)?.copy_from_slice(&before);
+        }
+        // the undone changes may have allocated or freed clusters
+        self.free_clusters = None;
+        Ok(())
+    }
+
+    /// The number of sectors that can still be changed before the journal is
+    /// full.
+    fn journal_room(&self) -> usize {
+        match &self.journal {
+            Some(journal) => journal
+                .capacity(self.bytes_per_sector as usize)
+                .saturating_sub(self.device.pinned_count()),
+            None => usize::MAX,
+        }
+    }
+
+    /// The most sectors that changing one FAT entry touches: FAT12 entries
+    /// may straddle two sectors, and every mirrored FAT is changed too.
+    fn fat_change_sectors(&self) -> usize {
+        let per_fat = match self.fat_type {
+            FatType::Fat12 => 2,
+            FatType::Fat16 | FatType::Fat32 => 1,
+        };
+        per_fat * (1 + self.mirrored_fats().count())
+    }
+
+    /// Commits the changes made so far if the journal has no room to change
+    /// another FAT entry. Only called between steps of an operation that
+    /// each leave the metadata consistent.
+    fn make_room_for_fat_change(&mut self) -> io::Result<()> {
+        if self.journal_room() < self.fat_change_sectors() {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Whether changing `sector` would take more sectors than the journal
+    /// holds. Always `false` without a journal.
+    fn journal_full(&self, sector: u64) -> bool {
+        match &self.journal {
+            Some(journal) => {
+                !self.device.is_pinned(sector)
+                    && self.device.pinned_count()
+                        >= journal.capacity(self.bytes_per_sector as usize)
+            }
+            None => false,
+        }
+    }
+
+    /// Commits the pinned metadata sectors through the journal: file contents
+    /// are written first, then the new sector contents and a header listing
+    /// them, and only then the sectors in place before the header is
+    /// cleared. Only called where the metadata is consistent, such as
+    /// between operations.
+    pub(crate) fn commit(&mut self) -> io::Result<()> {
+        let (header, log, capacity) = match &self.journal {
+            Some(journal) => (
+                journal.header_sector(),
+                journal.log_sectors().to_vec(),
+                journal.capacity(self.bytes_per_sector as usize),
+            ),
+            None => return Ok(()),
+        };
+        let sector_size = self.bytes_per_sector as usize;
+
+        self.undo.clear();
+        self.device.flush()?;
+        let pinned = self.device.pinned_sectors();
+        for targets in pinned.chunks(capacity) {
+            let mut data = Vec::with_capacity(targets.len() * sector_size);
+            for &target in targets {
+                data.extend_from_slice(self.device.get(target)?);
+            }
+            let sequence = self
+                .journal
+                .as_mut()
+                .map_or(0, |journal| journal.next_sequence());
+            let record = Record::new(sequence, targets.to_vec(), &data);
+            let bytes = record.to_bytes(sector_size);
+
+            // the rest of the list and the contents go in before the header
+            let (first, spilled) = bytes.split_at(sector_size);
+            let logged = spilled.chunks(sector_size).chain(data.chunks(sector_size));
+            for (chunk, &sector) in logged.zip(&log) {
+                self.device.write_sectors(sector, 1, chunk)?;
+            }
+            self.device.write_sectors(header, 1, first)?;
+            self.device.write_back_pinned(targets)?;
+            let empty = Record::empty(sequence);
+            self.device
+                .write_sectors(header, 1, &empty.to_bytes(sector_size))?;
+        }
+        Ok(())
+    }
+
+    /* ------------- Recovery ------------- */
+    /// Undeletes the file whose deleted entry is `offset` bytes into `dir`,
+    /// such as one found with `Dir::raw_entries()`. `first` replaces the
+    /// first character of its short name, which was overwritten when the file
+    /// was deleted. The FAT only recorded where the file's data started, so
+    /// the data is taken to be the contiguous run of clusters from there that
+    /// holds the file's recorded size, and that run is linked into a chain
+    /// again. Long file name entries are not restored, so the file keeps only
+    /// its short name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if the entry at `offset` is not a
+    /// deleted file entry or `first` may not start a short name.
+    ///
+    /// Returns an error of `AlreadyExists` if another entry in `dir` has the
+    /// restored short name.
+    ///
+    /// Returns an error of `Other` if the entry records no clusters for a
+    /// file that is not empty, or if any cluster of the run is no longer
+    /// free, meaning that the file's data may have been overwritten. Nothing
+    /// is changed in that case.
+    pub fn recover(&mut self, dir: &Dir<HANDLE>, offset: u64, first: char) -> io::Result<()> {
+        self.begin_operation()?;
+        let dir = dir.first_cluster;
+        if !offset.is_multiple_of(ENTRY_SIZE) {
+            return ioerr!(InvalidInput, "offset is not at the start of an entry");
+        }
+        let first = first.to_ascii_uppercase();
+        if !first.is_ascii() || !name::is_short_name_char(first as u8) {
+            return ioerr!(InvalidInput, "character may not start a short name");
+        }
+
+        let mut raw = [0u8; ENTRY_SIZE as usize];
+        if self.read_chain_at(dir, offset, &mut raw)? < raw.len() {
+            return ioerr!(InvalidInput, "offset is past the end of the directory");
+        }
+        let raw = VFatDirEntry::from_bytes(&raw);
+        let mut entry = raw.regular();
+        if !raw.is_deleted() || raw.is_lfn() || entry.is_volume_id() {
+            return ioerr!(InvalidInput, "entry is not a deleted file");
+        }
+        if entry.metadata().attributes.directory() {
+            return ioerr!(InvalidInput, "only files can be recovered");
+        }
+
+        let mut short = entry.raw_short_name();
+        short[0] = first as u8;
+        if self.short_name_taken(dir, &short)? {
+            return ioerr!(AlreadyExists, "an entry with the restored name exists");
+        }
+
+        // the high half of the cluster number is only used by FAT32, and an
+        // empty file has no clusters at all
+        if entry.size() == 0 {
+            entry.set_cluster(Cluster::from(0));
+        } else if self.fat_type != FatType::Fat32 {
+            entry.set_cluster(Cluster::from(entry.cluster().low() as u32));
+        }
+        let start = entry.cluster().num();
+        if entry.size() != 0 && start == 0 {
+            return ioerr!(Other, "entry records no clusters for the file's data");
+        }
+
+        let len = (entry.size() as u64).div_ceil(self.cluster_size() as u64) as u32;
+        for num in start..start + len {
+            let cluster = Cluster::from(num);
+            self.check_cluster(cluster)
+                .map_err(|_| newioerr!(Other, "file data runs past the end of the volume"))?;
+            if self.fat_entry(cluster)?.status() != Status::Free {
+                return ioerr!(Other, "file data has been reused");
+            }
+        }
+        // until the entry is restored, a run linked in parts is only lost
+        // clusters that a repair frees again
+        for num in start..start + len {
+            let status = match num + 1 {
+                next if next < start + len => Status::Data(Cluster::from(next)),
+                _ => Status::Eoc(0x0FFFFFFF),
+            };
+            self.make_room_for_fat_change()?;
+            self.set_fat_status(Cluster::from(num), status)?;
+        }
+
+        entry.set_short_name(&short);
+        entry.write_to(self, dir, offset)
+    }
+
+    /// Whether a file or directory in the directory starting at `dir` has the
+    /// 8.3 name `short`.
+    fn short_name_taken(&mut self, dir: Cluster, short: &[u8; 11]) -> io::Result<bool> {
+        let mut data = Vec::new();
+        let mut cluster = None;
+        while let Some(next) = self.read_next_cluster(dir, cluster, &mut data)? {
+            cluster = Some(next);
+            for raw in data.as_chunks::<{ ENTRY_SIZE as usize }>().0 {
+                let entry = VFatDirEntry::from_bytes(raw);
+                match entry.id() {
+                    END_OF_ENTRIES => return Ok(false),
+                    DELETED_ENTRY => {}
+                    _ if entry.is_lfn() => {}
+                    _ => {
+                        let regular = entry.regular();
+                        if !regular.is_volume_id() && &regular.raw_short_name() == short {
+                            return Ok(true);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /* ------------- Volume ------------- */
+    /// The offset of the boot sector fields that follow the BIOS parameter
+    /// block shared by every FAT type.
+    fn boot_record_offset(&self) -> usize {
+        match self.fat_type {
+            FatType::Fat32 => 64,
+            FatType::Fat12 | FatType::Fat16 => 36,
+        }
+    }
+
+    /// The volume serial number recorded in the boot sector, or `None` if
+    /// the boot sector does not record one.
+    pub fn serial(&mut self) -> io::Result<Option<u32>> {
+        let offset = self.boot_record_offset();
+        let boot = self.device.get(0)?;
+        match boot[offset + EXTENDED_SIGNATURE] {
+            SERIAL_ONLY | SERIAL_AND_LABEL => {
+                let mut serial = [0u8; 4];
+                serial.copy_from_slice(&boot[offset + VOLUME_ID..offset + VOLUME_ID + 4]);
+                Ok(Some(u32::from_le_bytes(serial)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The volume label, without its padding, or `None` if the volume is
+    /// unlabelled. The label in the root directory is preferred over the one
+    /// in the boot sector, as that is the one other systems keep up to date.
+    pub fn label(&mut self) -> io::Result<Option<String>> {
+        let raw = match self.find_root_entry(|entry| entry.is_volume_id())?.entry {
+            Some((_, entry)) => entry.raw_short_name(),
+            None => {
+                let offset = self.boot_record_offset();
+                let boot = self.device.get(0)?;
+                if boot[offset + EXTENDED_SIGNATURE] != SERIAL_AND_LABEL {
+                    return Ok(None);
+                }
+                let mut raw = [0u8; 11];
+                raw.copy_from_slice(&boot[offset + VOLUME_LABEL..offset + VOLUME_LABEL + 11]);
+                if &raw == NO_LABEL {
+                    return Ok(None);
+                }
+                raw
+            }
+        };
+
+        let len = raw
+            .iter()
+            .rposition(|&b| b != b' ' && b != 0)
+            .map_or(0, |i| i + 1);
+        match len {
+            0 => Ok(None),
+            len => Ok(Some(String::from_utf8_lossy(&raw[..len]).into_owned())),
+        }
+    }
+
+    /// Sets the volume label to `label`, stored in uppercase, or removes it
+    /// if `label` is `None`. Both the root directory entry and the boot
+    /// sector, along with its backup, are updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `label` is not a valid volume
+    /// label and an error of `Other` if a FAT12 or FAT16 root directory has
+    /// no room for the label.
+    pub fn set_label(&mut self, label: Option<&str>) -> io::Result<()> {
+        self.begin_operation()?;
+        let raw = match label {
+            Some(label) => Some(name::volume_label(label)?),
+            None => None,
+        };
+
+        let root = self.rootdir_cluster;
+        let search = self.find_root_entry(|entry| entry.is_volume_id())?;
+        match (raw, search.entry) {
+            (Some(raw), Some((offset, mut entry))) => {
+                entry.set_short_name(&raw);
+                if let Some(now) = self.now() {
+                    entry.stamp_modified(now);
+                }
+                entry.write_to(self, root, offset)?;
+            }
+            (Some(raw), None) => {
+                let offset = self.free_root_entry(&search)?;
+                let mut entry = VFatRegularDirEntry::new(Attributes::VOLUME_ID, Cluster::from(0));
+                entry.set_short_name(&raw);
+                if let Some(now) = self.now() {
+                    entry.stamp_created(now);
+                }
+                entry.write_to(self, root, offset)?;
+            }
+            (None, Some((offset, _))) => {
+                self.write_chain_at(root, offset, &[DELETED_ENTRY])?;
+            }
+            (None, None) => {}
+        }
+
+        let offset = self.boot_record_offset();
+        let sectors = Some(0).into_iter().chain(self.backup_boot_sector);
+        for sector in sectors {
+            if self.device.get(sector)?[offset + EXTENDED_SIGNATURE] != SERIAL_AND_LABEL {
+                continue;
+            }
+            let boot = self.metadata_sector_mut(sector)?;
+            let label = &mut boot[offset + VOLUME_LABEL..offset + VOLUME_LABEL + 11];
+            label.copy_from_slice(raw.as_ref().unwrap_or(NO_LABEL));
+        }
+        Ok(())
+    }
+
+    /// Searches the root directory for the first regular entry for which
+    /// `matches` returns `true`, and for a free entry.
+    fn find_root_entry(
+        &mut self,
+        matches: impl Fn(&VFatRegularDirEntry) -> bool,
+    ) -> io::Result<RootSearch> {
+        let root = self.rootdir_cluster;
+        let mut data = Vec::new();
+        let mut cluster = None;
+        let mut len = 0;
+
+        let mut found = None;
+        let mut free = None;
+        'clusters: while let Some(next) = self.read_next_cluster(root, cluster, &mut data)? {
+            cluster = Some(next);
+            for (i, raw) in data
+                .as_chunks::<{ ENTRY_SIZE as usize }>()
+                .0
+                .iter()
+                .enumerate()
+            {
+                let offset = len + i as u64 * ENTRY_SIZE;
+                let entry = VFatDirEntry::from_bytes(raw);
+                match entry.id() {
+                    END_OF_ENTRIES => {
+                        free = free.or(Some(offset));
+                        break 'clusters;
+                    }
+                    DELETED_ENTRY => free = free.or(Some(offset)),
+                    _ if entry.is_lfn() => {}
+                    _ => {
+                        let regular = entry.regular();
+                        if found.is_none() && matches(&regular) {
+                            found = Some((offset, regular));
+                        }
+                    }
+                }
+            }
+            len += data.len() as u64;
+        }
+
+        Ok(RootSearch {
+            entry: found,
+            free,
+            len,
+        })
+    }
+
+    /// Returns the offset of the free root directory entry found by
+    /// `search`, extending the root directory if there is none.
+    fn free_root_entry(&mut self, search: &RootSearch) -> io::Result<u64> {
+        if let Some(offset) = search.free {
+            return Ok(offset);
+        }
+
+        // new clusters are zeroed, so the entries still end after the new one
+        let end = search.len + ENTRY_SIZE;
+        let root = self.rootdir_cluster;
+        self.extend_chain(root, end.div_ceil(self.cluster_size() as u64))?;
+        Ok(search.len)
+    }
+
+    /* ------------- Time ------------- */
+    /// Sets the clock used to stamp entries when they are created, written
+    /// and read. Without a clock, timestamps are left as they are.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Some(ClockSource(Box::new(clock)));
+    }
+
+    /// The current time according to the clock, if one is set.
+    pub(crate) fn now(&self) -> Option<Timestamp> {
+        self.clock.as_ref().map(|clock| clock.0.now())
+    }
+
+    /* ------------- Cache ------------- */
+    /// Hit, miss, eviction and write-back counts for the sector cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.device.stats()
+    }
+
+    /// Resets the counts returned by `cache_stats()`.
+    pub fn reset_cache_stats(&mut self) {
+        self.device.reset_stats()
+    }
+
+    /// Changes the most sectors cached at once, writing back and evicting
+    /// sectors until the cache fits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn set_cache_capacity(&mut self, capacity: usize) -> io::Result<()> {
+        self.device.set_capacity(capacity)
+    }
 }
 
-impl<'a, HANDLE: VFatHandle> FileSystem for &'a HANDLE {
-    type File = crate::traits::Dummy;
-    type Dir = crate::traits::Dummy;
-    type Entry = crate::traits::Dummy;
+impl<HANDLE: VFatHandle> FileSystem for &HANDLE {
+    type File = File<HANDLE>;
+    type Dir = Dir<HANDLE>;
+    type Entry = Entry<HANDLE>;
 
     fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
-        unimplemented!("FileSystem::open()")
+        let path = path.as_ref();
+        if !path.is_absolute() {
+            return ioerr!(InvalidInput, "path is not absolute");
+        }
+
+        Dir::root(self.clone()).open(path)
+    }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let path = normalize(path);
+        let (parent, name) = split_path(&path)?;
+        self.open_dir(parent)?.create_file(name)
+    }
+
+    fn create_dir<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Dir> {
+        let path = normalize(path);
+        let (parent, name) = split_path(&path)?;
+        self.open_dir(parent)?.create_dir(name)
     }
+
+    fn remove<P: AsRef<Path>>(self, path: P) -> io::Result<()> {
+        let path = normalize(path);
+        let (parent, name) = split_path(&path)?;
+        self.open_dir(parent)?.remove(name)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, from: P, to: Q) -> io::Result<()> {
+        let (from, to) = (normalize(from), normalize(to));
+        let (from_parent, from_name) = split_path(&from)?;
+        let (to_parent, to_name) = split_path(&to)?;
+        let to_dir = self.open_dir(to_parent)?;
+        self.open_dir(from_parent)?
+            .rename(from_name, &to_dir, to_name)
+    }
+
+    fn set_read_only<P: AsRef<Path>>(self, path: P, read_only: bool) -> io::Result<()> {
+        let mut entry = self.open(path)?;
+        let mut attributes = entry.metadata().attributes;
+        attributes.set_read_only(read_only);
+        entry.set_attributes(attributes)
+    }
+}
+
+/// Splits `path` into its parent directory and final component.
+///
+/// # Errors
+///
+/// Returns an error of `InvalidInput` if `path` has no parent or final
+/// component, such as `/`.
+fn split_path(path: &Path) -> io::Result<(&Path, &OsStr)> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| newioerr!(InvalidInput, "path has no file name"))?;
+    let parent = path
+        .parent()
+        .ok_or_else(|| newioerr!(InvalidInput, "path has no parent directory"))?;
+    Ok((parent, name))
 }
@@ -15,7 +15,7 @@ macro_rules! const_assert {
 #[macro_export]
 macro_rules! const_assert_eq {
     ($x:expr, $($xs:expr),+) => {
-        const _: () = { $crate::const_assert!($($x == $xs),+); ()};
+        const _: () = { $crate::const_assert!($($x == $xs),+); };
     }
 }
 
@@ -35,13 +35,13 @@ macro_rules! const_assert_size {
 #[macro_export]
 macro_rules! newioerr {
     ($kind:tt, $msg:tt) => {
-        io::Error::new(io::ErrorKind::$kind, $msg);
+        io::Error::new(io::ErrorKind::$kind, $msg)
     }
 }
 
 #[macro_export]
 macro_rules! ioerr {
     ($kind:tt, $msg:tt) => {
-        Err(io::Error::new(io::ErrorKind::$kind, $msg));
+        Err(io::Error::new(io::ErrorKind::$kind, $msg))
     }
 }
\ No newline at end of file